@@ -0,0 +1,176 @@
+//! Syscall error codes.
+//!
+//! A failing syscall returns the negated [`Errno`] in `rax`, following the
+//! Linux convention: values from `-4095` to `-1` are errors, every other value
+//! is a successful result. User programs decode the value with
+//! [`Errno::from_return_value`].
+
+use core::fmt;
+
+/// Largest error number, so that `-MAX_ERRNO..0` is the error range.
+pub const MAX_ERRNO: u64 = 4095;
+
+/// Error returned by a syscall.
+///
+/// The numbers match the Linux ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Errno {
+    /// Operation not permitted.
+    EPERM = 1,
+    /// No such file or directory.
+    ENOENT = 2,
+    /// No such process.
+    ESRCH = 3,
+    /// Interrupted system call.
+    EINTR = 4,
+    /// Input/output error.
+    EIO = 5,
+    /// Argument list too long.
+    E2BIG = 7,
+    /// Exec format error.
+    ENOEXEC = 8,
+    /// Bad file descriptor.
+    EBADF = 9,
+    /// No child processes.
+    ECHILD = 10,
+    /// Resource temporarily unavailable.
+    EAGAIN = 11,
+    /// Cannot allocate memory.
+    ENOMEM = 12,
+    /// Bad address.
+    EFAULT = 14,
+    /// Invalid argument.
+    EINVAL = 22,
+    /// File name too long.
+    ENAMETOOLONG = 36,
+    /// Function not implemented.
+    ENOSYS = 38,
+}
+
+impl Errno {
+    /// All error codes, in increasing order.
+    pub const ALL: [Self; 15] = [
+        Self::EPERM,
+        Self::ENOENT,
+        Self::ESRCH,
+        Self::EINTR,
+        Self::EIO,
+        Self::E2BIG,
+        Self::ENOEXEC,
+        Self::EBADF,
+        Self::ECHILD,
+        Self::EAGAIN,
+        Self::ENOMEM,
+        Self::EFAULT,
+        Self::EINVAL,
+        Self::ENAMETOOLONG,
+        Self::ENOSYS,
+    ];
+
+    /// Returns the error number.
+    #[must_use]
+    pub const fn number(self) -> u16 {
+        self as u16
+    }
+
+    /// Returns the error with the given number, if it is known.
+    #[must_use]
+    pub fn from_number(number: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|errno| errno.number() == number)
+    }
+
+    /// Returns the value placed in `rax` to report this error: the negated
+    /// error number.
+    #[must_use]
+    pub const fn as_return_value(self) -> u64 {
+        0_u64.wrapping_sub(self as u64)
+    }
+
+    /// Decodes the value of `rax` after a syscall.
+    ///
+    /// Returns `None` if the value is a successful result, or an error number
+    /// not known by the kernel.
+    #[must_use]
+    pub fn from_return_value(value: u64) -> Option<Self> {
+        let number = 0_u64.wrapping_sub(value);
+        if number == 0 || number > MAX_ERRNO {
+            return None;
+        }
+        Self::from_number(u16::try_from(number).ok()?)
+    }
+
+    /// Returns the symbolic name of the error, like `"EFAULT"`.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::EPERM => "EPERM",
+            Self::ENOENT => "ENOENT",
+            Self::ESRCH => "ESRCH",
+            Self::EINTR => "EINTR",
+            Self::EIO => "EIO",
+            Self::E2BIG => "E2BIG",
+            Self::ENOEXEC => "ENOEXEC",
+            Self::EBADF => "EBADF",
+            Self::ECHILD => "ECHILD",
+            Self::EAGAIN => "EAGAIN",
+            Self::ENOMEM => "ENOMEM",
+            Self::EFAULT => "EFAULT",
+            Self::EINVAL => "EINVAL",
+            Self::ENAMETOOLONG => "ENAMETOOLONG",
+            Self::ENOSYS => "ENOSYS",
+        }
+    }
+
+    /// Returns a short description of the error.
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::EPERM => "Operation not permitted",
+            Self::ENOENT => "No such file or directory",
+            Self::ESRCH => "No such process",
+            Self::EINTR => "Interrupted system call",
+            Self::EIO => "Input/output error",
+            Self::E2BIG => "Argument list too long",
+            Self::ENOEXEC => "Exec format error",
+            Self::EBADF => "Bad file descriptor",
+            Self::ECHILD => "No child processes",
+            Self::EAGAIN => "Resource temporarily unavailable",
+            Self::ENOMEM => "Cannot allocate memory",
+            Self::EFAULT => "Bad address",
+            Self::EINVAL => "Invalid argument",
+            Self::ENAMETOOLONG => "File name too long",
+            Self::ENOSYS => "Function not implemented",
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.description())
+    }
+}
+
+/// Decodes the value returned in `rax` by a syscall into a `Result`.
+///
+/// Error numbers unknown to this crate are reported as [`Errno::ENOSYS`].
+///
+/// # Errors
+///
+/// Returns the [`Errno`] if `value` is in the error range.
+pub fn decode_result(value: u64) -> Result<u64, Errno> {
+    let number = value.wrapping_neg();
+    if number == 0 || number > MAX_ERRNO {
+        return Ok(value);
+    }
+    Err(Errno::from_return_value(value).unwrap_or(Errno::ENOSYS))
+}
+
+/// Encodes `result` as the value placed in `rax`.
+#[must_use]
+pub const fn encode_result(result: Result<u64, Errno>) -> u64 {
+    match result {
+        Ok(value) => value,
+        Err(errno) => errno.as_return_value(),
+    }
+}
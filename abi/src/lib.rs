@@ -0,0 +1,91 @@
+//! Syscall ABI of `self_rust_os`.
+//!
+//! This crate is shared by the kernel and the user runtime (`libuser`), so
+//! that both sides agree on the syscall numbers and the error codes.
+//!
+//! A user program invokes a syscall either with the `syscall` instruction or
+//! with `int 0x80` ([`SYSCALL_INTERRUPT_INDEX`]), with the syscall number in
+//! `rax` and its arguments in `rdi`, `rsi` and `rdx`. The result is returned in
+//! `rax`, a negated [`Errno`] on failure.
+
+#![no_std]
+
+pub mod errno;
+
+pub use errno::Errno;
+
+/// Interrupt vector number used for syscalls (`int 0x80`).
+pub const SYSCALL_INTERRUPT_INDEX: u8 = 0x80;
+
+/// Syscall number for `sys_exit`: terminates the current user process.
+pub const SYS_EXIT: u64 = 0;
+
+/// Syscall number for `sys_write`: writes a buffer to the VGA text display.
+pub const SYS_WRITE: u64 = 1;
+
+/// Syscall number for `sys_brk`: moves the program break (the end of the
+/// heap) to the address in `rdi` and returns the new break. `0` only queries
+/// the current break.
+pub const SYS_BRK: u64 = 2;
+
+/// Syscall number for `sys_mmap`: maps `rsi` bytes of zeroed, private,
+/// anonymous memory and returns its address. `rdi` is a page-aligned address
+/// hint, or `0` to let the kernel choose.
+pub const SYS_MMAP: u64 = 3;
+
+/// Syscall number for `sys_munmap`: unmaps the `rsi` bytes of anonymous memory
+/// starting at the page-aligned address `rdi`.
+pub const SYS_MUNMAP: u64 = 4;
+
+/// Syscall number for `sys_fork`: duplicates the calling process. Returns the
+/// PID of the child in the parent, and `0` in the child.
+pub const SYS_FORK: u64 = 5;
+
+/// Syscall number for `sys_exec`: replaces the image of the calling process
+/// with the registered program whose name is the `rsi` bytes at `rdi`. Does
+/// not return on success.
+pub const SYS_EXEC: u64 = 6;
+
+/// Syscall number for `sys_wait`: waits for any child to exit, stores its exit
+/// code at the address in `rdi` (unless it is `0`) and returns its PID.
+pub const SYS_WAIT: u64 = 7;
+
+/// Syscall number for `sys_waitpid`: waits for the child whose PID is in
+/// `rdi` to exit, stores its exit code at the address in `rsi` (unless it is
+/// `0`) and returns its PID.
+pub const SYS_WAITPID: u64 = 8;
+
+/// Syscall number for `sys_yield`: gives the CPU up to another ready process,
+/// if any. Returns `0`.
+pub const SYS_YIELD: u64 = 9;
+
+/// Syscall number for `sys_sleep`: blocks the calling process for at least
+/// the number of milliseconds in `rdi`, rounded up to whole timer ticks.
+/// Returns `0`.
+pub const SYS_SLEEP: u64 = 10;
+
+/// Syscall number for `sys_clock_gettime`: stores the current time of the
+/// clock `rdi` as a [`Timespec`] at the address in `rsi`. Only
+/// [`CLOCK_MONOTONIC`] is supported.
+pub const SYS_CLOCK_GETTIME: u64 = 11;
+
+/// Syscall number for `sys_time`: returns the number of seconds since the
+/// Unix epoch (1970-01-01 00:00:00 UTC), and also stores it at the address in
+/// `rdi` unless it is `0`.
+pub const SYS_TIME: u64 = 12;
+
+/// Clock counting the time since boot, which never goes backwards.
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+/// A point in time, as returned by `sys_clock_gettime`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Timespec {
+    /// Whole seconds.
+    pub tv_sec: u64,
+    /// Nanoseconds past `tv_sec`, below `1_000_000_000`.
+    pub tv_nsec: u64,
+}
+
+/// Maximum length in bytes of a program name passed to `sys_exec`.
+pub const PROGRAM_NAME_MAX: usize = 32;
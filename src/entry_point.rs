@@ -1,7 +1,8 @@
 //! Main for little self made rust OS.
 //!
 //! This is the kernel entry point. It initializes all subsystems (GDT, IDT, PICs,
-//! paging, heap) and then loads and executes the embedded user-mode binary.
+//! timer, paging, heap, clock) and then loads and executes the embedded
+//! user-mode binary.
 
 #![no_std]
 #![no_main]
@@ -15,28 +16,17 @@ extern crate alloc;
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use self_rust_os::{
-    allocator,
-    memory::{self, BootInfoFrameAllocator},
-    println, serial_println,
-    task::{executor::Executor, keyboard, Task},
-    userspace,
+    allocator, memory, println, serial_println,
+    task::{deferred, executor::Executor, keyboard, Task},
+    time, userspace,
 };
 use x86_64::VirtAddr;
 
-/// The embedded flat binary of the user-mode hello program.
+/// The embedded ELF executable of the user-mode hello program.
 ///
-/// This binary is built from `user_programs/hello/` and converted to a flat
-/// binary with `llvm-objcopy -O binary`. See `user_programs/hello/build.sh`
-/// for build instructions.
-static USER_HELLO_BIN: &[u8] = include_bytes!("../user_programs/hello/hello.bin");
-
-/// Auto-generated metadata about the hello binary layout (section boundaries).
-///
-/// Provides `READONLY_SIZE`: the page-aligned byte count of the read-only
-/// region (`.text` + `.rodata`) so the kernel can enforce W^X permissions.
-mod hello_meta {
-    include!("../user_programs/hello/hello_meta.rs");
-}
+/// This executable is built from `user_programs/hello/`. See
+/// `user_programs/hello/build.sh` for build instructions.
+static USER_HELLO_ELF: &[u8] = include_bytes!("../user_programs/hello/hello.elf");
 
 entry_point!(kernel_main);
 
@@ -56,12 +46,34 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
 
     // SAFETY:
-    // The memory map is valid as guaranteed by the bootloader.
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::new(&boot_info.memory_map) };
+    // The memory map is valid as guaranteed by the bootloader, and the
+    // physical memory mapping has just been set up.
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map);
+    }
 
     #[expect(clippy::expect_used)]
-    allocator::init_heap(&mut mapper, &mut frame_allocator)
-        .expect("Heap initialization failed. Reboot required.");
+    memory::with_frame_allocator(|frame_allocator| {
+        allocator::init_heap(&mut mapper, frame_allocator)
+    })
+    .expect("Heap initialization failed. Reboot required.");
+
+    // Let interrupt handlers defer work from now on. It runs once the async
+    // executor starts.
+    deferred::init();
+
+    // Read the monotonic clock from the HPET when the machine has one, and
+    // from the PIT ticks otherwise.
+    let clock_source = if time::init_hpet() { "HPET" } else { "PIT" };
+    serial_println!(
+        "[kernel] clock source: {}, timer at {} Hz, uptime {}",
+        clock_source,
+        time::frequency(),
+        time::now(),
+    );
+    let boot_time = time::rtc::DateTime::from_unix_timestamp(time::boot_time().as_secs());
+    serial_println!("[kernel] boot time: {}", boot_time);
+    println!("Boot time: {}", boot_time);
 
     // In test mode, run the test harness and exit before entering user space
     // or the async executor (both of which never return).
@@ -71,22 +83,37 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     serial_println!("[kernel] Heap initialized, starting user space demo...");
     println!("--- User Space Demo ---");
 
-    // Load and execute the embedded user binary.
-    // The CPU switches to Ring 3 and the user program runs until it calls
-    // `sys_exit`, at which point the syscall handler restores the kernel
-    // context and process::run returns here.
-    #[expect(clippy::expect_used)]
-    userspace::process::run(
-        USER_HELLO_BIN,
-        hello_meta::READONLY_SIZE,
-        &mut mapper,
-        &mut frame_allocator,
-    )
-    .expect("Failed to launch user process. Reboot required.");
+    // Make the embedded program available to `sys_exec`.
+    userspace::programs::register("hello", USER_HELLO_ELF);
+
+    // Load two instances of the embedded user binary as separate processes.
+    // Each process gets its own PID, register context and page table, so both
+    // can live at the same time even though they are linked at the same
+    // virtual addresses.
+    let pids = [(); 2].map(|()| {
+        #[expect(clippy::expect_used)]
+        userspace::process::spawn(USER_HELLO_ELF)
+            .expect("Failed to load user process. Reboot required.")
+    });
+
+    // Execute the processes one after the other. The CPU switches to Ring 3
+    // and each program runs until it calls `sys_exit`, at which point the
+    // syscall handler restores the kernel context and the next process is
+    // scheduled. The memory of a process is released as soon as it exits.
+    // process::run returns here once every process has exited.
+    userspace::process::run();
+
+    // Report how each process terminated and remove it from the process table.
+    for pid in pids {
+        if let Some(status) = userspace::process::reap(pid) {
+            println!("[kernel] process {} {}", pid, status);
+        }
+    }
 
     println!("--- Returning to kernel async executor ---");
 
     let mut executor = Executor::new();
+    executor.spawn(deferred::worker());
     executor.spawn(Task::new(keyboard::print_keypresses()));
     executor.run();
 }
@@ -1,6 +1,11 @@
 //! GDT and TSS initialization.
 //! This module sets up the Global Descriptor Table (GDT) and the Task State Segment (TSS).
 //! It includes both kernel and user mode segment descriptors to support Ring 3 execution.
+//!
+//! The descriptors are laid out as required by the `SYSCALL`/`SYSRET`
+//! instructions: kernel code, kernel data, user data, user code. `SYSCALL`
+//! loads the kernel code selector and the selector right after it, while
+//! `SYSRET` loads the user data selector and the one right after it.
 
 use lazy_static::lazy_static;
 use x86_64::{
@@ -10,12 +15,24 @@ use x86_64::{
         gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
         tss::TaskStateSegment,
     },
-    VirtAddr,
+    PrivilegeLevel, VirtAddr,
 };
 
 /// The index of the IST entry for the double fault handler.
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
+/// Kernel code segment selector.
+pub const KERNEL_CODE_SELECTOR: SegmentSelector = SegmentSelector::new(1, PrivilegeLevel::Ring0);
+
+/// Kernel data segment selector, loaded in `SS` by `SYSCALL`.
+pub const KERNEL_DATA_SELECTOR: SegmentSelector = SegmentSelector::new(2, PrivilegeLevel::Ring0);
+
+/// User data segment selector with Ring 3 privilege level.
+pub const USER_DATA_SELECTOR: SegmentSelector = SegmentSelector::new(3, PrivilegeLevel::Ring3);
+
+/// User code segment selector with Ring 3 privilege level.
+pub const USER_CODE_SELECTOR: SegmentSelector = SegmentSelector::new(4, PrivilegeLevel::Ring3);
+
 /// Size of the kernel stack used when transitioning from Ring 3 to Ring 0.
 const KERNEL_STACK_SIZE: usize = 4096 * 5;
 
@@ -29,21 +46,28 @@ pub fn init() {
     // SAFETY:
     // The GDT is loaded and the CS register is set to the code selector.
     unsafe {
-        CS::set_reg(GDT.1.code_selector);
+        CS::set_reg(KERNEL_CODE_SELECTOR);
         load_tss(GDT.1.tss_selector);
     }
 }
 
 /// Returns the user code segment selector with Ring 3 privilege level.
 #[must_use]
-pub fn user_code_selector() -> SegmentSelector {
-    GDT.1.user_code_selector
+pub const fn user_code_selector() -> SegmentSelector {
+    USER_CODE_SELECTOR
 }
 
 /// Returns the user data segment selector with Ring 3 privilege level.
 #[must_use]
-pub fn user_data_selector() -> SegmentSelector {
-    GDT.1.user_data_selector
+pub const fn user_data_selector() -> SegmentSelector {
+    USER_DATA_SELECTOR
+}
+
+/// Returns the top of the kernel stack used when the CPU transitions from
+/// Ring 3 to Ring 0 (`RSP0` in the TSS).
+#[must_use]
+pub fn kernel_stack_top() -> VirtAddr {
+    TSS.privilege_stack_table[0]
 }
 
 lazy_static! {
@@ -75,36 +99,33 @@ lazy_static! {
     };
 }
 
-/// Holds the GDT segment selectors used during initialization and user mode transitions.
+/// Holds the GDT segment selectors used during initialization.
 struct Selectors {
-    code_selector: SegmentSelector,
     tss_selector: SegmentSelector,
-    user_code_selector: SegmentSelector,
-    user_data_selector: SegmentSelector,
 }
 
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
-        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+
+        // The order of these segments is required by SYSCALL/SYSRET. The
+        // selectors returned by `add_entry` carry the descriptor privilege
+        // level, so the user selectors already have RPL=3.
+        let segments = [
+            (Descriptor::kernel_code_segment(), KERNEL_CODE_SELECTOR),
+            (Descriptor::kernel_data_segment(), KERNEL_DATA_SELECTOR),
+            (Descriptor::user_data_segment(), USER_DATA_SELECTOR),
+            (Descriptor::user_code_segment(), USER_CODE_SELECTOR),
+        ];
+        for (descriptor, selector) in segments {
+            assert_eq!(
+                gdt.add_entry(descriptor),
+                selector,
+                "The GDT layout must match the SYSCALL/SYSRET requirements."
+            );
+        }
         let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
 
-        // Add user mode segments. The selectors returned by `add_entry` have RPL=0,
-        // so we create new selectors with RPL=3 for Ring 3 execution.
-        let user_data_entry = gdt.add_entry(Descriptor::user_data_segment());
-        let user_code_entry = gdt.add_entry(Descriptor::user_code_segment());
-
-        let user_data_selector = SegmentSelector(user_data_entry.0 | 3);
-        let user_code_selector = SegmentSelector(user_code_entry.0 | 3);
-
-        (
-            gdt,
-            Selectors {
-                code_selector,
-                tss_selector,
-                user_code_selector,
-                user_data_selector,
-            },
-        )
+        (gdt, Selectors { tss_selector })
     };
 }
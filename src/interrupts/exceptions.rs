@@ -0,0 +1,538 @@
+//! CPU exception handlers.
+//!
+//! Every fault that a user program can trigger (divide error, invalid opcode,
+//! segment faults, general protection, page fault, alignment check and
+//! floating-point errors) goes through a naked entry point that saves the
+//! interrupted registers as a [`Context`]. The handler prints a report to the
+//! serial port and the VGA buffer, then:
+//! - if the exception happened in Ring 3, terminates the current process with
+//!   the exit code of the [`Exception`] and returns to the kernel scheduler;
+//!   a page fault on a page that the process has not touched yet is resolved
+//!   by demand paging instead, without any report;
+//! - if it happened in the kernel, panics.
+//!
+//! A machine check reports a hardware error and is always fatal.
+
+use core::{arch::naked_asm, fmt};
+
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+    VirtAddr,
+};
+
+use crate::{
+    pop_context, println, push_context, push_context_with_error_code, serial_println,
+    userspace::{
+        context::Context,
+        process::{self, ExitStatus},
+        vma::{Access, PageFaultError},
+    },
+};
+
+/// Exit code base for processes terminated by an exception, following the
+/// Unix shell convention of `128 + signal number`.
+pub const SIGNAL_EXIT_BASE: u64 = 128;
+
+/// A CPU exception handled by this module, identified by its vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Exception {
+    /// `#DE`: division by zero or quotient overflow.
+    DivideError = 0,
+    /// `#UD`: undefined or reserved instruction.
+    InvalidOpcode = 6,
+    /// `#NP`: a segment register was loaded with a not-present segment.
+    SegmentNotPresent = 11,
+    /// `#SS`: stack segment limit violation or not-present stack segment.
+    StackSegmentFault = 12,
+    /// `#GP`: any other protection violation, e.g. a privileged instruction in Ring 3.
+    GeneralProtection = 13,
+    /// `#PF`: access to an unmapped page or violation of the page permissions.
+    PageFault = 14,
+    /// `#MF`: unmasked x87 floating-point error.
+    X87FloatingPoint = 16,
+    /// `#AC`: unaligned access with alignment checking enabled.
+    AlignmentCheck = 17,
+    /// `#MC`: hardware error detected by the processor.
+    MachineCheck = 18,
+    /// `#XM`: unmasked SSE floating-point error.
+    SimdFloatingPoint = 19,
+}
+
+impl Exception {
+    /// Every exception handled by this module.
+    pub const ALL: [Self; 10] = [
+        Self::DivideError,
+        Self::InvalidOpcode,
+        Self::SegmentNotPresent,
+        Self::StackSegmentFault,
+        Self::GeneralProtection,
+        Self::PageFault,
+        Self::X87FloatingPoint,
+        Self::AlignmentCheck,
+        Self::MachineCheck,
+        Self::SimdFloatingPoint,
+    ];
+
+    /// Returns the exception with interrupt vector `vector`, if it is handled
+    /// by this module.
+    #[must_use]
+    pub fn from_vector(vector: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|exception| exception.vector() == vector)
+    }
+
+    /// Returns the interrupt vector of the exception.
+    #[must_use]
+    pub const fn vector(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the name of the exception.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::DivideError => "DIVIDE ERROR",
+            Self::InvalidOpcode => "INVALID OPCODE",
+            Self::SegmentNotPresent => "SEGMENT NOT PRESENT",
+            Self::StackSegmentFault => "STACK-SEGMENT FAULT",
+            Self::GeneralProtection => "GENERAL PROTECTION FAULT",
+            Self::PageFault => "PAGE FAULT",
+            Self::X87FloatingPoint => "X87 FLOATING-POINT ERROR",
+            Self::AlignmentCheck => "ALIGNMENT CHECK",
+            Self::MachineCheck => "MACHINE CHECK",
+            Self::SimdFloatingPoint => "SIMD FLOATING-POINT ERROR",
+        }
+    }
+
+    /// Returns the mnemonic of the exception, e.g. `#GP`.
+    #[must_use]
+    pub const fn mnemonic(self) -> &'static str {
+        match self {
+            Self::DivideError => "#DE",
+            Self::InvalidOpcode => "#UD",
+            Self::SegmentNotPresent => "#NP",
+            Self::StackSegmentFault => "#SS",
+            Self::GeneralProtection => "#GP",
+            Self::PageFault => "#PF",
+            Self::X87FloatingPoint => "#MF",
+            Self::AlignmentCheck => "#AC",
+            Self::MachineCheck => "#MC",
+            Self::SimdFloatingPoint => "#XM",
+        }
+    }
+
+    /// Returns `true` if the CPU pushes an error code for this exception.
+    #[must_use]
+    pub const fn has_error_code(self) -> bool {
+        matches!(
+            self,
+            Self::SegmentNotPresent
+                | Self::StackSegmentFault
+                | Self::GeneralProtection
+                | Self::PageFault
+                | Self::AlignmentCheck
+        )
+    }
+
+    /// Returns the exit code of a process terminated by this exception.
+    ///
+    /// The code is `128` plus the number of the signal a Unix kernel would
+    /// deliver: `SIGILL` (4) for `#UD`, `SIGFPE` (8) for arithmetic errors,
+    /// `SIGBUS` (7) for segment and alignment faults and `SIGSEGV` (11) for
+    /// protection and page faults.
+    #[must_use]
+    pub const fn exit_code(self) -> u64 {
+        let signal = match self {
+            Self::InvalidOpcode => 4,
+            Self::SegmentNotPresent
+            | Self::StackSegmentFault
+            | Self::AlignmentCheck
+            | Self::MachineCheck => 7,
+            Self::DivideError | Self::X87FloatingPoint | Self::SimdFloatingPoint => 8,
+            Self::GeneralProtection | Self::PageFault => 11,
+        };
+        SIGNAL_EXIT_BASE + signal
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.mnemonic())
+    }
+}
+
+/// Generates the naked entry point of an exception.
+///
+/// The entry point builds a [`Context`] from the interrupted registers and
+/// calls [`exception_handler`] (or `$handler`) with the context, the error
+/// code (`0` if the CPU pushes none) and the vector of the exception. If the
+/// handler terminated the current process, control returns to the kernel
+/// scheduler; otherwise the faulting instruction is retried with `iretq`.
+macro_rules! exception_entry {
+    ($name:ident, $exception:expr, error_code) => {
+        exception_entry!(
+            $name,
+            $exception,
+            exception_handler,
+            push_context_with_error_code!(),
+            "mov rsi, rax"
+        );
+    };
+    ($name:ident, $exception:expr, error_code, $handler:ident) => {
+        exception_entry!(
+            $name,
+            $exception,
+            $handler,
+            push_context_with_error_code!(),
+            "mov rsi, rax"
+        );
+    };
+    ($name:ident, $exception:expr) => {
+        exception_entry!(
+            $name,
+            $exception,
+            exception_handler,
+            push_context!(),
+            "xor esi, esi"
+        );
+    };
+    ($name:ident, $exception:expr, $handler:ident, $push:expr, $error_code:literal) => {
+        #[doc = concat!("Naked entry point of the `", stringify!($exception), "` exception.")]
+        #[naked]
+        extern "x86-interrupt" fn $name(_frame: InterruptStackFrame) {
+            // SAFETY:
+            //
+            // This naked function manually manages the entire register
+            // save/restore and stack layout. The CPU has already pushed SS,
+            // RSP, RFLAGS, CS, RIP (and the error code, if any) before
+            // entering this handler.
+            unsafe {
+                naked_asm!(
+                    $push,
+
+                    // rdi = pointer to the saved context, rsi = error code,
+                    // dl = exception vector.
+                    "mov rdi, rsp",
+                    $error_code,
+                    "mov edx, {vector}",
+                    "call {handler}",
+
+                    // The handler returns `true` if the process has been
+                    // terminated.
+                    "test al, al",
+                    "jnz {return_to_kernel}",
+
+                    pop_context!(),
+                    "iretq",
+
+                    vector = const $exception as u8,
+                    handler = sym $handler,
+                    return_to_kernel = sym process::return_to_kernel,
+                );
+            }
+        }
+    };
+}
+
+exception_entry!(divide_error_entry, Exception::DivideError);
+exception_entry!(invalid_opcode_entry, Exception::InvalidOpcode);
+exception_entry!(
+    segment_not_present_entry,
+    Exception::SegmentNotPresent,
+    error_code
+);
+exception_entry!(
+    stack_segment_fault_entry,
+    Exception::StackSegmentFault,
+    error_code
+);
+exception_entry!(
+    general_protection_entry,
+    Exception::GeneralProtection,
+    error_code
+);
+exception_entry!(
+    page_fault_entry,
+    Exception::PageFault,
+    error_code,
+    page_fault_handler
+);
+exception_entry!(x87_floating_point_entry, Exception::X87FloatingPoint);
+exception_entry!(alignment_check_entry, Exception::AlignmentCheck, error_code);
+exception_entry!(machine_check_entry, Exception::MachineCheck);
+exception_entry!(simd_floating_point_entry, Exception::SimdFloatingPoint);
+
+/// Registers the exception entry points in `idt`.
+pub(super) fn register_exception_handlers(idt: &mut InterruptDescriptorTable) {
+    let address = |entry: extern "x86-interrupt" fn(InterruptStackFrame)| {
+        VirtAddr::new(entry as *const () as u64)
+    };
+
+    // SAFETY:
+    //
+    // Every entry point is generated by `exception_entry!`: it pops the error
+    // code if the CPU pushes one, saves and restores every register and ends
+    // with `iretq` (or leaves through `return_to_kernel`).
+    unsafe {
+        idt.divide_error
+            .set_handler_addr(address(divide_error_entry));
+        idt.invalid_opcode
+            .set_handler_addr(address(invalid_opcode_entry));
+        idt.segment_not_present
+            .set_handler_addr(address(segment_not_present_entry));
+        idt.stack_segment_fault
+            .set_handler_addr(address(stack_segment_fault_entry));
+        idt.general_protection_fault
+            .set_handler_addr(address(general_protection_entry));
+        idt.page_fault.set_handler_addr(address(page_fault_entry));
+        idt.x87_floating_point
+            .set_handler_addr(address(x87_floating_point_entry));
+        idt.alignment_check
+            .set_handler_addr(address(alignment_check_entry));
+        idt.machine_check
+            .set_handler_addr(address(machine_check_entry));
+        idt.simd_floating_point
+            .set_handler_addr(address(simd_floating_point_entry));
+    }
+}
+
+/// Reports an exception and terminates the faulting user process.
+///
+/// Returns `true` if the process has been terminated and the kernel scheduler
+/// must run instead of resuming the faulting code.
+extern "C" fn exception_handler(context: &Context, error_code: u64, vector: u8) -> bool {
+    let Some(exception) = Exception::from_vector(vector) else {
+        panic!("EXCEPTION: unexpected vector {vector}");
+    };
+
+    report(format_args!(
+        "EXCEPTION: {exception}\n  error code: {}\n{}",
+        SelectorErrorCode(error_code),
+        ContextReport(context),
+    ));
+
+    assert!(
+        exception != Exception::MachineCheck,
+        "EXCEPTION: {exception}: hardware error"
+    );
+    terminate_current_process(context, exception, context.rip)
+}
+
+/// Resolves a page fault by demand paging or copy-on-write, or reports it and
+/// terminates the faulting user process.
+///
+/// A fault in Ring 3 on a page of the process that has not been touched yet
+/// is resolved by mapping the page, and a write to a page shared with another
+/// process since a fork by copying it; the faulting instruction is then
+/// restarted. Any other fault is fatal to the process.
+///
+/// Returns `true` if the process has been terminated and the kernel scheduler
+/// must run instead of resuming the faulting code.
+extern "C" fn page_fault_handler(context: &Context, error_code: u64, _vector: u8) -> bool {
+    let address = Cr2::read();
+    let cause = PageFaultCause(PageFaultErrorCode::from_bits_truncate(error_code));
+
+    let resolution = if !context.is_user() {
+        None
+    } else if cause.is_protection_violation() && cause.access() != Access::Write {
+        Some(Err(PageFaultError::AccessViolation))
+    } else {
+        Some(process::handle_page_fault(address.as_u64(), cause.access()))
+    };
+    if resolution == Some(Ok(())) {
+        return false;
+    }
+
+    report(format_args!(
+        "EXCEPTION: {}\n  address: {:#x}\n  cause: {cause}\n{}",
+        Exception::PageFault,
+        address.as_u64(),
+        ContextReport(context),
+    ));
+    if let Some(Err(error)) = resolution {
+        report(format_args!("  reason: {error}"));
+    }
+
+    terminate_current_process(context, Exception::PageFault, address.as_u64())
+}
+
+/// Prints an exception report to the serial port and the VGA buffer.
+fn report(message: fmt::Arguments<'_>) {
+    serial_println!("{}", message);
+    println!("{}", message);
+}
+
+/// Terminates the process running in Ring 3 with an [`ExitStatus::Faulted`]
+/// status recording `exception` and the faulting `address`.
+///
+/// Always returns `true`; panics if the exception did not happen in Ring 3.
+fn terminate_current_process(context: &Context, exception: Exception, address: u64) -> bool {
+    assert!(
+        context.is_user(),
+        "EXCEPTION: {exception} in kernel mode at {:#x}",
+        context.rip
+    );
+
+    let Some(pid) = process::exit_current(ExitStatus::Faulted { exception, address }) else {
+        panic!("EXCEPTION: {exception} in user mode without a current process");
+    };
+    serial_println!("[kernel] process {} terminated by {}", pid, exception);
+    println!("[kernel] process {} terminated by {}", pid, exception);
+
+    true
+}
+
+/// The interrupted registers relevant to an exception report.
+struct ContextReport<'a>(&'a Context);
+
+impl fmt::Display for ContextReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let context = self.0;
+        let mode = if context.is_user() { "user" } else { "kernel" };
+        writeln!(
+            f,
+            "  rip: {:#x}  cs: {:#x} ({mode} mode)  rflags: {:#x}",
+            context.rip, context.cs, context.rflags
+        )?;
+        write!(f, "  rsp: {:#x}  ss: {:#x}", context.rsp, context.ss)
+    }
+}
+
+/// Human-readable description of a segment selector error code, pushed by
+/// `#NP`, `#SS`, `#GP` and `#AC`.
+struct SelectorErrorCode(u64);
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = self.0;
+        if code == 0 {
+            return write!(f, "0 (no selector)");
+        }
+
+        let table = match code & 0b110 {
+            0b000 => "GDT",
+            0b100 => "LDT",
+            _ => "IDT",
+        };
+        write!(f, "{code:#x} ({table} index {}", (code >> 3) & 0x1fff)?;
+        if code & 1 != 0 {
+            write!(f, ", external event")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Human-readable description of a page fault error code.
+struct PageFaultCause(PageFaultErrorCode);
+
+impl PageFaultCause {
+    /// Returns `true` if the page was present but does not allow the access.
+    const fn is_protection_violation(&self) -> bool {
+        self.0.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+    }
+
+    /// Returns the kind of access that caused the fault.
+    const fn access(&self) -> Access {
+        if self.0.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            Access::Execute
+        } else if self.0.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            Access::Write
+        } else {
+            Access::Read
+        }
+    }
+}
+
+impl fmt::Display for PageFaultCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = self.0;
+        let presence = if code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+            "protection violation"
+        } else {
+            "page not present"
+        };
+        let access = if code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            "instruction fetch"
+        } else if code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+            "write"
+        } else {
+            "read"
+        };
+        let mode = if code.contains(PageFaultErrorCode::USER_MODE) {
+            "user mode"
+        } else {
+            "kernel mode"
+        };
+
+        write!(f, "{presence}, {access}, {mode}")?;
+        if code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+            write!(f, ", reserved bit set")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    #[test_case]
+    fn test_exceptions_are_identified_by_their_vector() {
+        for exception in Exception::ALL {
+            assert_eq!(
+                Exception::from_vector(exception.vector()),
+                Some(exception),
+                "The vector of an exception must map back to it.",
+            );
+        }
+        assert_eq!(
+            Exception::from_vector(3),
+            None,
+            "Breakpoints are not handled by this module.",
+        );
+    }
+
+    #[test_case]
+    fn test_exit_codes_follow_the_signal_convention() {
+        assert_eq!(Exception::InvalidOpcode.exit_code(), 132, "SIGILL.");
+        assert_eq!(Exception::DivideError.exit_code(), 136, "SIGFPE.");
+        assert_eq!(Exception::GeneralProtection.exit_code(), 139, "SIGSEGV.");
+        assert_eq!(Exception::PageFault.exit_code(), 139, "SIGSEGV.");
+    }
+
+    #[test_case]
+    fn test_selector_error_code_is_decoded() {
+        assert_eq!(
+            format!("{}", SelectorErrorCode(0)),
+            "0 (no selector)",
+            "Most #GP faults do not involve a selector.",
+        );
+        assert_eq!(
+            format!("{}", SelectorErrorCode((5 << 3) | 0b010 | 1)),
+            "0x2b (IDT index 5, external event)",
+            "An IDT selector raised by an external event.",
+        );
+    }
+
+    #[test_case]
+    fn test_page_fault_cause_is_decoded() {
+        let user_write = PageFaultErrorCode::PROTECTION_VIOLATION
+            | PageFaultErrorCode::CAUSED_BY_WRITE
+            | PageFaultErrorCode::USER_MODE;
+        assert_eq!(
+            format!("{}", PageFaultCause(user_write)),
+            "protection violation, write, user mode",
+            "A user write to a read-only page.",
+        );
+        assert_eq!(
+            format!("{}", PageFaultCause(PageFaultErrorCode::INSTRUCTION_FETCH)),
+            "page not present, instruction fetch, kernel mode",
+            "A kernel instruction fetch from an unmapped page.",
+        );
+    }
+}
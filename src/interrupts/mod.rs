@@ -0,0 +1,264 @@
+//! Interrupt handling module.
+//! This module provides the implementation of the Interrupt Descriptor Table (IDT)
+//! and the handlers for the interrupts, including the syscall handler for user mode.
+//!
+//! CPU exceptions are handled in [`exceptions`].
+//!
+//! Hardware interrupt handlers must not print: the interrupted code may hold
+//! the VGA writer lock. They hand the rest of their work over to
+//! [`task::deferred`] instead. Clippy enforces this rule on the handlers,
+//! which deny the `disallowed_methods` lint: `clippy.toml` disallows the
+//! function behind `print!` and `println!`. At runtime, nothing is printed
+//! while [`in_hardware_interrupt`] returns `true`.
+
+use core::{
+    arch::naked_asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lazy_static::lazy_static;
+use pc_keyboard::{layouts, HandleControl, ScancodeSet1};
+use pic8259::ChainedPics;
+use spin::Mutex;
+use x86_64::{
+    instructions::port::Port,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
+    VirtAddr,
+};
+
+use crate::{
+    gdt, pop_context, print, println, push_context,
+    task::{self, deferred, keyboard},
+    time,
+    userspace::{self, context::Context, process, scheduler},
+};
+
+pub mod exceptions;
+
+/// The offset for the Programmable Interrupt Controller (PIC) 1 (starting after interrupt table
+/// max offset).
+pub const PIC_1_OFFSET: u8 = 32;
+
+/// The offset for the Programmable Interrupt Controller (PIC) 2.
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+
+/// The Programmable Interrupt Controller (PIC) used for handling hardware interrupts.
+pub static PICS: spin::Mutex<ChainedPics> =
+    spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+/// Enum for the interrupt indices.
+pub enum InterruptIndex {
+    /// Timer interrupt index.
+    Timer = PIC_1_OFFSET,
+    /// Keyboard interrupt index.
+    Keyboard,
+}
+
+impl InterruptIndex {
+    /// Returns the index as a `u8`.
+    #[must_use]
+    pub const fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the index as a `usize`.
+    #[must_use]
+    pub fn as_usize(self) -> usize {
+        usize::from(self.as_u8())
+    }
+}
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.breakpoint.set_handler_fn(breakpoint_handler);
+
+        // SAFETY:
+        // The double fault handler is safe to set as the index is valid.
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(double_fault_handler)
+                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+
+        // Faults that user programs can trigger terminate the faulting
+        // process instead of bringing down the whole kernel.
+        exceptions::register_exception_handlers(&mut idt);
+
+        // The timer handler is a naked function that saves the full register
+        // context so that it can preempt the running user process.
+        //
+        // SAFETY:
+        // `timer_interrupt_entry` saves and restores every register and ends
+        // with `iretq` (or leaves through `return_to_kernel`).
+        unsafe {
+            idt[InterruptIndex::Timer.as_usize()]
+                .set_handler_addr(VirtAddr::new(timer_interrupt_entry as *const () as u64));
+        }
+        idt[InterruptIndex::Keyboard.as_usize()]
+            .set_handler_fn(keyboard_interrupt_handler);
+
+        // Register the syscall handler at interrupt vector 0x80.
+        // DPL is set to Ring 3 so user-mode code can invoke it via `int 0x80`.
+        userspace::syscall::register_syscall_handler(&mut idt);
+
+        idt
+    };
+}
+
+/// Number of hardware interrupt handlers running, more than one if an
+/// exception is raised while one runs.
+static HARDWARE_INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns `true` while a hardware interrupt handler runs.
+#[must_use]
+pub fn in_hardware_interrupt() -> bool {
+    HARDWARE_INTERRUPTS.load(Ordering::Relaxed) > 0
+}
+
+/// Marks the current code as a hardware interrupt handler until dropped.
+struct HardwareInterrupt;
+
+impl HardwareInterrupt {
+    fn enter() -> Self {
+        HARDWARE_INTERRUPTS.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for HardwareInterrupt {
+    fn drop(&mut self) {
+        HARDWARE_INTERRUPTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Initialize the Interrupt Descriptor Table (IDT).
+pub fn init_idt() {
+    IDT.load();
+}
+
+extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+}
+
+extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    println!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+}
+
+/// Naked entry point for the timer interrupt.
+///
+/// Saves all general-purpose registers with the same layout as the syscall
+/// entry point (a [`Context`]), then calls [`timer_interrupt_handler`]. If
+/// the handler preempted the running user process, its context has been
+/// saved and control returns to the kernel scheduler; otherwise the
+/// interrupted code is resumed with `iretq`.
+#[naked]
+extern "x86-interrupt" fn timer_interrupt_entry(_frame: InterruptStackFrame) {
+    // SAFETY:
+    //
+    // This naked function manually manages the entire register save/restore
+    // and stack layout. The CPU has already pushed SS, RSP, RFLAGS, CS, RIP
+    // before entering this handler.
+    unsafe {
+        naked_asm!(
+            push_context!(),
+
+            // rdi = pointer to the saved context.
+            "mov rdi, rsp",
+            "call {handler}",
+
+            // The handler returns `true` if the process has been preempted.
+            "test al, al",
+            "jnz {return_to_kernel}",
+
+            pop_context!(),
+            "iretq",
+
+            handler = sym timer_interrupt_handler,
+            return_to_kernel = sym process::return_to_kernel,
+        );
+    }
+}
+
+/// Handles a timer tick and decides whether the interrupted process must be
+/// preempted.
+///
+/// Returns `true` if the context has been saved in the process table and the
+/// kernel scheduler must run instead of resuming the interrupted code.
+#[deny(clippy::disallowed_methods)]
+extern "C" fn timer_interrupt_handler(context: &Context) -> bool {
+    let _interrupt = HardwareInterrupt::enter();
+    time::tick();
+    task::timer::wake_expired(time::now());
+
+    // Print a dot every second to show that the timer interrupt is running.
+    #[cfg(debug_assertions)]
+    if time::ticks() % u64::from(time::frequency()) == 0 {
+        let _dropped = deferred::defer(print_heartbeat, 0);
+    }
+
+    // Notify the PICs that the interrupt has been handled. This must happen
+    // before the scheduler possibly leaves this handler for good.
+    //
+    // SAFETY:
+    // We are notifying the PIC that the interrupt has been handled.
+    // Can fail if the PIC is not initialized.
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+    }
+
+    scheduler::timer_tick(context)
+}
+
+/// Shows that the timer interrupt is running, on behalf of its handler.
+#[cfg(debug_assertions)]
+fn print_heartbeat(_: u64) {
+    print!(".");
+}
+
+#[deny(clippy::disallowed_methods)]
+extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let _interrupt = HardwareInterrupt::enter();
+    lazy_static! {
+        static ref KEYBOARD: spin::Mutex<pc_keyboard::Keyboard<layouts::Us104Key, ScancodeSet1>> =
+            Mutex::new(pc_keyboard::Keyboard::new(
+                ScancodeSet1::new(),
+                layouts::Us104Key,
+                HandleControl::Ignore,
+            ));
+    }
+    let mut port = Port::new(0x60);
+
+    // Read the scancode from the keyboard port.
+    //
+    // SAFETY:
+    // We are reading from the keyboard port which is known to be safe.
+    let scancode: u8 = unsafe { port.read() };
+    keyboard::add_scancode(scancode);
+
+    // Notify the PICs that the interrupt has been handled.
+    //
+    // SAFETY:
+    // Unsafe because accessing PICS directly can lead to undefined behavior if not done correctly.
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use x86_64::instructions::interrupts;
+
+    #[test_case]
+    fn test_breakpoint_exception() {
+        interrupts::int3();
+    }
+}
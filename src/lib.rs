@@ -27,6 +27,7 @@ pub mod interrupts;
 pub mod memory;
 pub mod serial;
 pub mod task;
+pub mod time;
 pub mod userspace;
 pub mod vga_buffer;
 
@@ -35,11 +36,13 @@ pub mod vga_buffer;
 /// It initializes all the necessary components of the kernel.
 pub fn init() {
     gdt::init();
+    userspace::syscall::init_syscall_instructions();
     interrupts::init_idt();
 
     // SAFETY:
     // Initialize the Programmable Interrupt Controller (PIC).
     unsafe { interrupts::PICS.lock().initialize() }
+    time::init();
     // Enable interrupts.
     instructions::interrupts::enable();
 }
@@ -0,0 +1,256 @@
+//! Physical frame allocator built from the bootloader memory map.
+//!
+//! The allocator keeps one bit per physical frame, set while the frame is
+//! free, and a reference count per frame so that a frame shared by several
+//! owners is only freed once the last of them gives it back. Both tables are
+//! stored in the first usable region large enough to hold them and are
+//! accessed through the physical memory mapping.
+
+use core::{ops::Range, ptr, slice};
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegion, MemoryRegionType};
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr,
+};
+
+use super::phys_to_virt;
+
+/// Size of a physical frame in bytes.
+const FRAME_SIZE: u64 = 4096;
+
+/// Number of frames tracked by a word of the free bitmap.
+const FRAMES_PER_WORD: usize = 64;
+
+/// Returns the number of the frame containing the physical address `addr`.
+#[expect(
+    clippy::integer_division,
+    reason = "Addresses are rounded down to their frame on purpose."
+)]
+const fn frame_number(addr: u64) -> u64 {
+    addr / FRAME_SIZE
+}
+
+/// Returns the bitmap word and the bit tracking the frame number `index`.
+#[expect(
+    clippy::integer_division,
+    reason = "The remainder is the bit inside the word."
+)]
+const fn bitmap_position(index: usize) -> (usize, usize) {
+    (index / FRAMES_PER_WORD, index % FRAMES_PER_WORD)
+}
+
+/// Frame usage of a [`BootInfoFrameAllocator`], in frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Number of usable frames reported by the bootloader.
+    pub total: usize,
+    /// Number of allocated frames, including the allocator's own tables.
+    pub used: usize,
+    /// Number of frames available for allocation.
+    pub free: usize,
+}
+
+/// A frame allocator whose frames can be shared by several mappings.
+///
+/// A shared frame holds one reference per mapping and is only freed once
+/// [`FrameDeallocator::deallocate_frame`] has been called once per reference.
+pub trait FrameReferences: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB> {
+    /// Adds a reference to the allocated `frame`.
+    ///
+    /// Does nothing if `frame` is not allocated. A frame reaching `u16::MAX`
+    /// references is never freed.
+    fn add_reference(&mut self, frame: PhysFrame);
+
+    /// Returns the number of references to `frame`, `0` if it is not allocated.
+    fn reference_count(&self, frame: PhysFrame) -> u16;
+}
+
+/// A frame allocator that returns usable frames from the bootloader's memory map.
+///
+/// Allocation returns the free frame with the lowest address. The search
+/// starts at the first bitmap word that may contain a free frame, so it runs in
+/// amortized constant time. Frames given back through [`FrameDeallocator`]
+/// become available again once their reference count drops to zero.
+pub struct BootInfoFrameAllocator {
+    /// One bit per frame, set while the frame is free.
+    free_bitmap: &'static mut [u64],
+    /// Number of references to each frame, `0` for free and unusable frames.
+    ref_counts: &'static mut [u16],
+    /// Every bitmap word before this one is fully allocated.
+    next_word: usize,
+    total: usize,
+    free: usize,
+}
+
+impl BootInfoFrameAllocator {
+    /// Create a `BootInfoFrameAllocator` from the passed memory map.
+    ///
+    /// The free bitmap and the reference counts are placed at the start of the
+    /// first usable region large enough to hold them; those frames are
+    /// reported as used.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the passed memory map is valid and that
+    /// [`memory::init`](super::init) has been called, so that physical memory
+    /// is accessible. Only one allocator may be created, since it takes
+    /// ownership of every usable frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no usable region can hold the allocator tables.
+    #[must_use]
+    pub unsafe fn new(memory_map: &'static MemoryMap) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        let end_frame = usable_regions()
+            .map(|r| frame_number(r.range.end_addr()))
+            .max()
+            .unwrap_or(0);
+        let frame_count = usize::try_from(end_frame).unwrap_or(0);
+        let word_count = frame_count.div_ceil(FRAMES_PER_WORD);
+        let table_size =
+            u64::try_from(word_count * size_of::<u64>() + frame_count * size_of::<u16>())
+                .unwrap_or(u64::MAX);
+
+        let Some(table_region) =
+            usable_regions().find(|r| r.range.end_addr() - r.range.start_addr() >= table_size)
+        else {
+            panic!("No usable memory region can hold the frame allocator tables.");
+        };
+        let table_start = table_region.range.start_addr();
+        let table_frames = frame_number(table_start)
+            ..frame_number(table_start + table_size.next_multiple_of(FRAME_SIZE));
+
+        let bitmap_ptr = phys_to_virt(PhysAddr::new(table_start)).as_mut_ptr::<u64>();
+        ptr::write_bytes(bitmap_ptr, 0, word_count);
+        let ref_counts_ptr = bitmap_ptr.add(word_count).cast::<u16>();
+        ptr::write_bytes(ref_counts_ptr, 0, frame_count);
+
+        let mut allocator = Self {
+            free_bitmap: slice::from_raw_parts_mut(bitmap_ptr, word_count),
+            ref_counts: slice::from_raw_parts_mut(ref_counts_ptr, frame_count),
+            next_word: 0,
+            total: 0,
+            free: 0,
+        };
+        for region in usable_regions() {
+            allocator.add_region(region, &table_frames);
+        }
+
+        allocator
+    }
+
+    /// Marks the frames of `region` as free, except the ones in `reserved`
+    /// which are marked as allocated.
+    fn add_region(&mut self, region: &MemoryRegion, reserved: &Range<u64>) {
+        for number in frame_number(region.range.start_addr())..frame_number(region.range.end_addr())
+        {
+            let Ok(index) = usize::try_from(number) else {
+                continue;
+            };
+            self.total += 1;
+            if reserved.contains(&number) {
+                self.ref_counts[index] = 1;
+            } else {
+                self.set_free(index, true);
+                self.free += 1;
+            }
+        }
+    }
+
+    /// Returns the index of `frame` in the allocator tables, if it is tracked.
+    fn index_of(&self, frame: PhysFrame) -> Option<usize> {
+        usize::try_from(frame_number(frame.start_address().as_u64()))
+            .ok()
+            .filter(|&index| index < self.ref_counts.len())
+    }
+
+    /// Sets or clears the free bit of the frame at `index`.
+    fn set_free(&mut self, index: usize, free: bool) {
+        let (word, bit) = bitmap_position(index);
+        if free {
+            self.free_bitmap[word] |= 1 << bit;
+            self.next_word = self.next_word.min(word);
+        } else {
+            self.free_bitmap[word] &= !(1 << bit);
+        }
+    }
+
+    /// Returns the current frame usage.
+    #[must_use]
+    pub const fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.total,
+            used: self.total - self.free,
+            free: self.free,
+        }
+    }
+}
+
+/// Implement the `FrameAllocator` trait for `BootInfoFrameAllocator`.
+///
+/// SAFETY:
+///
+/// Unsafe because the caller must guarantee that the memory map is valid.
+unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let (word, bits) = self
+            .free_bitmap
+            .iter()
+            .enumerate()
+            .skip(self.next_word)
+            .find(|&(_, &bits)| bits != 0)?;
+        let index = word * FRAMES_PER_WORD + bits.trailing_zeros() as usize;
+
+        self.next_word = word;
+        self.set_free(index, false);
+        self.ref_counts[index] = 1;
+        self.free -= 1;
+
+        let addr = u64::try_from(index).ok()? * FRAME_SIZE;
+        Some(PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    /// Drops a reference to `frame` and frees it once no reference is left.
+    ///
+    /// Frames that are not allocated are ignored.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let Some(index) = self.index_of(frame) else {
+            return;
+        };
+        match self.ref_counts[index] {
+            // A count stopped at the maximum no longer tracks the references
+            // left: the frame is never freed.
+            0 | u16::MAX => {}
+            1 => {
+                self.ref_counts[index] = 0;
+                self.set_free(index, true);
+                self.free += 1;
+            }
+            count => self.ref_counts[index] = count - 1,
+        }
+    }
+}
+
+impl FrameReferences for BootInfoFrameAllocator {
+    fn add_reference(&mut self, frame: PhysFrame) {
+        if let Some(index) = self.index_of(frame) {
+            if self.ref_counts[index] > 0 {
+                self.ref_counts[index] = self.ref_counts[index].saturating_add(1);
+            }
+        }
+    }
+
+    fn reference_count(&self, frame: PhysFrame) -> u16 {
+        self.index_of(frame)
+            .map_or(0, |index| self.ref_counts[index])
+    }
+}
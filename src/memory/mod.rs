@@ -0,0 +1,405 @@
+//! Memory management module for setting up paging and frame allocation.
+//!
+//! Besides the kernel page table set up by the bootloader, this module creates
+//! the level 4 page tables of user address spaces. Such a table shares every
+//! kernel mapping with the kernel page table, except in a private address
+//! range whose page tables and frames are owned by the address space. It is
+//! activated by writing its frame to `CR3` and released once it is no longer
+//! used.
+//!
+//! Physical frames are handed out by the [`BootInfoFrameAllocator`], which
+//! also takes them back once they are no longer referenced. The kernel owns a
+//! single allocator, created by [`init_frame_allocator`] and shared through
+//! [`with_frame_allocator`], so that syscall and exception handlers can map
+//! memory as well.
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bootloader::bootinfo::MemoryMap;
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts,
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::MapToError, page_table::PageTableLevel, FrameAllocator, FrameDeallocator, Mapper,
+        OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+mod frame_allocator;
+
+pub use frame_allocator::{BootInfoFrameAllocator, FrameReferences, FrameStats};
+
+/// Virtual address at which the bootloader mapped the physical memory.
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Physical address of the kernel level 4 page table, or `0` before [`init`].
+static KERNEL_LEVEL_4_TABLE: AtomicU64 = AtomicU64::new(0);
+
+/// The kernel frame allocator, `None` before [`init_frame_allocator`].
+static FRAME_ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
+/// Initialize a new `OffsetPageTable`.
+///
+/// The active level 4 table is recorded as the kernel page table: it is the
+/// template of the tables created by [`new_level_4_table`] and the table
+/// restored by [`switch_to_kernel_level_4_table`].
+///
+/// # Safety
+/// Unsafe because the caller must guarantee that the physical memory is mapped
+/// to virtual memory at the passed `physical_memory_offset`.
+/// This function must be only called once to avoid aliasing `&mut` references.
+#[must_use]
+pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
+    let (level_4_table_frame, _) = Cr3::read();
+    KERNEL_LEVEL_4_TABLE.store(
+        level_4_table_frame.start_address().as_u64(),
+        Ordering::Relaxed,
+    );
+
+    let level_4_table = active_level_4_table(physical_memory_offset);
+    OffsetPageTable::new(level_4_table, physical_memory_offset)
+}
+
+/// Creates the kernel frame allocator from the bootloader memory map.
+///
+/// # Safety
+///
+/// The memory map must be valid and [`init`] must have been called. This
+/// function must only be called once, since the allocator takes ownership of
+/// every usable frame.
+pub unsafe fn init_frame_allocator(memory_map: &'static MemoryMap) {
+    let frame_allocator = BootInfoFrameAllocator::new(memory_map);
+    interrupts::without_interrupts(|| *FRAME_ALLOCATOR.lock() = Some(frame_allocator));
+}
+
+/// Runs `f` with exclusive access to the kernel frame allocator.
+///
+/// Interrupts are disabled while the allocator is locked so that an interrupt
+/// handler using it cannot deadlock with the interrupted code. `f` must not
+/// call this function again.
+///
+/// # Panics
+///
+/// Panics if [`init_frame_allocator`] has not been called.
+pub fn with_frame_allocator<R, F>(f: F) -> R
+where
+    F: FnOnce(&mut BootInfoFrameAllocator) -> R,
+{
+    interrupts::without_interrupts(|| {
+        let mut guard = FRAME_ALLOCATOR.lock();
+        let Some(frame_allocator) = guard.as_mut() else {
+            panic!("The frame allocator is not initialized.");
+        };
+        f(frame_allocator)
+    })
+}
+
+/// Returns a mutable reference to the active level 4 table.
+///
+/// # Safety
+/// Unsafe because the caller must guarantee that the physical memory is mapped
+/// to virtual memory at the passed `physical_memory_offset`.
+/// This function must be only called once to avoid aliasing `&mut` references.
+#[must_use]
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    // Cr3 register holds the physical address of the level 4 page table.
+    let (level_4_table_frame, _) = Cr3::read();
+
+    let phys = level_4_table_frame.start_address();
+    let virt = physical_memory_offset + phys.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    &mut *page_table_ptr
+}
+
+/// Returns the virtual address at which the physical address `addr` can be
+/// accessed through the physical memory mapping.
+#[must_use]
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + addr.as_u64())
+}
+
+/// Returns `true` if `addr` is mapped in the kernel page table.
+#[must_use]
+pub fn is_mapped(addr: VirtAddr) -> bool {
+    let Some(frame) = kernel_level_4_frame() else {
+        return false;
+    };
+
+    // SAFETY:
+    //
+    // The kernel page table is only read.
+    let mapper = unsafe { mapper_for(frame) };
+    mapper.translate_addr(addr).is_some()
+}
+
+/// Makes the device registers in `frame` accessible through the physical
+/// memory mapping and returns their virtual address.
+///
+/// The bootloader only maps the physical memory up to the end of the last
+/// region of the memory map, so device memory past it may not be accessible
+/// yet. Such a frame is mapped here, uncached, in the kernel page table.
+/// Returns `None` if the frame cannot be mapped.
+///
+/// # Panics
+///
+/// Panics if the frame has to be mapped and [`init_frame_allocator`] has not
+/// been called.
+#[must_use]
+pub fn map_mmio(frame: PhysFrame) -> Option<VirtAddr> {
+    let virt = phys_to_virt(frame.start_address());
+    if is_mapped(virt) {
+        return Some(virt);
+    }
+
+    // SAFETY:
+    //
+    // The kernel page table is only modified here, with the frame allocator
+    // locked and interrupts disabled.
+    let mut mapper = unsafe { mapper_for(kernel_level_4_frame()?) };
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    let mapped = with_frame_allocator(|frame_allocator| {
+        // SAFETY:
+        //
+        // The page is the one of the physical memory mapping for `frame`, and
+        // is not mapped yet. Device memory is never handed out by the frame
+        // allocator, so the frame is not used for anything else.
+        unsafe {
+            mapper.map_to(
+                Page::containing_address(virt),
+                frame,
+                flags,
+                frame_allocator,
+            )
+        }
+    });
+    mapped.ok()?.flush();
+    Some(virt)
+}
+
+/// Returns the frame of the kernel level 4 table, if [`init`] has been called.
+fn kernel_level_4_frame() -> Option<PhysFrame> {
+    match KERNEL_LEVEL_4_TABLE.load(Ordering::Relaxed) {
+        0 => None,
+        addr => Some(PhysFrame::containing_address(PhysAddr::new(addr))),
+    }
+}
+
+/// Returns a mutable reference to the page table stored in `frame`.
+///
+/// # Safety
+///
+/// `frame` must hold a page table and no other reference to that table may be
+/// used while the returned one is alive.
+unsafe fn page_table_at(frame: PhysFrame) -> &'static mut PageTable {
+    &mut *phys_to_virt(frame.start_address()).as_mut_ptr::<PageTable>()
+}
+
+/// Returns a mapper for the level 4 table stored in `frame`.
+///
+/// The table does not need to be active: the mapper accesses the page tables
+/// through the physical memory mapping.
+///
+/// # Safety
+///
+/// `frame` must hold a level 4 table created by [`new_level_4_table`] and the
+/// caller must guarantee that no other mapper for this table is used while the
+/// returned one is alive.
+#[must_use]
+pub unsafe fn mapper_for(frame: PhysFrame) -> OffsetPageTable<'static> {
+    OffsetPageTable::new(
+        page_table_at(frame),
+        VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed)),
+    )
+}
+
+/// Creates a level 4 page table sharing the kernel mappings.
+///
+/// Every entry of the kernel page table is copied, so the kernel code, stacks,
+/// heap and physical memory mapping stay accessible once the new table is
+/// active. The addresses in `private` are left unmapped: the page tables
+/// covering them are copied rather than shared, so that pages mapped there do
+/// not leak into the kernel page table or into other tables.
+///
+/// # Errors
+///
+/// Returns [`MapToError::FrameAllocationFailed`] if the frame allocator runs
+/// out of frames, or [`MapToError::ParentEntryHugePage`] if a huge page of the
+/// kernel covers part of `private`. Frames allocated before the error are
+/// given back to `frame_allocator`. [`MapToError::FrameAllocationFailed`] is
+/// also returned if [`init`] has not been called, since there is no kernel
+/// page table to copy yet.
+pub fn new_level_4_table<A>(
+    private: &Range<u64>,
+    frame_allocator: &mut A,
+) -> Result<PhysFrame, MapToError<Size4KiB>>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let kernel_frame = kernel_level_4_frame().ok_or(MapToError::FrameAllocationFailed)?;
+    copy_table(
+        kernel_frame,
+        PageTableLevel::Four,
+        0,
+        private,
+        frame_allocator,
+    )
+}
+
+/// Copies the page table in `source`, which maps the addresses starting at
+/// `base`, into a new frame. See [`new_level_4_table`].
+fn copy_table<A>(
+    source: PhysFrame,
+    level: PageTableLevel,
+    base: u64,
+    private: &Range<u64>,
+    frame_allocator: &mut A,
+) -> Result<PhysFrame, MapToError<Size4KiB>>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let frame = frame_allocator
+        .allocate_frame()
+        .ok_or(MapToError::FrameAllocationFailed)?;
+    let span = level.entry_address_space_alignment();
+
+    // SAFETY:
+    //
+    // `source` is a page table of the kernel, which is only read here.
+    let source_table: &PageTable = unsafe { page_table_at(source) };
+    // SAFETY:
+    //
+    // `frame` was just allocated, so this is the only reference to the table.
+    let table = unsafe { page_table_at(frame) };
+    table.zero();
+
+    for (index, (entry, source_entry)) in (0..).zip(table.iter_mut().zip(source_table.iter())) {
+        let start = base + index * span;
+        let end = start + span;
+
+        if end <= private.start || start >= private.end {
+            entry.clone_from(source_entry);
+            continue;
+        }
+        if (private.start <= start && end <= private.end) || source_entry.is_unused() {
+            continue;
+        }
+
+        let copy = match (source_entry.frame(), level.next_lower_level()) {
+            (Ok(child), Some(child_level)) => {
+                copy_table(child, child_level, start, private, frame_allocator)
+            }
+            _ => Err(MapToError::ParentEntryHugePage),
+        };
+        match copy {
+            Ok(child_copy) => entry.set_frame(child_copy, source_entry.flags()),
+            Err(error) => {
+                // SAFETY:
+                //
+                // The partially built table has never been active and only
+                // owns the copies made so far.
+                unsafe {
+                    free_table(frame, level, base, private, frame_allocator);
+                }
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(frame)
+}
+
+/// Releases a level 4 table created by [`new_level_4_table`].
+///
+/// The page tables covering `private` are given back to `frame_allocator`,
+/// together with the level 4 table itself, and the reference held by each
+/// frame mapped in `private` is dropped: a frame shared with another table is
+/// only freed with its last reference. Kernel mappings are left untouched.
+///
+/// # Safety
+///
+/// `frame` must hold a level 4 table created by [`new_level_4_table`] with the
+/// same `private` range. The table must not be active and must not be used
+/// anymore. The page tables covering `private` must not be shared with another
+/// table, and each mapping of a frame in `private` must hold one reference to
+/// it, counted by `frame_allocator`.
+pub unsafe fn free_level_4_table<D>(frame: PhysFrame, private: &Range<u64>, frame_allocator: &mut D)
+where
+    D: FrameDeallocator<Size4KiB>,
+{
+    free_table(frame, PageTableLevel::Four, 0, private, frame_allocator);
+}
+
+/// Releases the page table in `frame`, which maps the addresses starting at
+/// `base`, along with the tables and frames it owns. See [`free_level_4_table`].
+///
+/// # Safety
+///
+/// Same requirements as [`free_level_4_table`].
+unsafe fn free_table<D>(
+    frame: PhysFrame,
+    level: PageTableLevel,
+    base: u64,
+    private: &Range<u64>,
+    frame_allocator: &mut D,
+) where
+    D: FrameDeallocator<Size4KiB>,
+{
+    let span = level.entry_address_space_alignment();
+
+    for (index, entry) in (0..).zip(page_table_at(frame).iter()) {
+        let start = base + index * span;
+        let end = start + span;
+        if end <= private.start || start >= private.end {
+            continue;
+        }
+
+        // Huge pages are never mapped in the private range.
+        let Ok(child) = entry.frame() else {
+            continue;
+        };
+        match level.next_lower_level() {
+            Some(child_level) => free_table(child, child_level, start, private, frame_allocator),
+            None => frame_allocator.deallocate_frame(child),
+        }
+    }
+
+    frame_allocator.deallocate_frame(frame);
+}
+
+/// Makes the level 4 table stored in `frame` the active page table.
+///
+/// # Safety
+///
+/// `frame` must hold a level 4 table mapping the kernel, such as one created
+/// by [`new_level_4_table`], and the table must stay alive while it is active.
+pub unsafe fn switch_level_4_table(frame: PhysFrame) {
+    let (active, flags) = Cr3::read();
+    if active != frame {
+        Cr3::write(frame, flags);
+    }
+}
+
+/// Makes the kernel level 4 table the active page table again.
+///
+/// Does nothing if [`init`] has not been called.
+pub fn switch_to_kernel_level_4_table() {
+    if let Some(frame) = kernel_level_4_frame() {
+        // SAFETY:
+        //
+        // The kernel page table maps the kernel and is never released.
+        unsafe {
+            switch_level_4_table(frame);
+        }
+    }
+}
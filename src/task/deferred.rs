@@ -0,0 +1,146 @@
+//! Deferred work of interrupt handlers (bottom halves).
+//!
+//! An interrupt handler runs with interrupts disabled, possibly in the
+//! middle of code holding a lock, so it must be short and must not lock
+//! anything the interrupted code may hold, such as the VGA writer used by
+//! [`println!`](crate::println). Instead, it queues the rest of the work with
+//! [`defer`], which neither allocates nor blocks. The work items are run in
+//! order by the [`worker`] task, in the [`Priority::BottomHalf`] class.
+//!
+//! Work items queued before [`init`] is called, or while the queue is full,
+//! are dropped and counted by [`dropped`].
+
+use core::{
+    fmt, future,
+    sync::atomic::{AtomicU64, Ordering},
+    task::Poll,
+};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+
+use super::{Priority, Task};
+
+/// Maximum number of work items waiting to be run.
+pub const CAPACITY: usize = 256;
+
+/// Maximum number of work items run in a row, before the worker lets other
+/// tasks run.
+pub const BATCH: usize = 32;
+
+static QUEUE: OnceCell<ArrayQueue<Work>> = OnceCell::uninit();
+
+/// Waker of the worker task.
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Number of work items dropped.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// A work item: a function and its argument.
+#[derive(Debug, Clone, Copy)]
+struct Work {
+    function: fn(u64),
+    argument: u64,
+}
+
+/// Error returned by [`defer`]. The work item is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeferError {
+    /// The queue has not been created by [`init`] yet.
+    Uninitialized,
+    /// [`CAPACITY`] work items are already waiting.
+    Full,
+}
+
+impl fmt::Display for DeferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Uninitialized => write!(f, "deferred work queue uninitialized"),
+            Self::Full => write!(f, "deferred work queue full"),
+        }
+    }
+}
+
+/// Creates the queue of work items. It allocates, so it must be called once
+/// the heap is initialized. Further calls do nothing.
+pub fn init() {
+    QUEUE.init_once(new_queue);
+}
+
+/// Queues `function` to be called with `argument` by the [`worker`] task,
+/// and wakes the worker.
+///
+/// This neither allocates nor blocks, so it can be called from an interrupt
+/// handler.
+///
+/// # Errors
+///
+/// Returns [`DeferError`] if the queue is not initialized or is full. The
+/// work item is then dropped and counted by [`dropped`].
+#[deny(clippy::disallowed_methods)]
+pub fn defer(function: fn(u64), argument: u64) -> Result<(), DeferError> {
+    let queued = QUEUE
+        .try_get()
+        .map_or(Err(DeferError::Uninitialized), |queue| {
+            queue
+                .push(Work { function, argument })
+                .or(Err(DeferError::Full))
+        });
+    match queued {
+        Ok(()) => WAKER.wake(),
+        Err(_) => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    queued
+}
+
+/// Returns the number of work items waiting to be run.
+#[must_use]
+pub fn pending() -> usize {
+    QUEUE.try_get().map_or(0, ArrayQueue::len)
+}
+
+/// Returns the number of work items dropped because the queue was not
+/// initialized or full.
+#[must_use]
+pub fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Returns the task running the deferred work items, creating the queue if
+/// needed. It never completes.
+///
+/// Only one worker should be spawned, so that work items run in the order
+/// they were queued.
+pub fn worker() -> Task {
+    let queue = QUEUE.get_or_init(new_queue);
+    Task::new(run(queue)).with_priority(Priority::BottomHalf)
+}
+
+fn new_queue() -> ArrayQueue<Work> {
+    ArrayQueue::new(CAPACITY)
+}
+
+/// Runs the work items as they are queued, [`BATCH`] at a time.
+async fn run(queue: &'static ArrayQueue<Work>) {
+    future::poll_fn(|cx| {
+        for _ in 0..BATCH {
+            let Some(work) = queue.pop() else {
+                WAKER.register(cx.waker());
+                // A work item queued before the waker was registered did not
+                // wake the worker.
+                if !queue.is_empty() {
+                    cx.waker().wake_by_ref();
+                }
+                return Poll::<()>::Pending;
+            };
+            (work.function)(work.argument);
+        }
+        // Let the other tasks run before the next batch.
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await;
+}
@@ -1,21 +1,82 @@
 //! A task executor.
+//!
+//! Ready tasks are kept in intrusive lists of their wakers: waking a task
+//! links its waker into a list without allocating or locking, so tasks can be
+//! woken from interrupt handlers and the lists never fill up. A task woken
+//! again before it is polled is only queued once, so the lists never hold
+//! more entries than there are tasks.
+//!
+//! There is one list per [`Priority`] class. Each round, the executor polls
+//! the tasks of the highest class with ready tasks, except that a class kept
+//! waiting for [`STARVATION_LIMIT`] rounds goes first. A task that wakes
+//! itself up at every poll is throttled after [`POLL_BUDGET`] polls: it runs
+//! as an idle task until it waits for something else.
 
-use core::task::{Context, Poll, Waker};
+use core::{
+    array,
+    cell::RefCell,
+    future::Future,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    task::{Context, Waker},
+    time::Duration,
+};
 
-use alloc::{collections::btree_map::BTreeMap, sync::Arc, task::Wake};
-use crossbeam_queue::ArrayQueue;
+use alloc::{
+    collections::{btree_map::BTreeMap, VecDeque},
+    rc::Rc,
+    sync::Arc,
+    task::Wake,
+};
 use x86_64::instructions::interrupts;
 
-use crate::task::TaskId;
+use crate::{task::TaskId, time};
 
-use super::Task;
+use super::{JoinHandle, Priority, Task};
+
+/// Number of rounds a priority class with ready tasks can be passed over for
+/// higher classes before it is polled first.
+pub const STARVATION_LIMIT: u32 = 8;
+
+/// Number of polls in a row, each waking the task up again, after which the
+/// task is throttled to [`Priority::Idle`]. Wakeups by interrupt handlers and
+/// other tasks do not count.
+pub const POLL_BUDGET: u32 = 16;
 
 /// A `Task` `Executor`.
 /// Store a tree with taskId and associated task. Also use `Waker` to wake ready tasks.
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
+    ready_queues: Arc<ReadyQueues>,
+    spawn_queue: Rc<RefCell<VecDeque<Task>>>,
+    /// Number of rounds each class with ready tasks has been passed over.
+    starved: [u32; Priority::COUNT],
+    /// Number of times a task has been throttled.
+    throttled: u64,
+    /// Number of times a task has been polled.
+    polls: u64,
+    /// Time spent halted, waiting for an interrupt.
+    idle: Duration,
+}
+
+/// Activity counters of an [`Executor`], for diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutorStats {
+    /// Number of tasks in the executor, whether ready or waiting.
+    pub tasks: usize,
+    /// Number of times a task has been woken, including when it was already
+    /// ready and when it was spawned.
+    pub wakes: u64,
+    /// Number of wakeups of tasks that were already ready, which did not
+    /// queue them again.
+    pub coalesced_wakes: u64,
+    /// Number of times a task has been polled.
+    pub polls: u64,
+    /// Number of times a task has exhausted its [`POLL_BUDGET`] and been
+    /// throttled.
+    pub throttled: u64,
+    /// Time spent halted, waiting for an interrupt.
+    pub idle: Duration,
 }
 
 impl Executor {
@@ -23,85 +84,396 @@ impl Executor {
     pub fn new() -> Self {
         Executor {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
-            waker_cache: BTreeMap::new(),
+            ready_queues: Arc::new(ReadyQueues::new()),
+            spawn_queue: Rc::new(RefCell::new(VecDeque::new())),
+            starved: [0; Priority::COUNT],
+            throttled: 0,
+            polls: 0,
+            idle: Duration::ZERO,
+        }
+    }
+
+    /// Returns a handle spawning tasks on this executor, usable from the
+    /// running tasks.
+    #[must_use]
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            spawn_queue: self.spawn_queue.clone(),
         }
     }
 
     /// Spawn a new task
     pub fn spawn(&mut self, task: Task) {
         let task_id = task.id;
+        let priority = task.priority;
         if self.tasks.insert(task.id, task).is_some() {
             panic!("Task with same ID already in tasks");
         }
-        self.task_queue.push(task_id).expect("queue full");
+        TaskWaker::new(task_id, priority, self.ready_queues.clone()).wake();
+    }
+
+    /// Moves the tasks queued by the spawners into the executor.
+    fn spawn_queued_tasks(&mut self) {
+        while let Some(task) = self.pop_spawned_task() {
+            self.spawn(task);
+        }
+    }
+
+    /// Takes the oldest task queued by the spawners.
+    fn pop_spawned_task(&self) -> Option<Task> {
+        self.spawn_queue.borrow_mut().pop_front()
     }
 
+    /// Polls the ready tasks of one priority class, chosen by
+    /// [`next_class`](Self::next_class).
     fn run_ready_tasks(&mut self) {
-        let Self {
-            tasks,
-            task_queue,
-            waker_cache,
-        } = self;
-
-        while let Some(task_id) = task_queue.pop() {
-            let task = match tasks.get_mut(&task_id) {
-                Some(task) => task,
-                None => continue,
+        let Some(class) = self.next_class() else {
+            return;
+        };
+        for task_waker in self.ready_queues.classes[class].take() {
+            // Wakeups from now on queue the task again.
+            task_waker.queued.store(false, Ordering::Release);
+
+            let task_id = task_waker.task_id;
+            let Some(task) = self.tasks.get_mut(&task_id) else {
+                continue;
             };
-            let waker = waker_cache
-                .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
-
-            let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    tasks.remove(&task_id);
-                    waker_cache.remove(&task_id);
+            let waker = Waker::from(task_waker.clone());
+            let mut context = Context::from_waker(&waker);
+            self.polls += 1;
+            task_waker.polling.store(true, Ordering::Relaxed);
+            let poll = task.poll(&mut context);
+            task_waker.polling.store(false, Ordering::Relaxed);
+            let woke_itself = task_waker.woke_itself.swap(false, Ordering::Relaxed);
+            if poll.is_ready() {
+                self.tasks.remove(&task_id);
+                continue;
+            }
+
+            if woke_itself {
+                // The task woke itself up: it is using its budget. Its class
+                // only changes for the next wakeups, as it is queued already.
+                task.budget = task.budget.saturating_sub(1);
+                if task.budget == 0 && task_waker.class() != Priority::Idle {
+                    task_waker.set_class(Priority::Idle);
+                    self.throttled += 1;
                 }
-                Poll::Pending => {}
+            } else {
+                task.budget = POLL_BUDGET;
+                task_waker.set_class(task.priority);
             }
         }
     }
 
+    /// Chooses the priority class whose ready tasks are polled this round,
+    /// if any task is ready.
+    ///
+    /// This is the highest class with ready tasks, unless a class has been
+    /// passed over [`STARVATION_LIMIT`] times in a row.
+    fn next_class(&mut self) -> Option<usize> {
+        let ready: [bool; Priority::COUNT] =
+            array::from_fn(|class| !self.ready_queues.classes[class].is_empty());
+        let chosen = (0..Priority::COUNT)
+            .find(|&class| ready[class] && self.starved[class] >= STARVATION_LIMIT)
+            .or_else(|| (0..Priority::COUNT).find(|&class| ready[class]))?;
+
+        for (class, starved) in self.starved.iter_mut().enumerate() {
+            *starved = if ready[class] && class != chosen {
+                starved.saturating_add(1)
+            } else {
+                0
+            };
+        }
+        Some(chosen)
+    }
+
     /// Run the executor. Should be call in one thread.
     pub fn run(&mut self) -> ! {
         loop {
+            self.spawn_queued_tasks();
             self.run_ready_tasks();
             self.sleep_if_idle();
         }
     }
 
-    fn sleep_if_idle(&self) {
+    /// Runs the tasks until none is ready to make progress, then returns.
+    ///
+    /// Tasks waiting for an event stay in the executor and resume on the next
+    /// call.
+    pub fn run_until_idle(&mut self) {
+        while !self.is_idle() {
+            self.spawn_queued_tasks();
+            self.run_ready_tasks();
+        }
+    }
+
+    /// Returns the number of tasks in the executor, whether ready or waiting.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tasks.len() + self.spawn_queue.borrow().len()
+    }
+
+    /// Returns `true` if the executor has no task left.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the activity counters of the executor.
+    #[must_use]
+    pub fn stats(&self) -> ExecutorStats {
+        ExecutorStats {
+            tasks: self.len(),
+            wakes: self.ready_queues.wakes.load(Ordering::Relaxed),
+            coalesced_wakes: self.ready_queues.coalesced_wakes.load(Ordering::Relaxed),
+            polls: self.polls,
+            throttled: self.throttled,
+            idle: self.idle,
+        }
+    }
+
+    /// Returns `true` if no task is ready to make progress.
+    fn is_idle(&self) -> bool {
+        self.ready_queues.is_empty() && self.spawn_queue.borrow().is_empty()
+    }
+
+    fn sleep_if_idle(&mut self) {
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        if self.is_idle() {
+            let start = time::now();
             interrupts::enable_and_hlt();
+            self.idle += start.elapsed();
         } else {
             interrupts::enable();
         }
     }
 }
 
+impl Drop for Executor {
+    fn drop(&mut self) {
+        // The queued wakers refer to the queues: release them to break the
+        // cycle.
+        for queue in &self.ready_queues.classes {
+            drop(queue.take());
+        }
+    }
+}
+
+/// Cloneable handle spawning tasks on an [`Executor`], returned by
+/// [`Executor::spawner`].
+///
+/// The tasks are queued, and the executor picks them up before polling the
+/// ready tasks.
+#[derive(Clone)]
+pub struct Spawner {
+    spawn_queue: Rc<RefCell<VecDeque<Task>>>,
+}
+
+impl Spawner {
+    /// Spawns a task running `future`, and returns the handle awaiting its
+    /// output. Dropping the handle cancels the task, unless it is detached.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_with_priority(future, Priority::Normal)
+    }
+
+    /// Spawns a task running `future` in the priority class `priority`, and
+    /// returns the handle awaiting its output.
+    pub fn spawn_with_priority<F>(&self, future: F, priority: Priority) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (task, handle) = Task::joinable(future);
+        self.spawn_queue
+            .borrow_mut()
+            .push_back(task.with_priority(priority));
+        handle
+    }
+}
+
+/// Ready queues of an executor, one per priority class.
+struct ReadyQueues {
+    /// Queue of each class, from the highest to the lowest.
+    classes: [ReadyQueue; Priority::COUNT],
+    /// Number of wakeups.
+    wakes: AtomicU64,
+    /// Number of wakeups of tasks that were already queued.
+    coalesced_wakes: AtomicU64,
+}
+
+impl ReadyQueues {
+    const fn new() -> Self {
+        Self {
+            classes: [ReadyQueue::new(), ReadyQueue::new(), ReadyQueue::new()],
+            wakes: AtomicU64::new(0),
+            coalesced_wakes: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues the task of `task_waker` in the queue of its class, unless it
+    /// is already queued.
+    fn push(&self, task_waker: &Arc<TaskWaker>) {
+        self.wakes.fetch_add(1, Ordering::Relaxed);
+        if task_waker.queued.swap(true, Ordering::AcqRel) {
+            self.coalesced_wakes.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.classes[task_waker.class().index()].push(task_waker);
+    }
+
+    /// Returns `true` if no task is queued.
+    fn is_empty(&self) -> bool {
+        self.classes.iter().all(ReadyQueue::is_empty)
+    }
+}
+
+/// Lock-free list of the wakers of the ready tasks of a priority class.
+///
+/// Each queued waker holds a reference, obtained with [`Arc::into_raw`], and
+/// is linked to the previously queued one through [`TaskWaker::next`].
+struct ReadyQueue {
+    /// Most recently queued waker, or null.
+    head: AtomicPtr<TaskWaker>,
+}
+
+impl ReadyQueue {
+    const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Links `task_waker` at the head of the list.
+    ///
+    /// The caller must have set its `queued` flag, so that it is in no list.
+    fn push(&self, task_waker: &Arc<TaskWaker>) {
+        let node = Arc::into_raw(task_waker.clone()).cast_mut();
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // The `queued` flag makes this the only access to `next` until
+            // the node is taken.
+            task_waker.next.store(head, Ordering::Relaxed);
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Takes every queued task, oldest first.
+    fn take(&self) -> ReadyTasks {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+
+        // The list is newest first: reverse it.
+        let mut reversed = ptr::null_mut();
+        while !node.is_null() {
+            // SAFETY:
+            //
+            // The node was queued with `Arc::into_raw`, so it is alive until
+            // its reference is released by `ReadyTasks`. It has been unlinked
+            // from the queue, so nothing else accesses its `next` field.
+            let next = unsafe { (*node).next.swap(reversed, Ordering::Relaxed) };
+            reversed = node;
+            node = next;
+        }
+        ReadyTasks { next: reversed }
+    }
+
+    /// Returns `true` if no task is queued.
+    fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+/// Ready tasks taken from a [`ReadyQueue`], oldest first.
+struct ReadyTasks {
+    /// Next waker, or null. It owns a reference to the waker.
+    next: *mut TaskWaker,
+}
+
+impl Iterator for ReadyTasks {
+    type Item = Arc<TaskWaker>;
+
+    fn next(&mut self) -> Option<Arc<TaskWaker>> {
+        if self.next.is_null() {
+            return None;
+        }
+        // SAFETY:
+        //
+        // The pointer was obtained with `Arc::into_raw` when the task was
+        // queued, and the reference it owns is moved out of the list here.
+        let task_waker = unsafe { Arc::from_raw(self.next) };
+        self.next = task_waker.next.load(Ordering::Relaxed);
+        Some(task_waker)
+    }
+}
+
+impl Drop for ReadyTasks {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
 struct TaskWaker {
     task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    ready_queues: Arc<ReadyQueues>,
+    /// Index of the class the task is queued in when woken up.
+    class: AtomicUsize,
+    /// `true` while the task is in the ready queue.
+    queued: AtomicBool,
+    /// `true` while the task is polled.
+    polling: AtomicBool,
+    /// `true` if the task woke itself up while it was polled.
+    woke_itself: AtomicBool,
+    /// Waker queued before this one, while it is queued.
+    next: AtomicPtr<TaskWaker>,
 }
 
 impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
+    fn new(task_id: TaskId, priority: Priority, ready_queues: Arc<ReadyQueues>) -> Arc<Self> {
+        Arc::new(TaskWaker {
             task_id,
-            task_queue,
-        }))
+            ready_queues,
+            class: AtomicUsize::new(priority.index()),
+            queued: AtomicBool::new(false),
+            polling: AtomicBool::new(false),
+            woke_itself: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        })
     }
 
-    fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task_queue full");
+    /// Returns the class the task is queued in when woken up.
+    fn class(&self) -> Priority {
+        Priority::ALL[self.class.load(Ordering::Relaxed)]
+    }
+
+    /// Sets the class the task is queued in when woken up.
+    fn set_class(&self, priority: Priority) {
+        self.class.store(priority.index(), Ordering::Relaxed);
+    }
+
+    /// Queues the task, noting whether it woke itself up.
+    fn wake_task(self: &Arc<Self>) {
+        // Interrupt handlers run with interrupts disabled: a wakeup from an
+        // interrupt raised while the task is polled is not the task's doing.
+        if self.polling.load(Ordering::Relaxed) && interrupts::are_enabled() {
+            self.woke_itself.store(true, Ordering::Relaxed);
+        }
+        self.ready_queues.push(self);
     }
 }
 
 impl Wake for TaskWaker {
     fn wake(self: Arc<Self>) {
+        // The queue keeps a reference, so dropping `self` never frees the
+        // waker, even in an interrupt handler.
         self.wake_task();
     }
 
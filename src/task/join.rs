@@ -0,0 +1,149 @@
+//! Handles on the result of spawned tasks.
+//!
+//! A task spawned through a [`Spawner`](super::executor::Spawner) shares its
+//! state with the [`JoinHandle`] returned to the caller. When the task
+//! completes, it stores its output there and wakes the task awaiting the
+//! handle.
+//!
+//! Dropping the handle cancels the task: the task is woken, notices the
+//! cancellation the next time it is polled and completes without running its
+//! future any further. [`JoinHandle::detach`] drops the handle without
+//! cancelling the task.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use alloc::{boxed::Box, sync::Arc};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+
+/// State shared by a task and its [`JoinHandle`].
+struct JoinState<T> {
+    /// Output of the task, until the handle takes it.
+    output: Mutex<Option<T>>,
+    /// `true` once the task has completed.
+    finished: AtomicBool,
+    /// `true` once the handle has been dropped without being detached.
+    cancelled: AtomicBool,
+    /// Waker of the task awaiting the handle.
+    join_waker: AtomicWaker,
+    /// Waker of the spawned task, to notice the cancellation.
+    task_waker: AtomicWaker,
+}
+
+/// Future awaiting the output of a spawned task.
+///
+/// Dropping the handle cancels the task, unless it is [detached](Self::detach).
+#[must_use = "dropping a join handle cancels the task"]
+pub struct JoinHandle<T> {
+    state: Arc<JoinState<T>>,
+    /// `false` once the task no longer depends on the handle.
+    attached: bool,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns `true` if the task has completed.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+
+    /// Drops the handle and lets the task run to completion.
+    pub fn detach(mut self) {
+        self.attached = false;
+    }
+
+    /// Cancels the task. It is not polled again.
+    pub fn cancel(self) {}
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    /// Returns the output of the task once it has completed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if polled again after it has returned the output.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let this = self.get_mut();
+        this.state.join_waker.register(cx.waker());
+        if !this.is_finished() {
+            return Poll::Pending;
+        }
+
+        this.attached = false;
+        #[expect(clippy::expect_used)]
+        let output = this
+            .state
+            .output
+            .lock()
+            .take()
+            .expect("JoinHandle polled after completion");
+        Poll::Ready(output)
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        if self.attached && !self.is_finished() {
+            self.state.cancelled.store(true, Ordering::Release);
+            self.state.task_waker.wake();
+        }
+    }
+}
+
+/// Future of a spawned task: runs the user future, then hands its output to
+/// the [`JoinHandle`].
+pub(super) struct Joinable<F: Future> {
+    future: Pin<Box<F>>,
+    state: Arc<JoinState<F::Output>>,
+}
+
+impl<F: Future> Future for Joinable<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.state.cancelled.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        this.state.task_waker.register(cx.waker());
+
+        let Poll::Ready(output) = this.future.as_mut().poll(cx) else {
+            return Poll::Pending;
+        };
+        *this.state.output.lock() = Some(output);
+        this.state.finished.store(true, Ordering::Release);
+        this.state.join_waker.wake();
+        Poll::Ready(())
+    }
+}
+
+/// Wraps `future` into the future of a task, and returns it with the handle
+/// awaiting its output.
+pub(super) fn joinable<F>(future: F) -> (Joinable<F>, JoinHandle<F::Output>)
+where
+    F: Future,
+{
+    let state = Arc::new(JoinState {
+        output: Mutex::new(None),
+        finished: AtomicBool::new(false),
+        cancelled: AtomicBool::new(false),
+        join_waker: AtomicWaker::new(),
+        task_waker: AtomicWaker::new(),
+    });
+    let task_future = Joinable {
+        future: Box::pin(future),
+        state: state.clone(),
+    };
+    let handle = JoinHandle {
+        state,
+        attached: true,
+    };
+    (task_future, handle)
+}
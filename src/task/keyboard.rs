@@ -12,22 +12,36 @@ use pc_keyboard::{layouts, HandleControl, Keyboard, ScancodeSet1};
 
 use crate::{print, println};
 
+use super::deferred;
+
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 
 static WAKER: AtomicWaker = AtomicWaker::new();
 
+/// Called by the keyboard interrupt handler. Warnings are printed later, by
+/// the worker of [`deferred`] work: a warning that cannot be queued is only
+/// counted.
+#[deny(clippy::disallowed_methods)]
 pub(crate) fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
+            let _dropped = deferred::defer(warn_queue_full, 0);
         } else {
             WAKER.wake();
         }
     } else {
-        println!("WARNING: scancode queue uninitialized");
+        let _dropped = deferred::defer(warn_queue_uninitialized, 0);
     }
 }
 
+fn warn_queue_full(_: u64) {
+    println!("WARNING: scancode queue full; dropping keyboard input");
+}
+
+fn warn_queue_uninitialized(_: u64) {
+    println!("WARNING: scancode queue uninitialized");
+}
+
 /// Concrete implementation of a stream for keyboard scancode queue.
 pub struct ScancodeStream {
     _private: (),
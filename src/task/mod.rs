@@ -1,4 +1,9 @@
 //! A simple task executor.
+//!
+//! Tasks are woken by the keyboard interrupt ([`keyboard`]) and by the timer
+//! interrupt ([`timer`]). Tasks waiting for each other use the primitives of
+//! [`sync`], and interrupt handlers defer their work to a task with
+//! [`deferred`].
 
 use core::{
     future::Future,
@@ -9,8 +14,16 @@ use core::{
 
 use alloc::boxed::Box;
 
+pub mod deferred;
 pub mod executor;
+pub mod join;
 pub mod keyboard;
+pub mod sync;
+pub mod timer;
+
+pub use executor::Spawner;
+pub use join::JoinHandle;
+pub use timer::{interval, sleep, sleep_until, timeout};
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
 struct TaskId(u64);
@@ -22,10 +35,44 @@ impl TaskId {
     }
 }
 
+/// Priority class of a [`Task`].
+///
+/// The executor polls the ready tasks of the highest class first. Lower
+/// classes still run when higher ones keep it busy: see
+/// [`executor::STARVATION_LIMIT`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Work deferred by interrupt handlers, which should run as soon as
+    /// possible.
+    BottomHalf,
+    /// Regular tasks.
+    #[default]
+    Normal,
+    /// Background work, run when nothing else is ready.
+    Idle,
+}
+
+impl Priority {
+    /// Number of priority classes.
+    pub const COUNT: usize = 3;
+
+    /// Every priority class, from the highest to the lowest.
+    pub const ALL: [Self; Self::COUNT] = [Self::BottomHalf, Self::Normal, Self::Idle];
+
+    /// Returns the rank of the class, `0` being the highest.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
 /// Represent a Task that can be asynchronously launched.
 pub struct Task {
     future: Pin<Box<dyn Future<Output = ()>>>,
     id: TaskId,
+    priority: Priority,
+    /// Polls left before the task is throttled, if it keeps waking itself up.
+    budget: u32,
 }
 
 impl Task {
@@ -34,9 +81,36 @@ impl Task {
         Task {
             future: Box::pin(future),
             id: TaskId::new(),
+            priority: Priority::Normal,
+            budget: executor::POLL_BUDGET,
         }
     }
 
+    /// Sets the priority class of the task, [`Priority::Normal`] by default.
+    #[must_use]
+    pub const fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns the priority class of the task.
+    #[must_use]
+    pub const fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Instanciate a new Task, and the handle awaiting the output of
+    /// `future`. Dropping the handle cancels the task.
+    #[must_use = "the task does nothing unless it is spawned"]
+    pub fn joinable<F>(future: F) -> (Self, JoinHandle<F::Output>)
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (task_future, handle) = join::joinable(future);
+        (Self::new(task_future), handle)
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
         self.future.as_mut().poll(context)
     }
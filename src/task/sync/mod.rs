@@ -0,0 +1,51 @@
+//! Synchronization primitives for async tasks.
+//!
+//! Unlike [`spin::Mutex`], these primitives never spin: a task that has to
+//! wait registers its [`Waker`](core::task::Waker) and returns
+//! [`Poll::Pending`](core::task::Poll::Pending), so that the executor runs
+//! other tasks until it is woken up.
+//!
+//! - [`mpsc`]: bounded and unbounded multi-producer, single-consumer
+//!   channels.
+//! - [`oneshot`]: channel carrying a single value.
+//! - [`Mutex`]: mutual exclusion across `.await` points.
+//! - [`Semaphore`]: a fixed number of permits shared by tasks.
+//! - [`Notify`]: wakes tasks waiting for an event.
+//!
+//! Their state is protected by spinlocks, which are only held with
+//! interrupts disabled and never across an `.await`, so that an interrupt
+//! handler can notify a [`Notify`], send on a [`oneshot`] channel or
+//! [`try_send`](mpsc::Sender::try_send) on a bounded channel: none of them
+//! allocates.
+
+use core::task::Waker;
+
+use spin::Mutex as SpinMutex;
+use x86_64::instructions::interrupts;
+
+pub mod mpsc;
+mod mutex;
+mod notify;
+pub mod oneshot;
+mod semaphore;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use semaphore::{Semaphore, SemaphorePermit};
+
+/// Runs `f` on the value protected by `lock`, with interrupts disabled so
+/// that an interrupt handler never finds it locked.
+fn with_locked<T, R, F>(lock: &SpinMutex<T>, f: F) -> R
+where
+    F: FnOnce(&mut T) -> R,
+{
+    interrupts::without_interrupts(|| f(&mut lock.lock()))
+}
+
+/// Stores `waker` in `slot`, reusing the waker already there if any.
+fn register_waker(slot: &mut Option<Waker>, waker: &Waker) {
+    match *slot {
+        Some(ref mut registered) => registered.clone_from(waker),
+        None => *slot = Some(waker.clone()),
+    }
+}
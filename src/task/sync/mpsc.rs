@@ -0,0 +1,364 @@
+//! Multi-producer, single-consumer channels.
+//!
+//! A [bounded](channel) channel holds at most a fixed number of values:
+//! [`Sender::send`] waits while it is full, which slows producers down to the
+//! pace of the receiver. Its buffer is allocated upfront, so
+//! [`Sender::try_send`] never allocates and can be called from an interrupt
+//! handler. An [unbounded](unbounded) channel never waits, but allocates as
+//! it grows.
+//!
+//! The channel is closed once every sender, or the receiver, is dropped.
+
+use core::{
+    fmt,
+    future::{self, Future},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{collections::vec_deque::VecDeque, sync::Arc};
+use futures_util::Stream;
+use spin::Mutex;
+
+use super::{register_waker, with_locked, Notify};
+
+/// State shared by the senders and the receiver of a channel.
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Maximum number of values in the queue, if bounded.
+    capacity: Option<usize>,
+    /// Notified when room is made in the queue, or the receiver is dropped.
+    room: Notify,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    /// Number of senders alive.
+    senders: usize,
+    /// `false` once the receiver is dropped.
+    receiver_alive: bool,
+    /// Waker of the task waiting on the receiver.
+    rx_waker: Option<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn new(capacity: Option<usize>) -> Arc<Self> {
+        let queue = capacity.map_or_else(VecDeque::new, VecDeque::with_capacity);
+        Arc::new(Self {
+            state: Mutex::new(State {
+                queue,
+                senders: 1,
+                receiver_alive: true,
+                rx_waker: None,
+            }),
+            capacity,
+            room: Notify::new(),
+        })
+    }
+
+    /// Runs `f` on the state.
+    fn with_state<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut State<T>) -> R,
+    {
+        with_locked(&self.state, f)
+    }
+
+    /// Queues `value` and wakes the receiver.
+    fn push(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.with_state(|state| {
+            if !state.receiver_alive {
+                return Err(TrySendError::Closed(value));
+            }
+            if self
+                .capacity
+                .is_some_and(|capacity| state.queue.len() >= capacity)
+            {
+                return Err(TrySendError::Full(value));
+            }
+            state.queue.push_back(value);
+            if let Some(waker) = state.rx_waker.as_ref() {
+                waker.wake_by_ref();
+            }
+            Ok(())
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.with_state(|state| !state.receiver_alive)
+    }
+
+    fn add_sender(&self) {
+        self.with_state(|state| state.senders += 1);
+    }
+
+    /// Wakes the receiver if the last sender is dropped, so that it notices
+    /// the channel is closed.
+    fn drop_sender(&self) {
+        self.with_state(|state| {
+            state.senders -= 1;
+            if state.senders == 0 {
+                if let Some(waker) = state.rx_waker.as_ref() {
+                    waker.wake_by_ref();
+                }
+            }
+        });
+    }
+}
+
+/// Creates a channel holding at most `capacity` values.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+#[must_use]
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be non-zero");
+    let shared = Shared::new(Some(capacity));
+    let receiver = Receiver {
+        shared: shared.clone(),
+    };
+    (Sender { shared }, receiver)
+}
+
+/// Creates a channel without a capacity limit.
+#[must_use]
+pub fn unbounded<T>() -> (UnboundedSender<T>, Receiver<T>) {
+    let shared = Shared::new(None);
+    let receiver = Receiver {
+        shared: shared.clone(),
+    };
+    (UnboundedSender { shared }, receiver)
+}
+
+/// Sending half of a bounded channel, created by [`channel`].
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `value`, waiting while the channel is full.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value if the receiver has been dropped.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut pending = value;
+        loop {
+            let room = self.shared.room.notified();
+            match self.shared.push(pending) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Closed(value)) => return Err(SendError(value)),
+                Err(TrySendError::Full(value)) => pending = value,
+            }
+            room.await;
+        }
+    }
+
+    /// Sends `value` if the channel is not full. It neither waits nor
+    /// allocates.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value if the channel is full or the receiver has been
+    /// dropped.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.shared.push(value)
+    }
+
+    /// Returns `true` if the receiver has been dropped.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.shared.is_closed()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.add_sender();
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.drop_sender();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// Sending half of an unbounded channel, created by [`unbounded`].
+pub struct UnboundedSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> UnboundedSender<T> {
+    /// Sends `value` without waiting. The queue grows as needed, so this
+    /// must not be called from an interrupt handler.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value if the receiver has been dropped.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.shared.push(value).map_err(|error| match error {
+            TrySendError::Full(value) | TrySendError::Closed(value) => SendError(value),
+        })
+    }
+
+    /// Returns `true` if the receiver has been dropped.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.shared.is_closed()
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.add_sender();
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        self.shared.drop_sender();
+    }
+}
+
+impl<T> fmt::Debug for UnboundedSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnboundedSender").finish_non_exhaustive()
+    }
+}
+
+/// Receiving half of a channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, waiting until one is sent.
+    ///
+    /// Returns `None` once every sender has been dropped and the queue is
+    /// empty.
+    pub fn recv(&mut self) -> impl Future<Output = Option<T>> + '_ {
+        future::poll_fn(|cx| self.poll_recv(cx))
+    }
+
+    /// Receives the next value if one is queued.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TryRecvError::Empty`] if the queue is empty, and
+    /// [`TryRecvError::Disconnected`] if every sender has also been dropped.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let received = self
+            .shared
+            .with_state(|state| match state.queue.pop_front() {
+                Some(value) => Ok(value),
+                None if state.senders == 0 => Err(TryRecvError::Disconnected),
+                None => Err(TryRecvError::Empty),
+            });
+        if received.is_ok() {
+            self.shared.room.notify_one();
+        }
+        received
+    }
+
+    /// Polls for the next value, registering the waker of the task if none
+    /// is queued.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let received = self.shared.with_state(|state| {
+            if let Some(value) = state.queue.pop_front() {
+                return Poll::Ready(Some(value));
+            }
+            if state.senders == 0 {
+                return Poll::Ready(None);
+            }
+            register_waker(&mut state.rx_waker, cx.waker());
+            Poll::Pending
+        });
+        if let Poll::Ready(Some(_)) = received {
+            self.shared.room.notify_one();
+        }
+        received
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.with_state(|state| state.receiver_alive = false);
+        self.shared.room.notify_waiters();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+/// Error returned by [`Sender::send`] and [`UnboundedSender::send`] when the
+/// receiver has been dropped. It holds the value that could not be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sending on a closed channel")
+    }
+}
+
+/// Error returned by [`Sender::try_send`]. It holds the value that could not
+/// be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is full.
+    Full(T),
+    /// The receiver has been dropped.
+    Closed(T),
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Full(_) => write!(f, "sending on a full channel"),
+            Self::Closed(_) => write!(f, "sending on a closed channel"),
+        }
+    }
+}
+
+/// Error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// No value is queued.
+    Empty,
+    /// No value is queued and every sender has been dropped.
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Empty => write!(f, "receiving on an empty channel"),
+            Self::Disconnected => write!(f, "receiving on a closed channel"),
+        }
+    }
+}
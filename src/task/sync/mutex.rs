@@ -0,0 +1,115 @@
+//! Async mutex.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::{Semaphore, SemaphorePermit};
+
+/// Mutual exclusion lock that can be held across `.await` points.
+///
+/// A task waiting for the lock parks until it is released, instead of
+/// spinning like [`spin::Mutex`]. Holding a [`spin::Mutex`] guard across an
+/// `.await` would deadlock the executor as soon as another task tries to
+/// lock it.
+pub struct Mutex<T: ?Sized> {
+    semaphore: Semaphore,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The mutex hands the value over to the task holding the lock, so it
+// can be sent to another thread along with the mutex.
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+
+// SAFETY: The value is only accessed through a guard, and only one guard can
+// exist at a time: the semaphore has a single permit.
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Creates an unlocked mutex protecting `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            semaphore: Semaphore::new(1),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consumes the mutex, returning the protected value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Locks the mutex, waiting until it is released.
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        let permit = self.semaphore.acquire().await;
+        MutexGuard {
+            mutex: self,
+            _permit: permit,
+        }
+    }
+
+    /// Locks the mutex if it is not locked already.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire()?;
+        Some(MutexGuard {
+            mutex: self,
+            _permit: permit,
+        })
+    }
+
+    /// Returns a mutable reference to the protected value. No locking is
+    /// needed since the mutex is borrowed mutably.
+    pub const fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex")
+            .field("locked", &(self.semaphore.available_permits() == 0))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Guard of a locked [`Mutex`], releasing it when dropped.
+#[must_use = "the mutex is released as soon as the guard is dropped"]
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    /// The single permit of the mutex, given back when the guard is dropped.
+    _permit: SemaphorePermit<'a>,
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The guard holds the only permit of the mutex, so no mutable
+        // reference to the value exists elsewhere.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The guard holds the only permit of the mutex, and is borrowed
+        // mutably, so no other reference to the value exists.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,195 @@
+//! Notification of waiting tasks.
+
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::collections::btree_map::BTreeMap;
+use spin::Mutex;
+
+use super::with_locked;
+
+/// Wakes tasks waiting for an event.
+///
+/// [`notify_one`](Self::notify_one) wakes the task that has been waiting the
+/// longest or, if none is waiting, stores a permit so that the next
+/// [`notified`](Self::notified) completes immediately. This way, a
+/// notification sent between checking a condition and waiting for it is not
+/// lost. [`notify_waiters`](Self::notify_waiters) wakes every waiting task
+/// and stores no permit.
+///
+/// Notifying neither allocates nor blocks, so it can be done from an
+/// interrupt handler.
+#[derive(Debug, Default)]
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+/// State of a [`Notify`].
+#[derive(Debug, Default)]
+struct State {
+    /// `true` if [`Notify::notify_one`] was called with no task waiting.
+    permit: bool,
+    /// Waiting tasks, in the order they started waiting.
+    waiters: BTreeMap<WaiterId, Waiter>,
+}
+
+/// Identifier of a [`Notified`] future, increasing in creation order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct WaiterId(u64);
+
+impl WaiterId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A task waiting on a [`Notify`].
+#[derive(Debug)]
+struct Waiter {
+    waker: Waker,
+    /// How the task was notified, if it was.
+    notified: Option<Notification>,
+}
+
+/// Notification method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Notification {
+    /// By [`Notify::notify_one`], which is passed on if the task stops
+    /// waiting before it sees it.
+    One,
+    /// By [`Notify::notify_waiters`].
+    All,
+}
+
+impl Notify {
+    /// Creates a `Notify` without a stored permit.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                permit: false,
+                waiters: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Returns a future completing once the `Notify` is notified.
+    ///
+    /// The future takes the stored permit, if any, when it is first polled.
+    pub const fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            id: None,
+        }
+    }
+
+    /// Wakes the task that has been waiting the longest, or stores a permit
+    /// if none is waiting.
+    pub fn notify_one(&self) {
+        self.with_state(|state| {
+            let oldest = state
+                .waiters
+                .values_mut()
+                .find(|waiter| waiter.notified.is_none());
+            match oldest {
+                Some(waiter) => {
+                    waiter.notified = Some(Notification::One);
+                    waiter.waker.wake_by_ref();
+                }
+                None => state.permit = true,
+            }
+        });
+    }
+
+    /// Wakes every waiting task. No permit is stored.
+    pub fn notify_waiters(&self) {
+        self.with_state(|state| {
+            for waiter in state.waiters.values_mut() {
+                if waiter.notified.is_none() {
+                    waiter.notified = Some(Notification::All);
+                    waiter.waker.wake_by_ref();
+                }
+            }
+        });
+    }
+
+    /// Runs `f` on the state.
+    fn with_state<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut State) -> R,
+    {
+        with_locked(&self.state, f)
+    }
+}
+
+/// Future returned by [`Notify::notified`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    /// Identifier of the future once it is waiting.
+    id: Option<WaiterId>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let notify = this.notify;
+        let id = &mut this.id;
+        let ready = notify.with_state(|state| {
+            let Some(waiter_id) = *id else {
+                if mem::take(&mut state.permit) {
+                    return true;
+                }
+                let waiter_id = WaiterId::new();
+                let waiter = Waiter {
+                    waker: cx.waker().clone(),
+                    notified: None,
+                };
+                state.waiters.insert(waiter_id, waiter);
+                *id = Some(waiter_id);
+                return false;
+            };
+
+            match state.waiters.get_mut(&waiter_id) {
+                Some(waiter) if waiter.notified.is_none() => {
+                    waiter.waker.clone_from(cx.waker());
+                    false
+                }
+                Some(_) | None => {
+                    state.waiters.remove(&waiter_id);
+                    *id = None;
+                    true
+                }
+            }
+        });
+
+        if ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.id else {
+            return;
+        };
+        let removed = self.notify.with_state(|state| state.waiters.remove(&id));
+        // A task notified by `notify_one` that stops waiting passes the
+        // notification on, so that it is not lost.
+        if removed.is_some_and(|waiter| waiter.notified == Some(Notification::One)) {
+            self.notify.notify_one();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Channel carrying a single value.
+//!
+//! Sending neither waits nor allocates, so an interrupt handler can complete
+//! a request made by a task.
+
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use alloc::sync::Arc;
+use spin::Mutex;
+
+use super::{register_waker, with_locked};
+
+/// State shared by the sender and the receiver.
+struct State<T> {
+    value: Option<T>,
+    /// `false` once the sender is consumed or dropped.
+    sender_alive: bool,
+    /// `false` once the receiver is dropped.
+    receiver_alive: bool,
+    /// Waker of the task awaiting the receiver.
+    rx_waker: Option<Waker>,
+}
+
+/// Creates a channel carrying a single value.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let state = Arc::new(Mutex::new(State {
+        value: None,
+        sender_alive: true,
+        receiver_alive: true,
+        rx_waker: None,
+    }));
+    let receiver = Receiver {
+        state: state.clone(),
+    };
+    (Sender { state }, receiver)
+}
+
+/// Sending half of a oneshot channel.
+pub struct Sender<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `value` and wakes the receiver.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value if the receiver has been dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        with_locked(&self.state, |state| {
+            if !state.receiver_alive {
+                return Err(value);
+            }
+            state.value = Some(value);
+            Ok(())
+        })
+        // Dropping the sender wakes the receiver.
+    }
+
+    /// Returns `true` if the receiver has been dropped.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        with_locked(&self.state, |state| !state.receiver_alive)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        with_locked(&self.state, |state| {
+            state.sender_alive = false;
+            if let Some(waker) = state.rx_waker.as_ref() {
+                waker.wake_by_ref();
+            }
+        });
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+/// Receiving half of a oneshot channel: a future resolving to the value.
+#[must_use = "futures do nothing unless awaited"]
+pub struct Receiver<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives the value if it has been sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TryRecvError::Empty`] if the value has not been sent yet,
+    /// and [`TryRecvError::Closed`] if the sender was dropped without sending
+    /// it, or the value was already received.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        with_locked(&self.state, |state| match state.value.take() {
+            Some(value) => Ok(value),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Closed),
+        })
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        with_locked(&self.state, |state| {
+            if let Some(value) = state.value.take() {
+                return Poll::Ready(Ok(value));
+            }
+            if !state.sender_alive {
+                return Poll::Ready(Err(RecvError));
+            }
+            register_waker(&mut state.rx_waker, cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        with_locked(&self.state, |state| state.receiver_alive = false);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+/// Error returned by the [`Receiver`] future when the sender was dropped
+/// without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sender dropped without sending")
+    }
+}
+
+/// Error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The value has not been sent yet.
+    Empty,
+    /// The sender was dropped without sending a value, or the value was
+    /// already received.
+    Closed,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Empty => write!(f, "value not sent yet"),
+            Self::Closed => write!(f, "sender dropped without sending"),
+        }
+    }
+}
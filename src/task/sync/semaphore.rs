@@ -0,0 +1,77 @@
+//! Counting semaphore.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::Notify;
+
+/// A number of permits shared by tasks.
+///
+/// [`acquire`](Self::acquire) waits until a permit is available, and the
+/// returned [`SemaphorePermit`] gives it back when dropped. Waiting tasks are
+/// woken in the order they started waiting, but a task acquiring a permit
+/// without waiting may take it first.
+#[derive(Debug)]
+pub struct Semaphore {
+    permits: AtomicUsize,
+    /// Notified each time a permit is given back.
+    released: Notify,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with `permits` permits available.
+    #[must_use]
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            released: Notify::new(),
+        }
+    }
+
+    /// Returns the number of permits currently available.
+    #[must_use]
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Acquire)
+    }
+
+    /// Acquires a permit, waiting until one is available.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        loop {
+            let released = self.released.notified();
+            if let Some(permit) = self.try_acquire() {
+                return permit;
+            }
+            released.await;
+        }
+    }
+
+    /// Acquires a permit if one is available right away.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |permits| {
+                permits.checked_sub(1)
+            })
+            .ok()
+            .map(|_| SemaphorePermit { semaphore: self })
+    }
+
+    /// Adds `count` permits, waking up to `count` waiting tasks.
+    pub fn add_permits(&self, count: usize) {
+        self.permits.fetch_add(count, Ordering::Release);
+        for _ in 0..count {
+            self.released.notify_one();
+        }
+    }
+}
+
+/// A permit acquired from a [`Semaphore`], given back when dropped.
+#[derive(Debug)]
+#[must_use = "the permit is given back as soon as it is dropped"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(1);
+    }
+}
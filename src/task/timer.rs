@@ -0,0 +1,277 @@
+//! Timers for async tasks.
+//!
+//! Pending timers are kept in a queue sorted by deadline. Every timer
+//! interrupt calls [`wake_expired`], which wakes the tasks whose deadline has
+//! passed; the task then completes its timer future the next time it is
+//! polled. The resolution is therefore one timer tick (see
+//! [`time::tick_period`]).
+//!
+//! - [`sleep`] and [`sleep_until`] complete once a deadline has passed.
+//! - [`interval`] yields periodically, for background work such as a blinking
+//!   cursor.
+//! - [`timeout`] cancels a future that takes too long.
+//!
+//! The queue is only locked with interrupts disabled, so that the interrupt
+//! handler never finds it locked. The handler only wakes tasks: entries are
+//! inserted and removed, which may allocate, by the futures themselves.
+
+use core::{
+    fmt,
+    future::{self, Future},
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use alloc::{boxed::Box, collections::btree_map::BTreeMap};
+use futures_util::Stream;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::time::{self, Instant};
+
+/// Pending timers, sorted by deadline, then by registration order.
+static TIMERS: Mutex<BTreeMap<(Instant, TimerId), Timer>> = Mutex::new(BTreeMap::new());
+
+/// Unique identifier of a timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TimerId(u64);
+
+impl TimerId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A pending timer.
+struct Timer {
+    /// Waker of the task waiting for the deadline.
+    waker: Waker,
+    /// `true` once the task has been woken.
+    fired: bool,
+}
+
+/// Wakes the tasks whose deadline is at or before `now`.
+///
+/// Called by the timer interrupt handler. The wakers must neither allocate
+/// nor block, which holds for the wakers of the kernel executor.
+pub(crate) fn wake_expired(now: Instant) {
+    let Some(mut timers) = TIMERS.try_lock() else {
+        return;
+    };
+    for (_, timer) in timers
+        .iter_mut()
+        .take_while(|&(&(deadline, _), _)| deadline <= now)
+    {
+        if !timer.fired {
+            timer.fired = true;
+            timer.waker.wake_by_ref();
+        }
+    }
+}
+
+/// Returns the number of pending timers.
+#[must_use]
+pub fn pending() -> usize {
+    interrupts::without_interrupts(|| TIMERS.lock().len())
+}
+
+/// Future completing once its deadline has passed, returned by [`sleep`] and
+/// [`sleep_until`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Sleep {
+    deadline: Instant,
+    id: TimerId,
+    /// `true` while the timer is in the queue.
+    registered: bool,
+}
+
+impl Sleep {
+    /// Returns the instant at which the future completes.
+    #[must_use]
+    pub const fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Removes the timer from the queue, if it is there.
+    fn cancel(&mut self) {
+        if self.registered {
+            self.registered = false;
+            interrupts::without_interrupts(|| {
+                TIMERS.lock().remove(&(self.deadline, self.id));
+            });
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if time::now() >= this.deadline {
+            this.cancel();
+            return Poll::Ready(());
+        }
+
+        let key = (this.deadline, this.id);
+        interrupts::without_interrupts(|| {
+            let mut timers = TIMERS.lock();
+            match timers.get_mut(&key) {
+                Some(timer) if timer.waker.will_wake(cx.waker()) => {}
+                Some(timer) => {
+                    timer.waker.clone_from(cx.waker());
+                    timer.fired = false;
+                }
+                None => {
+                    timers.insert(
+                        key,
+                        Timer {
+                            waker: cx.waker().clone(),
+                            fired: false,
+                        },
+                    );
+                }
+            }
+        });
+        this.registered = true;
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Returns a future completing once `duration` has elapsed.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(time::now() + duration)
+}
+
+/// Returns a future completing once the monotonic clock reaches `deadline`.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        id: TimerId::new(),
+        registered: false,
+    }
+}
+
+/// Periodic timer, returned by [`interval`].
+///
+/// It ticks every period, starting one period after its creation. Ticks
+/// missed because the task was busy are skipped rather than delivered in a
+/// burst.
+#[derive(Debug)]
+#[must_use = "intervals do nothing unless polled"]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Interval {
+    /// Returns the period of the interval.
+    #[must_use]
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Waits for the next tick and returns its scheduled instant.
+    pub async fn tick(&mut self) -> Instant {
+        future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    /// Polls for the next tick, returning its scheduled instant.
+    #[expect(
+        clippy::integer_division,
+        reason = "Only whole missed periods are skipped."
+    )]
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let scheduled = self.sleep.deadline;
+        let now = time::now();
+        let mut next = scheduled + self.period;
+        if next <= now {
+            // Skip the missed ticks: keep the phase, but not the backlog.
+            let period = self.period.as_nanos().max(1);
+            let late = now.saturating_duration_since(scheduled).as_nanos();
+            let missed = u32::try_from(late / period).unwrap_or(u32::MAX);
+            next = scheduled + self.period.saturating_mul(missed.saturating_add(1));
+        }
+        self.sleep = sleep_until(next);
+        Poll::Ready(scheduled)
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Instant>> {
+        self.get_mut().poll_tick(cx).map(Some)
+    }
+}
+
+/// Returns a timer ticking every `period`, starting one period from now.
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    assert!(
+        !period.is_zero(),
+        "The period of an interval cannot be zero."
+    );
+    Interval {
+        period,
+        sleep: sleep(period),
+    }
+}
+
+/// Error returned by [`timeout`] when the deadline passes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline has elapsed")
+    }
+}
+
+/// Future returned by [`timeout`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct Timeout<F: Future> {
+    future: Pin<Box<F>>,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Poll::Ready(output) = this.future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut this.sleep).poll(cx).map(|()| Err(Elapsed))
+    }
+}
+
+/// Runs `future`, giving up if it has not completed after `duration`.
+///
+/// The future is polled first, so one that is already ready completes even
+/// with a zero `duration`. The returned future resolves to [`Elapsed`] if
+/// `duration` elapses first; `future` is then dropped without completing.
+pub fn timeout<F: Future>(future: F, duration: Duration) -> Timeout<F> {
+    Timeout {
+        future: Box::pin(future),
+        sleep: sleep(duration),
+    }
+}
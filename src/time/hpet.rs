@@ -0,0 +1,196 @@
+//! Driver of the High Precision Event Timer (HPET).
+//!
+//! The HPET is described by the ACPI tables. The root system description
+//! pointer (RSDP), found in the BIOS read-only area, points to the root table
+//! (RSDT, or XSDT from ACPI 2.0 on), which lists the other tables. The `HPET`
+//! table holds the physical address of the timer registers.
+//!
+//! Only the main counter is used, as a clock source: it counts up at a fixed
+//! period given in femtoseconds by the capabilities register. The timer
+//! interrupt is still raised by the [PIT](super::pit).
+
+use core::{ops::Range, ptr};
+
+use x86_64::{structures::paging::PhysFrame, PhysAddr, VirtAddr};
+
+use crate::memory;
+
+/// Physical addresses searched for the RSDP.
+const BIOS_AREA: Range<u64> = 0xE_0000..0x10_0000;
+
+/// Signature of the RSDP.
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+/// Signature of the HPET description table.
+const HPET_SIGNATURE: &[u8; 4] = b"HPET";
+
+/// Size of the header shared by the ACPI description tables.
+const SDT_HEADER_SIZE: u64 = 36;
+
+/// Offset of the register base address in the HPET description table.
+const HPET_TABLE_ADDRESS: u64 = 44;
+
+/// General capabilities and ID register.
+const CAPABILITIES: u64 = 0x000;
+
+/// General configuration register.
+const CONFIGURATION: u64 = 0x010;
+
+/// Main counter value register.
+const MAIN_COUNTER: u64 = 0x0F0;
+
+/// Set in [`CAPABILITIES`] if the main counter is 64 bits wide.
+const COUNTER_64_BIT: u64 = 1 << 13;
+
+/// Set in [`CONFIGURATION`] to make the main counter run.
+const ENABLE: u64 = 1;
+
+/// Longest counter period allowed by the specification, in femtoseconds.
+const MAX_PERIOD_FS: u64 = 100_000_000;
+
+/// Femtoseconds in a nanosecond.
+const FS_PER_NS: u128 = 1_000_000;
+
+/// An enabled HPET whose main counter is running.
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    /// Virtual address of the registers.
+    registers: VirtAddr,
+    /// Period of the main counter, in femtoseconds.
+    period_fs: u64,
+}
+
+impl Hpet {
+    /// Finds the HPET described by the ACPI tables and starts its main
+    /// counter.
+    ///
+    /// Returns `None` if there are no ACPI tables, if they describe no HPET,
+    /// or if its main counter is not 64 bits wide.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frame allocator is not initialized, since it may be
+    /// needed to map the registers.
+    #[must_use]
+    pub fn find() -> Option<Self> {
+        let base = PhysAddr::try_new(find_table(*HPET_SIGNATURE)? + HPET_TABLE_ADDRESS).ok()?;
+        let registers_phys = PhysAddr::try_new(read_phys::<u64>(base)?).ok()?;
+        let registers = memory::map_mmio(PhysFrame::from_start_address(registers_phys).ok()?)?;
+
+        let mut hpet = Self {
+            registers,
+            period_fs: 0,
+        };
+        let capabilities = hpet.read(CAPABILITIES);
+        hpet.period_fs = capabilities >> 32;
+        if capabilities & COUNTER_64_BIT == 0 || !(1..=MAX_PERIOD_FS).contains(&hpet.period_fs) {
+            return None;
+        }
+
+        let configuration = hpet.read(CONFIGURATION);
+        hpet.write(CONFIGURATION, configuration | ENABLE);
+        Some(hpet)
+    }
+
+    /// Returns the current value of the main counter.
+    #[must_use]
+    pub fn counter(&self) -> u64 {
+        self.read(MAIN_COUNTER)
+    }
+
+    /// Returns the period of the main counter, in femtoseconds.
+    #[must_use]
+    pub const fn period_fs(&self) -> u64 {
+        self.period_fs
+    }
+
+    /// Converts a number of counter periods to nanoseconds.
+    #[must_use]
+    #[expect(
+        clippy::integer_division,
+        reason = "Partial nanoseconds are dropped on purpose."
+    )]
+    pub fn periods_to_nanos(&self, periods: u64) -> u64 {
+        let nanos = u128::from(periods) * u128::from(self.period_fs) / FS_PER_NS;
+        u64::try_from(nanos).unwrap_or(u64::MAX)
+    }
+
+    /// Reads the register at `offset`.
+    fn read(&self, offset: u64) -> u64 {
+        // SAFETY:
+        //
+        // The registers were mapped by `find` and `offset` is one of the
+        // register offsets defined by the specification.
+        unsafe { ptr::read_volatile((self.registers + offset).as_ptr::<u64>()) }
+    }
+
+    /// Writes `value` to the register at `offset`.
+    fn write(&mut self, offset: u64, value: u64) {
+        // SAFETY:
+        //
+        // See `read`.
+        unsafe {
+            ptr::write_volatile((self.registers + offset).as_mut_ptr::<u64>(), value);
+        }
+    }
+}
+
+/// Reads a `T` at the physical address `addr`.
+///
+/// Returns `None` if `addr` is past the physical memory mapping.
+fn read_phys<T: Copy>(addr: PhysAddr) -> Option<T> {
+    let virt = memory::phys_to_virt(addr);
+    memory::is_mapped(virt).then(|| {
+        // SAFETY:
+        //
+        // The address is mapped and only read; ACPI tables and the BIOS area
+        // may hold any bit pattern for the integers and byte arrays read here.
+        unsafe { ptr::read_unaligned(virt.as_ptr::<T>()) }
+    })
+}
+
+/// Returns the physical address of the RSDP, if the BIOS provides one.
+fn find_rsdp() -> Option<u64> {
+    BIOS_AREA.step_by(16).find(|&addr| {
+        let Some(header) = read_phys::<[u8; 20]>(PhysAddr::new(addr)) else {
+            return false;
+        };
+        header.starts_with(RSDP_SIGNATURE)
+            && header
+                .iter()
+                .fold(0_u8, |sum, &byte| sum.wrapping_add(byte))
+                == 0
+    })
+}
+
+/// Returns the physical address of the ACPI description table whose
+/// signature is `signature`, if any.
+fn find_table(signature: [u8; 4]) -> Option<u64> {
+    let rsdp = find_rsdp()?;
+    let revision = read_phys::<u8>(PhysAddr::new(rsdp + 15))?;
+    let (root, entry_size) = if revision >= 2 {
+        (read_phys::<u64>(PhysAddr::new(rsdp + 24))?, 8)
+    } else {
+        (u64::from(read_phys::<u32>(PhysAddr::new(rsdp + 16))?), 4)
+    };
+
+    let root_addr = PhysAddr::try_new(root).ok()?;
+    let length = u64::from(read_phys::<u32>(root_addr + 4_u64)?);
+    (root + SDT_HEADER_SIZE..root + length)
+        .step_by(entry_size)
+        .filter_map(|entry| {
+            let entry_addr = PhysAddr::try_new(entry).ok()?;
+            let table = if entry_size == 8 {
+                read_phys::<u64>(entry_addr)?
+            } else {
+                u64::from(read_phys::<u32>(entry_addr)?)
+            };
+            Some(table)
+        })
+        .find(|&table| {
+            PhysAddr::try_new(table)
+                .ok()
+                .and_then(read_phys::<[u8; 4]>)
+                .is_some_and(|found| found == signature)
+        })
+}
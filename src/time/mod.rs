@@ -0,0 +1,293 @@
+//! Monotonic clock and timer interrupt rate.
+//!
+//! The timer interrupt is raised by channel 0 of the [PIT](pit), which
+//! [`init`] programs to fire [`DEFAULT_FREQUENCY`] times per second;
+//! [`set_frequency`] changes the rate later on. Every interrupt calls
+//! [`tick`], which counts the ticks and the cycles of the PIT input clock they
+//! cover. The monotonic clock is derived from these cycles, with a resolution
+//! of one tick.
+//!
+//! When the ACPI tables describe an [HPET](hpet), [`init_hpet`] starts its
+//! main counter and the clock reads it instead, with a resolution of a few
+//! tens of nanoseconds. The clock does not jump when switching: it resumes
+//! from the time counted by the PIT when the HPET was enabled.
+//!
+//! [`now`] returns an [`Instant`] of the monotonic clock and [`uptime`] the
+//! time elapsed since the clock started. User programs read the clock with
+//! `sys_clock_gettime`.
+//!
+//! [`init`] also reads the date and time from the [RTC](rtc) once, which
+//! dates the start of the monotonic clock: [`wall_time`] adds the uptime to
+//! this [`boot_time`] to give the UTC time without reading the RTC again. User
+//! programs read it with `sys_time`.
+
+use core::{
+    fmt,
+    ops::Add,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use spin::Once;
+
+pub mod hpet;
+pub mod pit;
+pub mod rtc;
+
+use hpet::Hpet;
+
+/// Default rate of the timer interrupt, in Hz.
+pub const DEFAULT_FREQUENCY: u32 = 100;
+
+/// Nanoseconds in a second.
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Number of timer interrupts since the clock started.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of cycles of the PIT input clock covered by [`TICKS`].
+static PIT_CYCLES: AtomicU64 = AtomicU64::new(0);
+
+/// Divisor currently programmed in the PIT.
+static DIVISOR: AtomicU32 = AtomicU32::new(pit::MAX_DIVISOR);
+
+/// Time since the Unix epoch at which the monotonic clock started, in
+/// nanoseconds.
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// The HPET, once [`init_hpet`] has enabled it.
+static HPET_CLOCK: Once<HpetClock> = Once::new();
+
+/// The HPET used as clock source.
+struct HpetClock {
+    hpet: Hpet,
+    /// Value of the main counter when the HPET became the clock source.
+    start_counter: u64,
+    /// Time of the clock when the HPET became the clock source.
+    start: Instant,
+}
+
+/// A point in time of the monotonic clock, counted in nanoseconds since the
+/// clock started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// The time at which the clock started.
+    pub const ZERO: Self = Self(0);
+
+    /// Returns the instant `nanos` nanoseconds after the clock started.
+    #[must_use]
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self(nanos)
+    }
+
+    /// Returns the number of nanoseconds between the start of the clock and
+    /// this instant.
+    #[must_use]
+    pub const fn as_nanos(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the time elapsed from `earlier` to this instant, or zero if
+    /// `earlier` is later.
+    #[must_use]
+    pub const fn saturating_duration_since(self, earlier: Self) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Returns the time elapsed since this instant.
+    #[must_use]
+    pub fn elapsed(self) -> Duration {
+        now().saturating_duration_since(self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    /// Returns the instant `duration` after this one, saturating at the
+    /// latest representable instant.
+    fn add(self, duration: Duration) -> Self {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        Self(self.0.saturating_add(nanos))
+    }
+}
+
+impl fmt::Display for Instant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = Duration::from_nanos(self.0);
+        write!(f, "{}.{:06}", time.as_secs(), time.subsec_micros())
+    }
+}
+
+/// Programs the PIT to raise the timer interrupt [`DEFAULT_FREQUENCY`]
+/// times per second, and reads the boot time from the RTC.
+pub fn init() {
+    set_frequency(DEFAULT_FREQUENCY);
+
+    let rtc_time = Duration::from_secs(rtc::read().unix_timestamp());
+    let boot_time = rtc_time.saturating_sub(uptime());
+    BOOT_TIME.store(
+        u64::try_from(boot_time.as_nanos()).unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+}
+
+/// Makes the HPET the source of the monotonic clock, if the ACPI tables
+/// describe one. Returns `true` if the HPET is used.
+///
+/// # Panics
+///
+/// Panics if [`memory::init_frame_allocator`](crate::memory::init_frame_allocator)
+/// has not been called, since the HPET registers may have to be mapped.
+pub fn init_hpet() -> bool {
+    if HPET_CLOCK.r#try().is_some() {
+        return true;
+    }
+    let Some(hpet) = Hpet::find() else {
+        return false;
+    };
+    HPET_CLOCK.call_once(|| HpetClock {
+        start: now(),
+        start_counter: hpet.counter(),
+        hpet,
+    });
+    true
+}
+
+/// Returns `true` if the monotonic clock reads the HPET.
+#[must_use]
+pub fn uses_hpet() -> bool {
+    HPET_CLOCK.r#try().is_some()
+}
+
+/// Sets the rate of the timer interrupt to the frequency closest to
+/// `frequency` Hz that the PIT can produce (see [`pit::divisor_for`]).
+///
+/// Without an HPET, the clock may be off by up to one tick after the change,
+/// since the tick in progress is accounted at the new rate.
+pub fn set_frequency(frequency: u32) {
+    let divisor = pit::divisor_for(frequency);
+    DIVISOR.store(divisor, Ordering::Relaxed);
+    pit::set_divisor(divisor);
+}
+
+/// Returns the rate of the timer interrupt, in Hz, rounded to the nearest
+/// integer.
+#[must_use]
+#[expect(
+    clippy::integer_division,
+    reason = "The frequency is rounded to the nearest integer on purpose."
+)]
+pub fn frequency() -> u32 {
+    let divisor = u64::from(DIVISOR.load(Ordering::Relaxed));
+    u32::try_from((pit::BASE_FREQUENCY + divisor / 2) / divisor).unwrap_or(u32::MAX)
+}
+
+/// Returns the time between two timer interrupts.
+#[must_use]
+pub fn tick_period() -> Duration {
+    cycles_to_duration(u64::from(DIVISOR.load(Ordering::Relaxed)))
+}
+
+/// Returns the number of timer interrupts since the clock started.
+#[must_use]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Accounts for one timer interrupt.
+///
+/// Called by the timer interrupt handler, and only by it.
+pub(crate) fn tick() {
+    PIT_CYCLES.fetch_add(
+        u64::from(DIVISOR.load(Ordering::Relaxed)),
+        Ordering::Relaxed,
+    );
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the current time of the monotonic clock.
+#[must_use]
+pub fn now() -> Instant {
+    if let Some(clock) = HPET_CLOCK.r#try() {
+        let periods = clock.hpet.counter().wrapping_sub(clock.start_counter);
+        return clock.start + Duration::from_nanos(clock.hpet.periods_to_nanos(periods));
+    }
+
+    let elapsed = cycles_to_duration(PIT_CYCLES.load(Ordering::Relaxed));
+    Instant::ZERO + elapsed
+}
+
+/// Returns the time elapsed since the clock started.
+#[must_use]
+pub fn uptime() -> Duration {
+    now().saturating_duration_since(Instant::ZERO)
+}
+
+/// Returns the time since the Unix epoch at which the monotonic clock
+/// started, to the second of the RTC.
+#[must_use]
+pub fn boot_time() -> Duration {
+    Duration::from_nanos(BOOT_TIME.load(Ordering::Relaxed))
+}
+
+/// Returns the current UTC time, as the time since the Unix epoch.
+#[must_use]
+pub fn wall_time() -> Duration {
+    boot_time().saturating_add(uptime())
+}
+
+/// Converts a number of cycles of the PIT input clock to a duration.
+#[expect(
+    clippy::integer_division,
+    reason = "Partial nanoseconds are dropped on purpose."
+)]
+fn cycles_to_duration(cycles: u64) -> Duration {
+    let nanos = u128::from(cycles) * NANOS_PER_SECOND / u128::from(pit::BASE_FREQUENCY);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_instants_add_durations_and_saturate() {
+        let start = Instant::from_nanos(1_000);
+        let later = start + Duration::from_micros(2);
+        assert_eq!(later.as_nanos(), 3_000, "2 µs after 1 µs.");
+        assert_eq!(
+            later.saturating_duration_since(start),
+            Duration::from_micros(2),
+            "Elapsed time between the instants.",
+        );
+        assert_eq!(
+            start.saturating_duration_since(later),
+            Duration::ZERO,
+            "An earlier instant gives no elapsed time.",
+        );
+        assert_eq!(
+            start + Duration::MAX,
+            Instant::from_nanos(u64::MAX),
+            "Adding too much saturates.",
+        );
+    }
+
+    #[test_case]
+    fn test_pit_cycles_convert_to_time() {
+        assert_eq!(
+            cycles_to_duration(pit::BASE_FREQUENCY),
+            Duration::from_secs(1),
+            "The PIT input clock runs at its base frequency.",
+        );
+    }
+
+    #[test_case]
+    fn test_clock_is_monotonic() {
+        let first = now();
+        let second = now();
+        assert!(second >= first, "The clock never goes backwards.");
+    }
+}
@@ -0,0 +1,78 @@
+//! Driver of the 8253/8254 programmable interval timer (PIT).
+//!
+//! Channel 0 of the PIT is wired to IRQ 0 and raises the timer interrupt. It
+//! counts down from a divisor at [`BASE_FREQUENCY`] and fires every time the
+//! count reaches zero, so the interrupt rate is `BASE_FREQUENCY / divisor`.
+//! Until it is reprogrammed, the divisor is [`MAX_DIVISOR`], about 18.2 Hz.
+
+use x86_64::instructions::{interrupts, port::Port};
+
+/// Frequency of the oscillator driving the PIT, in Hz.
+pub const BASE_FREQUENCY: u64 = 1_193_182;
+
+/// Largest divisor, the one programmed at power-on. It is written as `0`.
+pub const MAX_DIVISOR: u32 = 65_536;
+
+/// Data port of channel 0.
+const CHANNEL_0_PORT: u16 = 0x40;
+
+/// Mode/command register.
+const COMMAND_PORT: u16 = 0x43;
+
+/// Selects channel 0, low byte then high byte access, mode 2 (rate
+/// generator) and binary counting.
+const CHANNEL_0_RATE_GENERATOR: u8 = 0b0011_0100;
+
+/// Returns the divisor making the PIT fire as close as possible to
+/// `frequency` times per second.
+///
+/// Frequencies the PIT cannot reach are clamped: `0` and anything below
+/// 19 Hz give [`MAX_DIVISOR`], anything above [`BASE_FREQUENCY`] gives `1`.
+#[must_use]
+#[expect(
+    clippy::integer_division,
+    reason = "The divisor is rounded to the nearest integer on purpose."
+)]
+pub fn divisor_for(frequency: u32) -> u32 {
+    let hz = u64::from(frequency.max(1));
+    let divisor = (BASE_FREQUENCY + hz / 2) / hz;
+    u32::try_from(divisor.clamp(1, u64::from(MAX_DIVISOR))).unwrap_or(MAX_DIVISOR)
+}
+
+/// Programs channel 0 to fire every `divisor` cycles of the input clock.
+///
+/// `divisor` is clamped to `1..=MAX_DIVISOR`.
+pub fn set_divisor(divisor: u32) {
+    let reload = u16::try_from(divisor.clamp(1, MAX_DIVISOR)).unwrap_or(0);
+    let [low, high] = reload.to_le_bytes();
+
+    let mut command = Port::<u8>::new(COMMAND_PORT);
+    let mut channel_0 = Port::<u8>::new(CHANNEL_0_PORT);
+
+    // The two bytes of the reload value must not be interleaved with another
+    // access to the PIT.
+    interrupts::without_interrupts(|| {
+        // SAFETY:
+        //
+        // The ports belong to the PIT, which only drives the timer interrupt.
+        // The command selects the access mode used by the two writes below.
+        unsafe {
+            command.write(CHANNEL_0_RATE_GENERATOR);
+            channel_0.write(low);
+            channel_0.write(high);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_divisor_is_rounded_and_clamped() {
+        assert_eq!(divisor_for(100), 11_932, "100 Hz.");
+        assert_eq!(divisor_for(1000), 1193, "1 kHz.");
+        assert_eq!(divisor_for(0), MAX_DIVISOR, "Too slow for the PIT.");
+        assert_eq!(divisor_for(u32::MAX), 1, "Too fast for the PIT.");
+    }
+}
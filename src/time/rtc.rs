@@ -0,0 +1,325 @@
+//! Driver of the CMOS real-time clock (RTC).
+//!
+//! The RTC keeps the date and time while the machine is off. Its registers
+//! are read by writing their index to port `0x70` and reading port `0x71`.
+//! Depending on status register B, the values are BCD or binary and the hour
+//! is in 12 or 24-hour format. The RTC holds no century: years are taken to be
+//! in the 21st century.
+//!
+//! The RTC updates its registers once per second. A read racing with an update
+//! may mix the old and new time, so [`read`] waits for the update-in-progress
+//! flag to clear and reads the registers until two reads in a row agree.
+//!
+//! The time is assumed to be UTC, as with QEMU's default `-rtc base=utc`.
+
+use core::{fmt, hint};
+
+use x86_64::instructions::{interrupts, port::Port};
+
+/// Index port, which selects the register accessed through [`DATA_PORT`].
+const INDEX_PORT: u16 = 0x70;
+
+/// Data port of the selected register.
+const DATA_PORT: u16 = 0x71;
+
+/// Register indexes of the time and date, in the order of [`Registers`].
+const TIME_REGISTERS: [u8; 6] = [0x00, 0x02, 0x04, 0x07, 0x08, 0x09];
+
+/// Status register A.
+const STATUS_A: u8 = 0x0A;
+
+/// Status register B.
+const STATUS_B: u8 = 0x0B;
+
+/// Set in [`STATUS_A`] while the RTC updates its registers.
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+
+/// Set in [`STATUS_B`] if the hour is in 24-hour format.
+const HOUR_24: u8 = 1 << 1;
+
+/// Set in [`STATUS_B`] if the values are binary rather than BCD.
+const BINARY: u8 = 1 << 2;
+
+/// Set in the hour register, in 12-hour format, for hours after noon.
+const PM: u8 = 1 << 7;
+
+/// First year of the century the two-digit RTC year belongs to.
+const CENTURY: u16 = 2000;
+
+/// Seconds in a day.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Days from 0000-03-01 to 1970-01-01 in the proleptic Gregorian calendar.
+const UNIX_EPOCH_DAYS: u64 = 719_468;
+
+/// Days in a 400-year cycle of the Gregorian calendar.
+const DAYS_PER_ERA: u64 = 146_097;
+
+/// Raw values of the time registers: second, minute, hour, day, month and
+/// two-digit year.
+type Registers = [u8; 6];
+
+/// A UTC date and time, to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    /// Year, e.g. `2024`.
+    pub year: u16,
+    /// Month, from `1` to `12`.
+    pub month: u8,
+    /// Day of the month, from `1` to `31`.
+    pub day: u8,
+    /// Hour, from `0` to `23`.
+    pub hour: u8,
+    /// Minute, from `0` to `59`.
+    pub minute: u8,
+    /// Second, from `0` to `59`.
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the number of seconds from the Unix epoch (1970-01-01
+    /// 00:00:00 UTC) to this date and time.
+    ///
+    /// Dates before the epoch give `0`.
+    #[must_use]
+    #[expect(
+        clippy::integer_division,
+        reason = "The calendar computations work on whole days."
+    )]
+    pub fn unix_timestamp(&self) -> u64 {
+        // Count years from March, so that the leap day ends the year.
+        let (year, month) = if self.month <= 2 {
+            (
+                u64::from(self.year).saturating_sub(1),
+                u64::from(self.month) + 9,
+            )
+        } else {
+            (
+                u64::from(self.year),
+                u64::from(self.month).saturating_sub(3),
+            )
+        };
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let day_of_year = (153 * month + 2) / 5 + u64::from(self.day).saturating_sub(1);
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = (era * DAYS_PER_ERA + day_of_era).saturating_sub(UNIX_EPOCH_DAYS);
+
+        days * SECONDS_PER_DAY
+            + u64::from(self.hour) * 3600
+            + u64::from(self.minute) * 60
+            + u64::from(self.second)
+    }
+
+    /// Returns the date and time `timestamp` seconds after the Unix epoch.
+    ///
+    /// Years past `u16::MAX` saturate.
+    #[must_use]
+    #[expect(
+        clippy::integer_division,
+        reason = "The calendar computations work on whole days."
+    )]
+    pub fn from_unix_timestamp(timestamp: u64) -> Self {
+        let days = timestamp / SECONDS_PER_DAY + UNIX_EPOCH_DAYS;
+        let seconds = timestamp % SECONDS_PER_DAY;
+
+        let era = days / DAYS_PER_ERA;
+        let day_of_era = days % DAYS_PER_ERA;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        // Month counted from March, as in `unix_timestamp`.
+        let march_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * march_month + 2) / 5 + 1;
+        let (month, year_offset) = if march_month < 10 {
+            (march_month + 3, 0)
+        } else {
+            (march_month - 9, 1)
+        };
+
+        Self {
+            year: u16::try_from(era * 400 + year_of_era + year_offset).unwrap_or(u16::MAX),
+            month: narrow(month),
+            day: narrow(day),
+            hour: narrow(seconds / 3600),
+            minute: narrow(seconds % 3600 / 60),
+            second: narrow(seconds % 60),
+        }
+    }
+
+    /// Decodes the raw time registers, given the value of status register B.
+    fn from_registers(registers: Registers, status_b: u8) -> Self {
+        let [second, minute, hour, day, month, year] = registers;
+        let decode = |value: u8| {
+            if status_b & BINARY == 0 {
+                from_bcd(value)
+            } else {
+                value
+            }
+        };
+
+        let mut hour_24 = decode(hour & !PM);
+        if status_b & HOUR_24 == 0 {
+            // In 12-hour format, midnight and noon are hour 12.
+            hour_24 %= 12;
+            if hour & PM != 0 {
+                hour_24 += 12;
+            }
+        }
+
+        Self {
+            year: CENTURY + u16::from(decode(year)),
+            month: decode(month),
+            day: decode(day),
+            hour: hour_24,
+            minute: decode(minute),
+            second: decode(second),
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Formats the date and time in ISO 8601, e.g. `2024-02-29T12:34:56Z`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Reads the current date and time from the RTC.
+#[must_use]
+pub fn read() -> DateTime {
+    let mut registers = read_consistent();
+    loop {
+        let again = read_consistent();
+        if again == registers {
+            break;
+        }
+        registers = again;
+    }
+    DateTime::from_registers(registers, read_register(STATUS_B))
+}
+
+/// Waits for the update in progress, if any, to end, then reads the time
+/// registers.
+fn read_consistent() -> Registers {
+    while read_register(STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+        hint::spin_loop();
+    }
+    TIME_REGISTERS.map(read_register)
+}
+
+/// Reads the RTC register at `index`.
+fn read_register(index: u8) -> u8 {
+    let mut index_port = Port::<u8>::new(INDEX_PORT);
+    let mut data_port = Port::<u8>::new(DATA_PORT);
+
+    // The index must not be changed by an interrupt handler between the two
+    // accesses.
+    interrupts::without_interrupts(|| {
+        // SAFETY:
+        //
+        // The ports belong to the CMOS, which is only accessed here. Bit 7 of
+        // the index is left clear, so NMIs stay enabled.
+        unsafe {
+            index_port.write(index);
+            data_port.read()
+        }
+    })
+}
+
+/// Converts a two-digit BCD value to binary.
+const fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Narrows a calendar field known to fit in a `u8`.
+fn narrow(value: u64) -> u8 {
+    u8::try_from(value).unwrap_or(u8::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    /// 2024-02-29T12:34:56Z.
+    const LEAP_DAY: DateTime = DateTime {
+        year: 2024,
+        month: 2,
+        day: 29,
+        hour: 12,
+        minute: 34,
+        second: 56,
+    };
+
+    #[test_case]
+    fn test_registers_are_decoded() {
+        assert_eq!(
+            DateTime::from_registers([0x56, 0x34, 0x12, 0x29, 0x02, 0x24], HOUR_24),
+            LEAP_DAY,
+            "BCD, 24-hour format.",
+        );
+        assert_eq!(
+            DateTime::from_registers([56, 34, 12, 29, 2, 24], HOUR_24 | BINARY),
+            LEAP_DAY,
+            "Binary, 24-hour format.",
+        );
+        assert_eq!(
+            DateTime::from_registers([0x56, 0x34, PM | 0x12, 0x29, 0x02, 0x24], 0),
+            LEAP_DAY,
+            "BCD, 12-hour format: 12 PM is noon.",
+        );
+        assert_eq!(
+            DateTime::from_registers([56, 34, 12, 29, 2, 24], BINARY).hour,
+            0,
+            "Binary, 12-hour format: 12 AM is midnight.",
+        );
+        assert_eq!(
+            DateTime::from_registers([56, 34, PM | 11, 29, 2, 24], BINARY).hour,
+            23,
+            "Binary, 12-hour format: 11 PM.",
+        );
+    }
+
+    #[test_case]
+    fn test_unix_timestamps() {
+        assert_eq!(LEAP_DAY.unix_timestamp(), 1_709_210_096, "Leap day.");
+        assert_eq!(
+            DateTime::from_unix_timestamp(1_709_210_096),
+            LEAP_DAY,
+            "Back from the timestamp.",
+        );
+        assert_eq!(
+            DateTime::from_unix_timestamp(0).unix_timestamp(),
+            0,
+            "The epoch.",
+        );
+        let last = DateTime::from_unix_timestamp(4_102_444_799);
+        assert_eq!(
+            (
+                last.year,
+                last.month,
+                last.day,
+                last.hour,
+                last.minute,
+                last.second
+            ),
+            (2099, 12, 31, 23, 59, 59),
+            "Last second the RTC can hold.",
+        );
+    }
+
+    #[test_case]
+    fn test_date_time_is_formatted_in_iso_8601() {
+        assert_eq!(
+            format!("{LEAP_DAY}"),
+            "2024-02-29T12:34:56Z",
+            "Zero-padded ISO 8601 in UTC.",
+        );
+    }
+}
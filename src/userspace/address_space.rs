@@ -0,0 +1,672 @@
+//! User address spaces.
+//!
+//! Every process owns an [`AddressSpace`]: a level 4 page table of its own,
+//! created by [`memory::new_level_4_table`]. The table shares the kernel
+//! mappings, but the user range ([`USER_CODE_START`] to [`USER_STACK_TOP`]) is
+//! private: the pages mapped there and the page tables covering them belong to
+//! the process. The scheduler [activates](AddressSpace::activate) the address
+//! space by loading its table in `CR3` before entering Ring 3, so several
+//! processes can be linked at the same virtual addresses without colliding.
+//! Once the process has exited, [`AddressSpace::free`] releases its page
+//! tables and drops its reference to every frame it maps.
+//!
+//! The program image is mapped when the process is loaded. The rest of its
+//! memory is described by [virtual memory areas](super::vma) whose pages are
+//! only mapped on first touch, by [`AddressSpace::handle_page_fault`]: the
+//! stack, reserved below [`USER_STACK_TOP`], the heap, which grows from the
+//! end of the image up to [`USER_MMAP_START`] by moving the program break
+//! ([`AddressSpace::set_brk`]), and the anonymous mappings placed between
+//! [`USER_MMAP_START`] and [`USER_MMAP_END`]. Their pages are zeroed, writable
+//! and non-executable.
+//!
+//! A forked address space shares the frames of its parent instead of copying
+//! them. Writable pages are made read-only and marked [`COPY_ON_WRITE`] on
+//! both sides, and the first write to such a page copies its frame, unless no
+//! other address space maps it anymore. The frame allocator counts the
+//! references to every shared frame, so a frame is only freed once the last
+//! address space mapping it unmaps it.
+
+use alloc::vec::Vec;
+use core::{ops::Range, ptr};
+
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::{MappedFrame, TranslateResult},
+        page::PageRange,
+        FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+        Translate,
+    },
+    VirtAddr,
+};
+
+use crate::{
+    memory::{self, FrameReferences},
+    userspace::{
+        process::LoadError,
+        vma::{Access, PageFaultError, Vma, VmaKind},
+        USER_CODE_START, USER_MMAP_END, USER_MMAP_START, USER_STACK_BOTTOM, USER_STACK_GUARD_PAGE,
+        USER_STACK_TOP,
+    },
+};
+
+/// Addresses private to each address space.
+const USER_RANGE: Range<u64> = USER_CODE_START..USER_STACK_TOP;
+
+/// Flags of the pages backing the stack, the heap and anonymous mappings.
+const ANONYMOUS_PAGE_FLAGS: PageTableFlags =
+    PageTableFlags::WRITABLE.union(PageTableFlags::NO_EXECUTE);
+
+/// Marks a page that is shared with another address space and must be copied
+/// before it is written. Such a page is mapped read-only.
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Returns the pages covering the addresses `start..end`.
+fn pages_between(start: u64, end: u64) -> PageRange<Size4KiB> {
+    Page::range(
+        Page::containing_address(VirtAddr::new(start)),
+        Page::containing_address(VirtAddr::new(end.next_multiple_of(4096))),
+    )
+}
+
+/// The page table and user pages owned by a process.
+#[derive(Debug)]
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    page_count: usize,
+    /// First address of the heap, right after the program image.
+    heap_start: u64,
+    /// Current program break: the end of the heap.
+    brk: u64,
+    /// Areas whose pages are mapped on first touch.
+    vmas: Vec<Vma>,
+}
+
+impl AddressSpace {
+    /// Creates an empty address space sharing the kernel mappings.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`LoadError`] if the page tables cannot be allocated.
+    pub fn new<A>(frame_allocator: &mut A) -> Result<Self, LoadError>
+    where
+        A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+    {
+        Ok(Self {
+            level_4_frame: memory::new_level_4_table(&USER_RANGE, frame_allocator)?,
+            page_count: 0,
+            heap_start: USER_CODE_START,
+            brk: USER_CODE_START,
+            vmas: Vec::new(),
+        })
+    }
+
+    /// Creates a copy of this address space for a forked process.
+    ///
+    /// The user pages are shared rather than copied: the copy maps the frames
+    /// of this address space and holds a reference to each of them. Writable
+    /// pages are made read-only and marked [`COPY_ON_WRITE`] in both address
+    /// spaces, so that the first write to one of them copies it. The areas,
+    /// the heap and the program break are duplicated.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`LoadError`] if the page tables of the copy cannot be
+    /// allocated. The copy is released on error; the pages it already shares
+    /// stay copy-on-write in this address space.
+    pub fn duplicate<A>(&mut self, frame_allocator: &mut A) -> Result<Self, LoadError>
+    where
+        A: FrameReferences,
+    {
+        let mut copy = Self::new(frame_allocator)?;
+        copy.heap_start = self.heap_start;
+        copy.brk = self.brk;
+        copy.vmas.clone_from(&self.vmas);
+
+        // SAFETY:
+        //
+        // The table belongs to this address space, which is borrowed mutably.
+        let mut mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+        for page in pages_between(USER_RANGE.start, USER_RANGE.end) {
+            let TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame),
+                flags,
+                ..
+            } = mapper.translate(page.start_address())
+            else {
+                continue;
+            };
+
+            let shared_flags = if flags.contains(PageTableFlags::WRITABLE) {
+                flags.difference(PageTableFlags::WRITABLE) | COPY_ON_WRITE
+            } else {
+                flags
+            };
+            if shared_flags != flags {
+                // SAFETY:
+                //
+                // The page is in the private user range and stays mapped to the
+                // same frame; it only loses write access until it is copied.
+                if let Ok(flush) = unsafe { mapper.update_flags(page, shared_flags) } {
+                    flush.flush();
+                }
+            }
+
+            if let Err(error) = copy.map_shared(page, frame, shared_flags, frame_allocator) {
+                copy.free(frame_allocator);
+                return Err(error);
+            }
+        }
+
+        Ok(copy)
+    }
+
+    /// Maps `page` to `frame`, a frame mapped by another address space, with
+    /// `flags`, and adds a reference to the frame.
+    fn map_shared<A>(
+        &mut self,
+        page: Page<Size4KiB>,
+        frame: PhysFrame,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<(), LoadError>
+    where
+        A: FrameReferences,
+    {
+        // SAFETY:
+        //
+        // The table belongs to this address space, which is borrowed mutably.
+        let mut mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+
+        // SAFETY:
+        //
+        // The page is in the private user range, so mapping it cannot affect
+        // the kernel mappings shared with other page tables. The frame is
+        // only shared with user address spaces, which are not allowed to
+        // write to it while it is shared.
+        let flush = unsafe { mapper.map_to(page, frame, flags, frame_allocator) }?;
+        flush.flush();
+        frame_allocator.add_reference(frame);
+        self.page_count += 1;
+
+        Ok(())
+    }
+
+    /// Allocates a zeroed frame and maps it at `page` with `flags`.
+    ///
+    /// The address space does not need to be active: the frame is zeroed
+    /// through the physical memory mapping.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`LoadError`] if `page` is outside the user range, if no frame
+    /// is available or if the page is already mapped.
+    pub fn map_page<A>(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<(), LoadError>
+    where
+        A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+    {
+        if !USER_RANGE.contains(&page.start_address().as_u64()) {
+            return Err(LoadError::SegmentOutOfRange);
+        }
+
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(LoadError::FrameAllocationFailed)?;
+
+        // SAFETY:
+        //
+        // The frame was freshly allocated and is not mapped anywhere else yet.
+        unsafe {
+            ptr::write_bytes(
+                memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(),
+                0,
+                4096,
+            );
+        }
+
+        // SAFETY:
+        //
+        // The table belongs to this address space, which is borrowed mutably.
+        let mut mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+        let page_flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+
+        // SAFETY:
+        //
+        // The page is in the private user range, so mapping it cannot affect
+        // the kernel mappings shared with other page tables.
+        // The frame was freshly allocated by the frame allocator.
+        let mapped = unsafe { mapper.map_to(page, frame, page_flags, frame_allocator) };
+        match mapped {
+            Ok(flush) => flush.flush(),
+            Err(error) => {
+                // SAFETY:
+                //
+                // The frame could not be mapped and is therefore unused.
+                unsafe {
+                    frame_allocator.deallocate_frame(frame);
+                }
+                return Err(error.into());
+            }
+        }
+        self.page_count += 1;
+
+        Ok(())
+    }
+
+    /// Unmaps the mapped pages of `pages` and drops the reference each of them
+    /// holds to its frame, which is freed once no reference is left.
+    ///
+    /// Pages that are not mapped are skipped.
+    pub fn unmap_pages<D>(&mut self, pages: PageRange<Size4KiB>, frame_allocator: &mut D)
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        // SAFETY:
+        //
+        // The table belongs to this address space, which is borrowed mutably.
+        let mut mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+
+        for page in pages {
+            if !USER_RANGE.contains(&page.start_address().as_u64()) {
+                continue;
+            }
+            let Ok((frame, flush)) = mapper.unmap(page) else {
+                continue;
+            };
+            flush.flush();
+
+            // SAFETY:
+            //
+            // The mapping at `page` held one reference to the frame, counted by
+            // the allocator, and it has just been removed. A frame shared with
+            // another address space is only freed with its last reference.
+            unsafe {
+                frame_allocator.deallocate_frame(frame);
+            }
+            self.page_count -= 1;
+        }
+    }
+
+    /// Returns `true` if `page` is mapped in this address space.
+    #[must_use]
+    pub fn is_mapped(&self, page: Page<Size4KiB>) -> bool {
+        // SAFETY:
+        //
+        // The table belongs to this address space and is only read.
+        let mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+        mapper.translate_page(page).is_ok()
+    }
+
+    /// Returns the area containing `addr`, if any.
+    #[must_use]
+    pub fn vma(&self, addr: u64) -> Option<&Vma> {
+        self.vmas.iter().find(|vma| vma.contains(addr))
+    }
+
+    /// Returns the first run of `count` pages of the anonymous mapping region
+    /// that no area covers, starting at `hint` if that run is free.
+    #[must_use]
+    pub fn find_free_pages(&self, hint: Option<u64>, count: u64) -> Option<PageRange<Size4KiB>> {
+        let is_free = |start: u64| {
+            let end = start.checked_add(count.checked_mul(4096)?)?;
+            let free = start >= USER_MMAP_START
+                && end <= USER_MMAP_END
+                && !self.vmas.iter().any(|vma| vma.overlaps(&(start..end)));
+            free.then(|| pages_between(start, end))
+        };
+
+        hint.and_then(is_free).or_else(|| {
+            (USER_MMAP_START..USER_MMAP_END)
+                .step_by(4096)
+                .find_map(is_free)
+        })
+    }
+
+    /// Reserves the stack area, between [`USER_STACK_BOTTOM`] and
+    /// [`USER_STACK_TOP`]. Its pages are mapped as the stack grows down.
+    pub fn reserve_stack(&mut self) {
+        self.vmas.push(Vma::new(
+            USER_STACK_BOTTOM..USER_STACK_TOP,
+            ANONYMOUS_PAGE_FLAGS,
+            VmaKind::Stack,
+        ));
+    }
+
+    /// Starts the heap, with an empty program break, at the page boundary
+    /// following `image_end`, the end of the program image.
+    pub fn set_heap_start(&mut self, image_end: u64) {
+        self.heap_start = image_end.next_multiple_of(4096);
+        self.brk = self.heap_start;
+        self.vmas.retain(|vma| vma.kind() != VmaKind::Heap);
+        self.vmas.push(Vma::new(
+            self.heap_start..self.heap_start,
+            ANONYMOUS_PAGE_FLAGS,
+            VmaKind::Heap,
+        ));
+    }
+
+    /// Returns the first address of the heap.
+    #[must_use]
+    pub const fn heap_start(&self) -> u64 {
+        self.heap_start
+    }
+
+    /// Returns the current program break.
+    #[must_use]
+    pub const fn brk(&self) -> u64 {
+        self.brk
+    }
+
+    /// Moves the program break to `brk`.
+    ///
+    /// Growing the heap only extends its area; the pages are mapped when they
+    /// are first touched. Shrinking it releases the pages past the new break.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::SegmentOutOfRange`] if `brk` is before the start
+    /// of the heap or past [`USER_MMAP_START`]. The break is left unchanged on
+    /// error.
+    pub fn set_brk<D>(&mut self, brk: u64, frame_allocator: &mut D) -> Result<(), LoadError>
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        if brk < self.heap_start || brk > USER_MMAP_START {
+            return Err(LoadError::SegmentOutOfRange);
+        }
+
+        let old_end = self.brk.next_multiple_of(4096);
+        let new_end = brk.next_multiple_of(4096);
+        if new_end < old_end {
+            self.unmap_pages(pages_between(new_end, old_end), frame_allocator);
+        }
+        if let Some(heap) = self.vmas.iter_mut().find(|vma| vma.kind() == VmaKind::Heap) {
+            heap.set_end(new_end);
+        }
+        self.brk = brk;
+
+        Ok(())
+    }
+
+    /// Adds an anonymous mapping covering `pages`, whose zeroed, writable and
+    /// non-executable pages are mapped on first touch.
+    pub fn map_anonymous(&mut self, pages: PageRange<Size4KiB>) {
+        self.vmas.push(Vma::new(
+            pages.start.start_address().as_u64()..pages.end.start_address().as_u64(),
+            ANONYMOUS_PAGE_FLAGS,
+            VmaKind::Anonymous,
+        ));
+    }
+
+    /// Unmaps the anonymous mappings between `start` and `end`, which are
+    /// rounded to page boundaries.
+    ///
+    /// Addresses outside of the anonymous mapping region are ignored.
+    pub fn unmap_anonymous<D>(&mut self, start: u64, end: u64, frame_allocator: &mut D)
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        let first = start.max(USER_MMAP_START);
+        let last = end.min(USER_MMAP_END);
+        if first >= last {
+            return;
+        }
+        let pages = pages_between(first, last);
+        let range = pages.start.start_address().as_u64()..pages.end.start_address().as_u64();
+
+        let mut vmas = Vec::with_capacity(self.vmas.len() + 1);
+        for vma in self.vmas.drain(..) {
+            if vma.kind() == VmaKind::Anonymous && vma.overlaps(&range) {
+                vmas.extend(
+                    vma.split_off(&range)
+                        .into_iter()
+                        .filter(|part| !part.is_empty()),
+                );
+            } else {
+                vmas.push(vma);
+            }
+        }
+        self.vmas = vmas;
+        self.unmap_pages(pages, frame_allocator);
+    }
+
+    /// Resolves a page fault at `addr` caused by `access`, by mapping the
+    /// page if it belongs to an area and has not been touched yet, or by
+    /// copying it if it is a [`COPY_ON_WRITE`] page being written.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PageFaultError`] if the fault is a genuine access
+    /// violation, hits the stack guard page, or if no frame is left to back
+    /// the page.
+    pub fn handle_page_fault<A>(
+        &mut self,
+        addr: u64,
+        access: Access,
+        frame_allocator: &mut A,
+    ) -> Result<(), PageFaultError>
+    where
+        A: FrameReferences,
+    {
+        if (USER_STACK_GUARD_PAGE..USER_STACK_BOTTOM).contains(&addr) {
+            return Err(PageFaultError::StackOverflow);
+        }
+
+        let page = Page::containing_address(VirtAddr::new(addr));
+        if let Some((frame, flags)) = self.translate(page.start_address()) {
+            return if access == Access::Write && flags.contains(COPY_ON_WRITE) {
+                self.copy_on_write(page, frame, flags, frame_allocator)
+            } else {
+                Err(PageFaultError::AccessViolation)
+            };
+        }
+
+        let flags = self.vma(addr).ok_or(PageFaultError::NotMapped)?.flags();
+        if !access.is_allowed_by(flags) {
+            return Err(PageFaultError::AccessViolation);
+        }
+        match self.map_page(page, flags, frame_allocator) {
+            Ok(()) => Ok(()),
+            Err(LoadError::FrameAllocationFailed) => Err(PageFaultError::OutOfMemory),
+            Err(_) => Err(PageFaultError::AccessViolation),
+        }
+    }
+
+    /// Gives the [`COPY_ON_WRITE`] `page`, mapped to `frame` with `flags`, a
+    /// private writable frame.
+    ///
+    /// The frame is copied if other address spaces still map it. Otherwise
+    /// this address space is its last owner and the page is made writable
+    /// again in place.
+    fn copy_on_write<A>(
+        &mut self,
+        page: Page<Size4KiB>,
+        frame: PhysFrame,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<(), PageFaultError>
+    where
+        A: FrameReferences,
+    {
+        let writable_flags = flags.difference(COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+
+        // SAFETY:
+        //
+        // The table belongs to this address space, which is borrowed mutably.
+        let mut mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+
+        if frame_allocator.reference_count(frame) == 1 {
+            // SAFETY:
+            //
+            // No other address space maps the frame anymore, so this one may
+            // write to it.
+            let flush = unsafe { mapper.update_flags(page, writable_flags) }
+                .ok()
+                .ok_or(PageFaultError::AccessViolation)?;
+            flush.flush();
+            return Ok(());
+        }
+
+        let copy = frame_allocator
+            .allocate_frame()
+            .ok_or(PageFaultError::OutOfMemory)?;
+
+        // SAFETY:
+        //
+        // The source frame is mapped by this address space and the copy was
+        // freshly allocated, so they are distinct frames.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                memory::phys_to_virt(frame.start_address()).as_ptr::<u8>(),
+                memory::phys_to_virt(copy.start_address()).as_mut_ptr::<u8>(),
+                4096,
+            );
+        }
+
+        let Ok((_, flush)) = mapper.unmap(page) else {
+            // SAFETY:
+            //
+            // The copy could not be mapped and is therefore unused.
+            unsafe {
+                frame_allocator.deallocate_frame(copy);
+            }
+            return Err(PageFaultError::AccessViolation);
+        };
+        flush.flush();
+
+        // SAFETY:
+        //
+        // The page was unmapped above and its page tables are still present.
+        // The copy is private to this address space.
+        let remapped = unsafe { mapper.map_to(page, copy, writable_flags, frame_allocator) };
+
+        // SAFETY:
+        //
+        // The page no longer maps the shared frame, which keeps the references
+        // of the other address spaces.
+        unsafe {
+            frame_allocator.deallocate_frame(frame);
+        }
+
+        if let Ok(flush) = remapped {
+            flush.flush();
+            Ok(())
+        } else {
+            // SAFETY:
+            //
+            // The copy could not be mapped and is therefore unused.
+            unsafe {
+                frame_allocator.deallocate_frame(copy);
+            }
+            self.page_count -= 1;
+            Err(PageFaultError::OutOfMemory)
+        }
+    }
+
+    /// Returns the frame mapped at the page containing `addr` and its flags,
+    /// if the page is mapped in this address space.
+    #[must_use]
+    pub fn translate(&self, addr: VirtAddr) -> Option<(PhysFrame, PageTableFlags)> {
+        // SAFETY:
+        //
+        // The table belongs to this address space and is only read.
+        let mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+        match mapper.translate(addr) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame),
+                flags,
+                ..
+            } => Some((frame, flags)),
+            TranslateResult::Mapped { .. }
+            | TranslateResult::NotMapped
+            | TranslateResult::InvalidFrameAddress(_) => None,
+        }
+    }
+
+    /// Copies `data` to the user memory starting at `addr`.
+    ///
+    /// The address space does not need to be active: the bytes are written
+    /// through the physical memory mapping, regardless of the page flags.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LoadError::PageNotMapped`] if part of the destination is not
+    /// mapped in this address space.
+    pub fn write(&mut self, addr: VirtAddr, data: &[u8]) -> Result<(), LoadError> {
+        // SAFETY:
+        //
+        // The table belongs to this address space, which is borrowed mutably.
+        let mapper = unsafe { memory::mapper_for(self.level_4_frame) };
+
+        let mut written = 0;
+        while written < data.len() {
+            let target = addr + written;
+            let phys = mapper
+                .translate_addr(target)
+                .ok_or(LoadError::PageNotMapped)?;
+            let chunk = (data.len() - written).min(4096 - usize::from(target.page_offset()));
+
+            // SAFETY:
+            //
+            // `phys` is backed by a frame owned by this address space and the
+            // chunk does not cross the end of its page. The source is kernel
+            // memory and cannot overlap with it.
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    data.as_ptr().add(written),
+                    memory::phys_to_virt(phys).as_mut_ptr::<u8>(),
+                    chunk,
+                );
+            }
+            written += chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Makes this address space the active one by loading its page table in
+    /// `CR3`.
+    ///
+    /// # Safety
+    ///
+    /// The address space must stay alive while it is active.
+    pub unsafe fn activate(&self) {
+        memory::switch_level_4_table(self.level_4_frame);
+    }
+
+    /// Releases the page tables of this address space and drops the reference
+    /// each user page holds to its frame. A frame shared copy-on-write with
+    /// another address space is only freed with its last reference.
+    ///
+    /// If the address space is active, the kernel page table is activated
+    /// first.
+    pub fn free<D>(self, frame_allocator: &mut D)
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        if Cr3::read().0 == self.level_4_frame {
+            memory::switch_to_kernel_level_4_table();
+        }
+
+        // SAFETY:
+        //
+        // The table was created by `new_level_4_table` with `USER_RANGE`, is no
+        // longer active and is consumed here. Its page tables are private to
+        // this address space, and each user page holds one counted reference
+        // to its frame, shared frames included.
+        unsafe {
+            memory::free_level_4_table(self.level_4_frame, &USER_RANGE, frame_allocator);
+        }
+    }
+
+    /// Returns the number of user pages owned by this address space.
+    #[must_use]
+    pub const fn page_count(&self) -> usize {
+        self.page_count
+    }
+}
@@ -0,0 +1,167 @@
+//! Saved user-mode register context.
+//!
+//! The [`Context`] layout mirrors the stack built by the naked syscall entry
+//! point: the general-purpose registers pushed by software (`rax` first, `r15`
+//! last) followed by the interrupt stack frame pushed by the CPU. This allows
+//! a context to be saved or restored with a plain sequence of `push`/`pop`
+//! instructions followed by `iretq`.
+//!
+//! The [`push_context!`](crate::push_context),
+//! [`push_context_with_error_code!`](crate::push_context_with_error_code) and
+//! [`pop_context!`](crate::pop_context) macros expand to that instruction
+//! sequence so every naked trap entry point builds the exact same layout.
+
+use x86_64::registers::rflags::RFlags;
+
+use crate::gdt;
+
+/// Assembly template pushing the general-purpose registers in the order
+/// expected by [`Context`]. Must be used right after the CPU pushed the
+/// interrupt stack frame.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! push_context {
+    () => {
+        "push rax
+        push rbx
+        push rcx
+        push rdx
+        push rsi
+        push rdi
+        push rbp
+        push r8
+        push r9
+        push r10
+        push r11
+        push r12
+        push r13
+        push r14
+        push r15"
+    };
+}
+
+/// Assembly template for exceptions that push an error code: swaps the error
+/// code with `rax`, then pushes the remaining general-purpose registers, so
+/// that the stack holds the same [`Context`] layout as with
+/// [`push_context!`](crate::push_context). The error code is left in `rax`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! push_context_with_error_code {
+    () => {
+        "xchg [rsp], rax
+        push rbx
+        push rcx
+        push rdx
+        push rsi
+        push rdi
+        push rbp
+        push r8
+        push r9
+        push r10
+        push r11
+        push r12
+        push r13
+        push r14
+        push r15"
+    };
+}
+
+/// Assembly template popping the general-purpose registers pushed by
+/// [`push_context!`](crate::push_context), leaving the interrupt stack frame
+/// ready for `iretq`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! pop_context {
+    () => {
+        "pop r15
+        pop r14
+        pop r13
+        pop r12
+        pop r11
+        pop r10
+        pop r9
+        pop r8
+        pop rbp
+        pop rdi
+        pop rsi
+        pop rdx
+        pop rcx
+        pop rbx
+        pop rax"
+    };
+}
+
+/// Complete register state of a user process, as seen from a trap.
+///
+/// ```text
+/// offset 0x00 : r15
+/// offset 0x08 : r14
+/// offset 0x10 : r13
+/// offset 0x18 : r12
+/// offset 0x20 : r11
+/// offset 0x28 : r10
+/// offset 0x30 : r9
+/// offset 0x38 : r8
+/// offset 0x40 : rbp
+/// offset 0x48 : rdi
+/// offset 0x50 : rsi
+/// offset 0x58 : rdx
+/// offset 0x60 : rcx
+/// offset 0x68 : rbx
+/// offset 0x70 : rax
+/// offset 0x78 : rip     (interrupt stack frame)
+/// offset 0x80 : cs
+/// offset 0x88 : rflags
+/// offset 0x90 : rsp
+/// offset 0x98 : ss
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+#[expect(missing_docs, reason = "Fields are named after the saved registers.")]
+pub struct Context {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl Context {
+    /// Creates the initial context of a user process.
+    ///
+    /// All general-purpose registers are zeroed, the code and stack segments
+    /// are set to the Ring 3 selectors and interrupts are enabled so that the
+    /// process can be interrupted by hardware.
+    #[must_use]
+    pub fn new_user(entry_point: u64, stack_top: u64) -> Self {
+        Self {
+            rip: entry_point,
+            cs: u64::from(gdt::user_code_selector().0),
+            rflags: RFlags::INTERRUPT_FLAG.bits(),
+            rsp: stack_top,
+            ss: u64::from(gdt::user_data_selector().0),
+            ..Self::default()
+        }
+    }
+
+    /// Returns `true` if the context was interrupted while executing in Ring 3.
+    #[must_use]
+    pub const fn is_user(&self) -> bool {
+        self.cs & 0b11 == 3
+    }
+}
@@ -0,0 +1,376 @@
+//! Minimal ELF64 executable parser.
+//!
+//! This module validates the ELF header of a user program and exposes its
+//! loadable (`PT_LOAD`) segments so that [`process`](super::process) can map
+//! them with the right page permissions. Only statically linked, little-endian
+//! x86-64 executables (`ET_EXEC`) are supported.
+//!
+//! All multi-byte fields are decoded with `from_le_bytes`, so the input slice
+//! does not need any particular alignment (e.g. data embedded with
+//! `include_bytes!`).
+
+use core::ops::Range;
+
+/// Magic bytes at the start of every ELF file.
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+/// `EI_CLASS` value for 64-bit objects.
+const ELFCLASS64: u8 = 2;
+
+/// `EI_DATA` value for little-endian objects.
+const ELFDATA2LSB: u8 = 1;
+
+/// `EI_VERSION` / `e_version` value for the current ELF version.
+const EV_CURRENT: u8 = 1;
+
+/// `e_type` value for executable files.
+const ET_EXEC: u16 = 2;
+
+/// `e_machine` value for AMD x86-64.
+const EM_X86_64: u16 = 62;
+
+/// Size of the ELF64 file header.
+const ELF64_HEADER_SIZE: usize = 64;
+
+/// Size of an ELF64 program header entry.
+const ELF64_PHDR_SIZE: usize = 56;
+
+/// Program header type of a loadable segment.
+const PT_LOAD: u32 = 1;
+
+/// Segment permission flag: executable.
+const PF_X: u32 = 0x1;
+
+/// Segment permission flag: writable.
+const PF_W: u32 = 0x2;
+
+/// Segment permission flag: readable.
+const PF_R: u32 = 0x4;
+
+/// Errors reported while parsing an ELF64 executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file is smaller than the structure that had to be read.
+    Truncated,
+    /// The file does not start with `\x7fELF`.
+    InvalidMagic,
+    /// The file is not a 64-bit ELF object.
+    UnsupportedClass,
+    /// The file is not little-endian.
+    UnsupportedEndianness,
+    /// The ELF version is not `EV_CURRENT`.
+    UnsupportedVersion,
+    /// The file is not an executable (`ET_EXEC`).
+    NotExecutable,
+    /// The file does not target x86-64.
+    UnsupportedMachine,
+    /// The program header table has an unexpected entry size or lies outside the file.
+    InvalidProgramHeaderTable,
+    /// A `PT_LOAD` segment has inconsistent sizes or file bytes outside the file.
+    InvalidSegment,
+    /// The file does not contain any `PT_LOAD` segment.
+    NoLoadableSegments,
+    /// The entry point does not lie inside an executable segment.
+    InvalidEntryPoint,
+}
+
+/// Access permissions requested by a segment (`p_flags`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentFlags {
+    /// The segment may be read (`PF_R`).
+    pub readable: bool,
+    /// The segment may be written (`PF_W`).
+    pub writable: bool,
+    /// The segment may be executed (`PF_X`).
+    pub executable: bool,
+}
+
+/// A loadable (`PT_LOAD`) segment of an ELF executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadSegment {
+    /// Virtual address the segment must be loaded at (`p_vaddr`).
+    pub vaddr: u64,
+    /// Size of the segment in memory (`p_memsz`).
+    pub mem_size: u64,
+    /// Offset of the segment bytes in the file (`p_offset`).
+    pub file_offset: u64,
+    /// Number of bytes backed by the file (`p_filesz`). The remaining
+    /// `mem_size - file_size` bytes must be zero-filled (`.bss`).
+    pub file_size: u64,
+    /// Access permissions of the segment.
+    pub flags: SegmentFlags,
+}
+
+impl LoadSegment {
+    /// Returns the virtual address range covered by the segment in memory.
+    #[must_use]
+    pub const fn memory_range(&self) -> Range<u64> {
+        self.vaddr..self.vaddr + self.mem_size
+    }
+}
+
+/// A validated ELF64 executable borrowed from a byte slice.
+#[derive(Debug, Clone, Copy)]
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    entry: u64,
+    phoff: usize,
+    phnum: usize,
+}
+
+impl<'a> ElfFile<'a> {
+    /// Parses and validates an ELF64 executable.
+    ///
+    /// Every `PT_LOAD` program header is checked here, so the segments
+    /// returned by [`ElfFile::load_segments`] are guaranteed to be consistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ElfError`] describing the first malformed or unsupported
+    /// field encountered.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        let header = data.get(..ELF64_HEADER_SIZE).ok_or(ElfError::Truncated)?;
+
+        if header.get(..4) != Some(&ELF_MAGIC[..]) {
+            return Err(ElfError::InvalidMagic);
+        }
+        if read_u8(header, 4)? != ELFCLASS64 {
+            return Err(ElfError::UnsupportedClass);
+        }
+        if read_u8(header, 5)? != ELFDATA2LSB {
+            return Err(ElfError::UnsupportedEndianness);
+        }
+        if read_u8(header, 6)? != EV_CURRENT || read_u32(header, 20)? != u32::from(EV_CURRENT) {
+            return Err(ElfError::UnsupportedVersion);
+        }
+        if read_u16(header, 16)? != ET_EXEC {
+            return Err(ElfError::NotExecutable);
+        }
+        if read_u16(header, 18)? != EM_X86_64 {
+            return Err(ElfError::UnsupportedMachine);
+        }
+
+        let entry = read_u64(header, 24)?;
+        let phoff = usize::try_from(read_u64(header, 32)?)
+            .ok()
+            .ok_or(ElfError::InvalidProgramHeaderTable)?;
+        let phentsize = usize::from(read_u16(header, 54)?);
+        let phnum = usize::from(read_u16(header, 56)?);
+
+        if phentsize != ELF64_PHDR_SIZE {
+            return Err(ElfError::InvalidProgramHeaderTable);
+        }
+        let table_end = phnum
+            .checked_mul(ELF64_PHDR_SIZE)
+            .and_then(|size| size.checked_add(phoff))
+            .ok_or(ElfError::InvalidProgramHeaderTable)?;
+        if table_end > data.len() {
+            return Err(ElfError::InvalidProgramHeaderTable);
+        }
+
+        let elf = Self {
+            data,
+            entry,
+            phoff,
+            phnum,
+        };
+
+        let mut has_segments = false;
+        let mut entry_is_executable = false;
+        for program_header in elf.load_segments() {
+            let segment = program_header?;
+            has_segments = true;
+            if segment.flags.executable && segment.memory_range().contains(&entry) {
+                entry_is_executable = true;
+            }
+        }
+
+        if !has_segments {
+            return Err(ElfError::NoLoadableSegments);
+        }
+        if !entry_is_executable {
+            return Err(ElfError::InvalidEntryPoint);
+        }
+
+        Ok(elf)
+    }
+
+    /// Returns the virtual address of the program entry point (`e_entry`).
+    #[must_use]
+    pub const fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Returns an iterator over the `PT_LOAD` segments of the executable.
+    ///
+    /// Other program header types (`PT_GNU_STACK`, `PT_NOTE`, ...) are skipped.
+    pub fn load_segments(&self) -> impl Iterator<Item = Result<LoadSegment, ElfError>> + 'a {
+        let data = self.data;
+        let phoff = self.phoff;
+        (0..self.phnum).filter_map(move |index| {
+            let offset = phoff + index * ELF64_PHDR_SIZE;
+            parse_program_header(data, offset).transpose()
+        })
+    }
+
+    /// Returns the bytes of `segment` that are stored in the file.
+    ///
+    /// The slice is `segment.file_size` bytes long; the caller is responsible
+    /// for zero-filling the rest of the segment up to `segment.mem_size`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ElfError::InvalidSegment`] if the segment bytes do not lie
+    /// inside the file.
+    pub fn segment_data(&self, segment: &LoadSegment) -> Result<&'a [u8], ElfError> {
+        let start = usize::try_from(segment.file_offset)
+            .ok()
+            .ok_or(ElfError::InvalidSegment)?;
+        let size = usize::try_from(segment.file_size)
+            .ok()
+            .ok_or(ElfError::InvalidSegment)?;
+        let end = start.checked_add(size).ok_or(ElfError::InvalidSegment)?;
+        self.data.get(start..end).ok_or(ElfError::InvalidSegment)
+    }
+}
+
+/// Parses the program header at `offset`, returning `None` if it is not a
+/// `PT_LOAD` segment.
+fn parse_program_header(data: &[u8], offset: usize) -> Result<Option<LoadSegment>, ElfError> {
+    let header = data
+        .get(offset..offset + ELF64_PHDR_SIZE)
+        .ok_or(ElfError::InvalidProgramHeaderTable)?;
+
+    if read_u32(header, 0)? != PT_LOAD {
+        return Ok(None);
+    }
+
+    let p_flags = read_u32(header, 4)?;
+    let segment = LoadSegment {
+        vaddr: read_u64(header, 16)?,
+        file_offset: read_u64(header, 8)?,
+        file_size: read_u64(header, 32)?,
+        mem_size: read_u64(header, 40)?,
+        flags: SegmentFlags {
+            readable: p_flags & PF_R != 0,
+            writable: p_flags & PF_W != 0,
+            executable: p_flags & PF_X != 0,
+        },
+    };
+
+    if segment.file_size > segment.mem_size || segment.vaddr.checked_add(segment.mem_size).is_none()
+    {
+        return Err(ElfError::InvalidSegment);
+    }
+    let file_end = segment
+        .file_offset
+        .checked_add(segment.file_size)
+        .ok_or(ElfError::InvalidSegment)?;
+    if file_end > data.len() as u64 {
+        return Err(ElfError::InvalidSegment);
+    }
+
+    Ok(Some(segment))
+}
+
+/// Reads a byte at `offset`.
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, ElfError> {
+    data.get(offset).copied().ok_or(ElfError::Truncated)
+}
+
+/// Reads a little-endian `u16` at `offset`.
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    read_array(data, offset).map(u16::from_le_bytes)
+}
+
+/// Reads a little-endian `u32` at `offset`.
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    read_array(data, offset).map(u32::from_le_bytes)
+}
+
+/// Reads a little-endian `u64` at `offset`.
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ElfError> {
+    read_array(data, offset).map(u64::from_le_bytes)
+}
+
+/// Copies `N` bytes starting at `offset` into an array.
+fn read_array<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], ElfError> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ElfError::Truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::userspace::USER_CODE_START;
+
+    /// The hello user program, used as a known-good ELF executable.
+    static HELLO_ELF: &[u8] = include_bytes!("../../user_programs/hello/hello.elf");
+
+    /// Returns a copy of the first bytes of the hello executable so that a
+    /// single header field can be corrupted.
+    fn hello_header() -> [u8; 4096] {
+        let mut header = [0; 4096];
+        header.copy_from_slice(&HELLO_ELF[..4096]);
+        header
+    }
+
+    #[test_case]
+    fn test_hello_elf_is_valid() {
+        let elf = ElfFile::parse(HELLO_ELF);
+        assert_eq!(
+            elf.map(|file| file.entry()),
+            Ok(USER_CODE_START),
+            "hello.elf should be valid and start at USER_CODE_START.",
+        );
+        assert!(
+            elf.is_ok_and(|file| file.load_segments().all(|segment| segment.is_ok())),
+            "All hello.elf segments should be valid.",
+        );
+    }
+
+    #[test_case]
+    fn test_truncated_file_is_rejected() {
+        assert_eq!(
+            ElfFile::parse(&HELLO_ELF[..32]).err(),
+            Some(ElfError::Truncated),
+            "A file shorter than the ELF header should be rejected.",
+        );
+    }
+
+    #[test_case]
+    fn test_invalid_magic_is_rejected() {
+        let mut header = hello_header();
+        header[1] = b'X';
+        assert_eq!(
+            ElfFile::parse(&header).err(),
+            Some(ElfError::InvalidMagic),
+            "A file without the ELF magic should be rejected.",
+        );
+    }
+
+    #[test_case]
+    fn test_32_bit_elf_is_rejected() {
+        let mut header = hello_header();
+        header[4] = 1;
+        assert_eq!(
+            ElfFile::parse(&header).err(),
+            Some(ElfError::UnsupportedClass),
+            "A 32-bit ELF file should be rejected.",
+        );
+    }
+
+    #[test_case]
+    fn test_program_header_table_out_of_file_is_rejected() {
+        let mut header = hello_header();
+        // e_phnum = 0xffff
+        header[56] = 0xff;
+        header[57] = 0xff;
+        assert_eq!(
+            ElfFile::parse(&header).err(),
+            Some(ElfError::InvalidProgramHeaderTable),
+            "A program header table past the end of the file should be rejected.",
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! Syscall error codes.
+//!
+//! The [`Errno`] values are defined by the [`abi`] crate shared with user
+//! programs. A failing syscall returns the negated error number in `rax`,
+//! following the Linux convention.
+
+pub use abi::errno::{decode_result, encode_result, Errno, MAX_ERRNO};
+
+use crate::userspace::{process::LoadError, user_ptr::UserFault};
+
+impl From<UserFault> for Errno {
+    fn from(_: UserFault) -> Self {
+        Self::EFAULT
+    }
+}
+
+impl From<LoadError> for Errno {
+    fn from(error: LoadError) -> Self {
+        match error {
+            LoadError::InvalidElf(_) => Self::ENOEXEC,
+            LoadError::SegmentOutOfRange | LoadError::FrameAllocationFailed => Self::ENOMEM,
+            LoadError::PageAlreadyMapped | LoadError::HugePageConflict => Self::EINVAL,
+            LoadError::PageNotMapped => Self::EFAULT,
+        }
+    }
+}
+
+/// Result of a syscall: the value returned in `rax` or an error.
+pub type SyscallResult = Result<u64, Errno>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_errno_return_value_roundtrip() {
+        for errno in Errno::ALL {
+            assert_eq!(
+                Errno::from_return_value(errno.as_return_value()),
+                Some(errno),
+                "Every error must be decoded from its return value.",
+            );
+            assert_eq!(
+                decode_result(encode_result(Err(errno))),
+                Err(errno),
+                "Every error must be decoded as a failure.",
+            );
+        }
+    }
+
+    #[test_case]
+    fn test_errno_return_values_are_negative() {
+        assert_eq!(
+            Errno::EFAULT.as_return_value(),
+            u64::MAX - 13,
+            "EFAULT must be returned as -14.",
+        );
+        assert_eq!(
+            Errno::from_return_value(0),
+            None,
+            "0 is a successful result.",
+        );
+        assert_eq!(
+            decode_result(u64::MAX - MAX_ERRNO),
+            Ok(u64::MAX - MAX_ERRNO),
+            "Values below -4095 are successful results.",
+        );
+    }
+}
@@ -2,12 +2,33 @@
 //!
 //! This module provides the infrastructure to load and execute user-mode binaries
 //! in Ring 3. It includes:
-//! - A syscall interface via `int 0x80` for user programs to request kernel services.
-//! - A process loader that maps a flat binary into user-accessible pages.
+//! - A syscall interface via `syscall` or `int 0x80` for user programs to request
+//!   kernel services.
+//! - An ELF64 loader that maps executable segments into user-accessible pages.
+//! - A process table holding every loaded program with its PID, saved register
+//!   context and address space, and the `fork`, `exec` and `wait` syscalls to
+//!   create processes from user space.
+//! - A registry of the programs that `sys_exec` can start.
+//! - Checked copies between kernel and user memory for syscall arguments.
+//! - Virtual memory areas for the stack, the heap and anonymous mappings,
+//!   whose pages are allocated on first touch by the page fault handler.
+//! - A preemptive round-robin scheduler driven by the timer interrupt.
 //! - A mechanism to switch from kernel mode (Ring 0) to user mode (Ring 3).
 
+pub mod address_space;
+pub mod context;
+pub mod elf;
+pub mod errno;
 pub mod process;
+pub mod programs;
+pub mod scheduler;
 pub mod syscall;
+pub mod user_ptr;
+pub mod vma;
+
+/// Interrupt vector number used for syscalls (`int 0x80`), defined by the
+/// [`abi`] crate.
+pub use abi::SYSCALL_INTERRUPT_INDEX;
 
 /// Base virtual address where user program code is loaded.
 pub const USER_CODE_START: u64 = 0x40_0000;
@@ -15,11 +36,21 @@ pub const USER_CODE_START: u64 = 0x40_0000;
 /// Top of the user-mode stack (stack grows downward).
 pub const USER_STACK_TOP: u64 = 0x80_0000;
 
-/// Size of the user-mode stack in bytes (16 KiB).
-pub const USER_STACK_SIZE: u64 = 4096 * 4;
+/// Maximum size of the user-mode stack in bytes (256 KiB). The stack pages
+/// are only allocated when the stack grows into them.
+pub const USER_STACK_SIZE: u64 = 4096 * 64;
 
-/// Bottom of the user-mode stack.
+/// Bottom of the user-mode stack, the lowest address it can grow to.
 pub const USER_STACK_BOTTOM: u64 = USER_STACK_TOP - USER_STACK_SIZE;
 
-/// Interrupt vector number used for syscalls (`int 0x80`).
-pub const SYSCALL_INTERRUPT_INDEX: u8 = 0x80;
+/// Guard page below the user-mode stack. It is never mapped, so that a stack
+/// overflow faults instead of running into other memory.
+pub const USER_STACK_GUARD_PAGE: u64 = USER_STACK_BOTTOM - 4096;
+
+/// Start of the region where `sys_mmap` places anonymous mappings. The heap
+/// grown by `sys_brk` from the end of the program image stops below it.
+pub const USER_MMAP_START: u64 = 0x60_0000;
+
+/// End of the anonymous mapping region. The addresses above it are left to
+/// the user stack and its guard page.
+pub const USER_MMAP_END: u64 = 0x70_0000;
@@ -1,327 +1,1001 @@
-//! Process loading and user mode execution.
+//! Process management and user mode execution.
 //!
-//! This module provides the infrastructure to load a flat binary into
-//! user-accessible memory pages and switch the CPU to Ring 3 for execution.
+//! This module provides the infrastructure to load ELF64 executables into
+//! user processes and switch the CPU to Ring 3 to execute them.
 //!
-//! The user binary is expected to be a position-dependent flat binary linked
-//! at [`USER_CODE_START`](super::USER_CODE_START). It is mapped into the current
-//! address space with `USER_ACCESSIBLE` page flags, and a separate user-mode
-//! stack is allocated below [`USER_STACK_TOP`](super::USER_STACK_TOP).
+//! Every loaded program is described by a [`Process`] (PID, saved register
+//! context, address space, state and exit status) held in a kernel process
+//! table. Several processes can be loaded with [`spawn`] and live at the same
+//! time; [`run`] then executes the ready processes one after the other until
+//! none is left. Each process has its own level 4 page table, loaded in `CR3`
+//! while it runs, and its memory is released as soon as it exits.
+//!
+//! A user binary is expected to be a statically linked executable whose
+//! `PT_LOAD` segments lie between [`USER_CODE_START`](super::USER_CODE_START)
+//! and [`USER_MMAP_START`](super::USER_MMAP_START). Each segment is mapped
+//! with `USER_ACCESSIBLE` page flags and the permissions requested by its
+//! program header, and a separate user-mode stack is reserved below
+//! [`USER_STACK_TOP`](super::USER_STACK_TOP). The stack and heap pages are
+//! only allocated when the process first touches them: a page fault in one of
+//! the [areas](super::vma) of the process is resolved by [`handle_page_fault`],
+//! which maps the page and resumes the process. A forked process shares the
+//! frames of its parent until one of them writes to a page, which is then
+//! copied by the same handler.
 
-use core::sync::atomic::AtomicU64;
+use alloc::collections::btree_map::BTreeMap;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
+use spin::Mutex;
 use x86_64::{
-    structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB},
+    instructions::interrupts,
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Page, PageTableFlags, Size4KiB,
+    },
     VirtAddr,
 };
 
-use crate::{gdt, println, serial_println, userspace};
+use crate::{
+    interrupts::exceptions::{Exception, SIGNAL_EXIT_BASE},
+    memory, pop_context, println, serial_println,
+    time::Instant,
+    userspace::{
+        self,
+        address_space::AddressSpace,
+        context::Context,
+        elf::{ElfError, ElfFile, LoadSegment},
+        scheduler,
+        vma::{Access, PageFaultError},
+    },
+};
 
 /// Saved kernel RSP before entering user mode.
 ///
 /// When [`switch_to_user_mode`] executes `iretq`, the original kernel stack is
-/// abandoned. This static stores the kernel RSP so that the `sys_exit` syscall
-/// handler can restore it and effectively "return" from `switch_to_user_mode`
-/// back to [`run`].
-pub(crate) static KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
+/// abandoned. This static stores the kernel RSP so that [`return_to_kernel`]
+/// can restore it and effectively "return" from `switch_to_user_mode` back
+/// to [`run`].
+static KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
+
+/// Signal number of a process terminated by the kernel with [`kill`].
+pub const SIGKILL: u64 = 9;
+
+/// The kernel process table.
+static PROCESS_TABLE: Mutex<ProcessTable> = Mutex::new(ProcessTable::new());
+
+/// Unique identifier of a user process.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub struct Pid(u64);
+
+impl Pid {
+    /// PID of the first process. Identifiers are never reused.
+    const FIRST: u64 = 1;
+
+    fn new() -> Self {
+        static NEXT_PID: AtomicU64 = AtomicU64::new(Pid::FIRST);
+        Self(NEXT_PID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Wraps a PID received from user space, which may not name any process.
+    #[must_use]
+    pub const fn from_u64(pid: u64) -> Self {
+        Self(pid)
+    }
+
+    /// Returns the PID as a `u64`.
+    #[must_use]
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Scheduling state of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    /// The process can be executed.
+    Ready,
+    /// The process is currently executing in Ring 3.
+    Running,
+    /// The process waits for one of its children to exit.
+    Waiting,
+    /// The process sleeps until the monotonic clock reaches `until`.
+    Sleeping {
+        /// The time at which the process becomes ready again.
+        until: Instant,
+    },
+    /// The process has terminated; its [`ExitStatus`] is available.
+    Exited,
+}
+
+/// How a process terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// The process called `sys_exit` with this code.
+    Exited(u64),
+    /// The process was terminated by a CPU exception. `address` is the
+    /// faulting address for a page fault and the faulting instruction
+    /// otherwise.
+    Faulted {
+        /// The exception raised by the process.
+        exception: Exception,
+        /// The address the exception was raised at.
+        address: u64,
+    },
+    /// The process was terminated by the kernel with this signal number.
+    Signaled(u64),
+}
+
+impl ExitStatus {
+    /// Returns the exit code reported to the parent by the wait syscalls.
+    ///
+    /// A process terminated by an exception or a signal gets the code
+    /// `128 + signal number`, following the Unix shell convention.
+    #[must_use]
+    pub const fn code(self) -> u64 {
+        match self {
+            Self::Exited(code) => code,
+            Self::Faulted { exception, .. } => exception.exit_code(),
+            Self::Signaled(signal) => SIGNAL_EXIT_BASE + signal,
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Exited(code) => write!(f, "exited with code {code}"),
+            Self::Faulted { exception, address } => {
+                write!(f, "terminated by {exception} at {address:#x}")
+            }
+            Self::Signaled(signal) => write!(f, "killed by signal {signal}"),
+        }
+    }
+}
+
+/// A user process.
+#[derive(Debug)]
+pub struct Process {
+    pid: Pid,
+    /// The process that forked this one, `None` for the processes started by
+    /// the kernel and the orphans.
+    parent: Option<Pid>,
+    /// `true` once the parent has exited. Nobody collects the exit status of
+    /// an orphan, so it is removed from the table as soon as it exits.
+    orphan: bool,
+    context: Context,
+    /// `None` once the process has exited and its memory has been released.
+    address_space: Option<AddressSpace>,
+    state: ProcessState,
+    exit_status: Option<ExitStatus>,
+}
+
+impl Process {
+    /// Returns the identifier of the process.
+    #[must_use]
+    pub const fn pid(&self) -> Pid {
+        self.pid
+    }
 
-/// Maps the user binary into memory and switches the CPU to Ring 3 execution.
+    /// Returns the identifier of the parent process, if any.
+    #[must_use]
+    pub const fn parent(&self) -> Option<Pid> {
+        self.parent
+    }
+
+    /// Returns the current scheduling state of the process.
+    #[must_use]
+    pub const fn state(&self) -> ProcessState {
+        self.state
+    }
+
+    /// Returns how the process terminated, if it has exited.
+    #[must_use]
+    pub const fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_status
+    }
+
+    /// Returns the exit code of the process, if it has exited. See
+    /// [`ExitStatus::code`].
+    #[must_use]
+    pub fn exit_code(&self) -> Option<u64> {
+        self.exit_status.map(ExitStatus::code)
+    }
+}
+
+/// Table of every process known to the kernel, indexed by PID.
+struct ProcessTable {
+    processes: BTreeMap<Pid, Process>,
+    current: Option<Pid>,
+}
+
+impl ProcessTable {
+    const fn new() -> Self {
+        Self {
+            processes: BTreeMap::new(),
+            current: None,
+        }
+    }
+
+    /// Returns the first ready process after `previous`, wrapping around to
+    /// the lowest PID so that processes are picked in round-robin order.
+    fn next_ready(&self, previous: Option<Pid>) -> Option<Pid> {
+        let is_ready = |process: &&Process| process.state == ProcessState::Ready;
+        let after = previous.map_or(0, Pid::as_u64);
+
+        self.processes
+            .range(Pid(after.saturating_add(1))..)
+            .map(|(_, process)| process)
+            .find(is_ready)
+            .or_else(|| self.processes.values().find(is_ready))
+            .map(Process::pid)
+    }
+
+    /// Returns `true` if a process sleeps, and will therefore become ready
+    /// again.
+    fn has_sleeping(&self) -> bool {
+        self.processes
+            .values()
+            .any(|process| matches!(process.state, ProcessState::Sleeping { .. }))
+    }
+
+    /// Returns the process currently executing in Ring 3, if any.
+    fn current_mut(&mut self) -> Option<&mut Process> {
+        let pid = self.current?;
+        self.processes.get_mut(&pid)
+    }
+
+    /// Marks the process `pid` as exited with `status`.
+    ///
+    /// A parent waiting for the process is made ready again. The children of
+    /// the process that have already exited are removed from the table, and
+    /// the others become orphans. Returns `false` if there is no such process
+    /// or if it has already exited.
+    fn terminate(&mut self, pid: Pid, status: ExitStatus) -> bool {
+        let Some(process) = self.processes.get_mut(&pid) else {
+            return false;
+        };
+        if process.state == ProcessState::Exited {
+            return false;
+        }
+        process.state = ProcessState::Exited;
+        process.exit_status = Some(status);
+        let parent = process.parent;
+
+        if let Some(waiting) = parent.and_then(|parent_pid| self.processes.get_mut(&parent_pid)) {
+            if waiting.state == ProcessState::Waiting {
+                waiting.state = ProcessState::Ready;
+            }
+        }
+        // Only the current process keeps its memory once it has exited, until
+        // it traps back to `run`, so the exited children hold no memory.
+        self.processes
+            .retain(|_, child| child.parent != Some(pid) || child.state != ProcessState::Exited);
+        for child in self.processes.values_mut() {
+            if child.parent == Some(pid) {
+                child.parent = None;
+                child.orphan = true;
+            }
+        }
+        true
+    }
+
+    /// Takes the memory of the exited process `pid`, to be released, and
+    /// removes the process from the table if it is an orphan.
+    fn release(&mut self, pid: Pid) -> Option<AddressSpace> {
+        let process = self.processes.get_mut(&pid)?;
+        let address_space = process.address_space.take();
+        if process.orphan {
+            self.processes.remove(&pid);
+        }
+        address_space
+    }
+}
+
+/// Runs `f` with exclusive access to the process table.
+///
+/// Interrupts are disabled while the table is locked so that an interrupt
+/// handler touching the table cannot deadlock with the interrupted code.
+fn with_table<R, F>(f: F) -> R
+where
+    F: FnOnce(&mut ProcessTable) -> R,
+{
+    interrupts::without_interrupts(|| f(&mut PROCESS_TABLE.lock()))
+}
+
+/// Errors that can occur while loading a user program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// The binary is not a valid ELF64 executable.
+    InvalidElf(ElfError),
+    /// A segment lies outside the user code region
+    /// ([`USER_CODE_START`](super::USER_CODE_START) to
+    /// [`USER_MMAP_START`](super::USER_MMAP_START)), or the program break is
+    /// moved outside the heap.
+    SegmentOutOfRange,
+    /// The frame allocator ran out of physical frames.
+    FrameAllocationFailed,
+    /// A page of the program is already mapped (e.g. overlapping segments).
+    PageAlreadyMapped,
+    /// A page could not be mapped because a huge page covers it.
+    HugePageConflict,
+    /// A page the loader writes to is not mapped in the address space.
+    PageNotMapped,
+}
+
+impl From<ElfError> for LoadError {
+    fn from(error: ElfError) -> Self {
+        Self::InvalidElf(error)
+    }
+}
+
+impl From<MapToError<Size4KiB>> for LoadError {
+    fn from(error: MapToError<Size4KiB>) -> Self {
+        match error {
+            MapToError::FrameAllocationFailed => Self::FrameAllocationFailed,
+            MapToError::ParentEntryHugePage => Self::HugePageConflict,
+            MapToError::PageAlreadyMapped(_) => Self::PageAlreadyMapped,
+        }
+    }
+}
+
+/// Loads an ELF executable into a new process and adds it to the process table.
 ///
 /// This function:
-/// 1. Maps the binary at [`USER_CODE_START`](super::USER_CODE_START) with user-accessible flags.
-///    Pages within the first `readonly_size` bytes are mapped read-only (W^X),
-///    while pages beyond that boundary are mapped writable for `.data`/`.bss`.
-/// 2. Allocates a user-mode stack below [`USER_STACK_TOP`](super::USER_STACK_TOP).
-/// 3. Performs an `iretq` to transition the CPU from Ring 0 to Ring 3.
+/// 1. Parses the ELF header and program headers of `binary`.
+/// 2. Creates a new address space with its own level 4 page table.
+/// 3. Maps every `PT_LOAD` segment with user-accessible flags matching its
+///    `p_flags` (W^X), zero-filling the bytes past `p_filesz` (`.bss`).
+/// 4. Reserves a user-mode stack below [`USER_STACK_TOP`](super::USER_STACK_TOP),
+///    whose pages are mapped on first touch.
+/// 5. Records the process as [`ProcessState::Ready`], starting at `e_entry`.
+///
+/// The image is written through the physical memory mapping, so the active
+/// page table is left untouched; the address space is only activated by
+/// [`run`] when the process is scheduled.
+///
+/// Frames are taken from the kernel frame allocator (see
+/// [`memory::with_frame_allocator`]). Frames allocated for a program that
+/// fails to load are given back to it.
 ///
 /// # Arguments
 ///
-/// * `binary` - The raw bytes of the flat user binary.
-/// * `readonly_size` - Number of bytes from the start of the binary that should
-///   be mapped read-only (`.text` + `.rodata`). Must be page-aligned (multiple
-///   of 4096). Pages beyond this offset are mapped writable.
-/// * `mapper` - The active page table mapper.
-/// * `frame_allocator` - A physical frame allocator.
+/// * `binary` - The raw bytes of the ELF64 user executable.
 ///
 /// # Errors
 ///
-/// Returns an error string if page mapping or frame allocation fails.
+/// Returns a [`LoadError`] if the executable is malformed, does not fit in the
+/// user code region, or if page mapping or frame allocation fails.
+pub fn spawn(binary: &[u8]) -> Result<Pid, LoadError> {
+    serial_println!("[kernel] loading user binary ({} bytes)...", binary.len());
+    println!("[kernel] loading user binary ({} bytes)...", binary.len());
+
+    let elf = ElfFile::parse(binary)?;
+    let address_space = new_address_space(&elf)?;
+
+    let pid = Pid::new();
+    let process = Process {
+        pid,
+        parent: None,
+        orphan: false,
+        context: Context::new_user(elf.entry(), userspace::USER_STACK_TOP),
+        address_space: Some(address_space),
+        state: ProcessState::Ready,
+        exit_status: None,
+    };
+    with_table(|table| table.processes.insert(pid, process));
+
+    serial_println!("[kernel] created process {}", pid);
+    println!("[kernel] created process {}", pid);
+
+    Ok(pid)
+}
+
+/// Executes the ready processes in Ring 3 until none is left.
+///
+/// Processes are picked in round-robin order. Each one runs until it invokes
+/// `sys_exit` or until its time slice expires while another process is ready
+/// (see [`scheduler`](super::scheduler)). In both cases the trap handler
+/// saves the user context and restores the kernel context saved by
+/// [`switch_to_user_mode`], and the next ready process is scheduled.
+///
+/// The page table of a process is loaded in `CR3` right before it enters
+/// Ring 3, and the kernel page table is restored once it traps back. When a
+/// process exits, its address space is released: every user frame and page
+/// table it owned is given back to the kernel frame allocator. Exited
+/// processes stay in the table so that their exit status can be queried with
+/// [`exit_status`], until they are removed with [`reap`].
+///
+/// Sleeping processes are not scheduled. When every process left sleeps, the
+/// CPU halts until a timer tick wakes one of them up.
 ///
 /// # Safety Considerations
 ///
-/// This function uses `iretq` to enter user mode and never returns to the caller.
 /// The caller must ensure that the GDT, TSS, and IDT (including the syscall
 /// handler at `int 0x80`) are fully initialized before calling this function.
-pub fn run(
-    binary: &[u8],
-    readonly_size: usize,
-    mapper: &mut impl Mapper<Size4KiB>,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) -> Result<(), &'static str> {
-    serial_println!("[kernel] loading user binary ({} bytes)...", binary.len());
-    println!("[kernel] loading user binary ({} bytes)...", binary.len());
+pub fn run() {
+    let mut previous = None;
 
-    map_user_binary(binary, readonly_size, mapper, frame_allocator)?;
-    map_user_stack(mapper, frame_allocator)?;
+    loop {
+        let Some(pid) = with_table(|table| table.next_ready(previous)) else {
+            if !with_table(|table| table.has_sleeping()) {
+                break;
+            }
+            // Every process left is asleep: idle until a timer tick wakes one
+            // up.
+            interrupts::enable_and_hlt();
+            continue;
+        };
+        previous = Some(pid);
 
-    serial_println!("[kernel] switching to user mode...");
-    println!("[kernel] switching to user mode...");
+        let scheduled = with_table(|table| {
+            table.current = Some(pid);
+            let process = table.processes.get_mut(&pid)?;
+            let address_space = process.address_space.as_ref()?;
+            process.state = ProcessState::Running;
 
-    let user_cs = gdt::user_code_selector();
-    let user_ds = gdt::user_data_selector();
+            // SAFETY:
+            //
+            // The address space stays in the process table until the process
+            // has exited and the kernel page table has been restored.
+            unsafe {
+                address_space.activate();
+            }
+            Some(process.context)
+        });
+        let Some(context) = scheduled else {
+            continue;
+        };
 
-    // SAFETY:
-    //
-    // The binary has been mapped at USER_CODE_START with USER_ACCESSIBLE pages.
-    // The user stack has been mapped below USER_STACK_TOP.
-    // The GDT contains valid Ring 3 code and data segments.
-    // The TSS has a valid RSP0 for kernel re-entry on interrupts.
-    //
-    // This call does not return until the user process invokes `sys_exit`,
-    // at which point the syscall handler restores the kernel RSP saved by
-    // `switch_to_user_mode` and execution resumes here.
-    unsafe {
-        switch_to_user_mode(
-            userspace::USER_CODE_START,
-            userspace::USER_STACK_TOP,
-            u64::from(user_cs.0),
-            u64::from(user_ds.0),
-        );
-    }
+        serial_println!("[kernel] switching to user mode (process {})...", pid);
+        scheduler::start_time_slice();
 
-    serial_println!("[kernel] user process exited, returning to kernel_main");
-    println!("[kernel] user process exited, returning to kernel_main");
+        // SAFETY:
+        //
+        // The address space of the process has just been activated: every
+        // PT_LOAD segment is mapped with USER_ACCESSIBLE pages, the entry point
+        // lies inside an executable segment and the user stack is mapped below
+        // USER_STACK_TOP.
+        // The context holds the Ring 3 code and data selectors from the GDT.
+        // The TSS has a valid RSP0 for kernel re-entry on interrupts.
+        //
+        // This call does not return until the user process invokes `sys_exit`
+        // or is preempted, at which point the trap handler restores the kernel
+        // RSP saved by `switch_to_user_mode` and execution resumes here.
+        unsafe {
+            switch_to_user_mode(&context);
+        }
 
-    Ok(())
+        memory::switch_to_kernel_level_4_table();
+
+        let released = with_table(|table| {
+            table.current = None;
+            let process = table.processes.get_mut(&pid)?;
+            match process.state {
+                ProcessState::Running => {
+                    process.state = ProcessState::Ready;
+                    None
+                }
+                ProcessState::Exited => table.release(pid),
+                ProcessState::Ready | ProcessState::Waiting | ProcessState::Sleeping { .. } => None,
+            }
+        });
+        if let Some(address_space) = released {
+            serial_println!(
+                "[kernel] released {} pages of process {}",
+                address_space.page_count(),
+                pid
+            );
+            memory::with_frame_allocator(|frame_allocator| address_space.free(frame_allocator));
+        }
+    }
 }
 
-/// Maps the flat binary bytes into user-accessible pages starting at
-/// [`USER_CODE_START`](super::USER_CODE_START).
+/// Loads the ELF executable `binary` into a new process, runs it along with
+/// the other ready processes until none is left, and returns how it
+/// terminated.
 ///
-/// All pages are initially mapped writable so the binary content can be
-/// copied in. After copying, pages within the first `readonly_size` bytes
-/// are remapped as read-only to enforce W^X (Write XOR Execute).
+/// The process is removed from the process table afterwards, so the same or
+/// another binary can be executed again without the table growing.
 ///
-/// # Arguments
+/// # Errors
 ///
-/// * `binary` - The raw bytes of the flat user binary.
-/// * `readonly_size` - Page-aligned byte count of the read-only region
-///   (`.text` + `.rodata`). Pages beyond this offset keep `WRITABLE`.
-/// * `mapper` - The active page table mapper.
-/// * `frame_allocator` - A physical frame allocator.
-fn map_user_binary(
-    binary: &[u8],
-    readonly_size: usize,
-    mapper: &mut impl Mapper<Size4KiB>,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) -> Result<(), &'static str> {
-    assert!(
-        readonly_size % 4096 == 0,
-        "readonly_size must be page-aligned"
-    );
+/// Returns a [`LoadError`] if the executable cannot be loaded (see [`spawn`]).
+pub fn execute(binary: &[u8]) -> Result<ExitStatus, LoadError> {
+    let pid = spawn(binary)?;
+    run();
+    // `run` only returns once every process has exited, so the process can
+    // only be missing if another caller reaped it concurrently.
+    Ok(reap(pid).unwrap_or(ExitStatus::Signaled(SIGKILL)))
+}
 
-    let start_addr = VirtAddr::new(userspace::USER_CODE_START);
-    let num_pages = (binary.len() as u64 + 4095) / 4096;
-    let readonly_pages = (readonly_size as u64) / 4096;
+/// Returns how the process `pid` terminated, if it has exited.
+#[must_use]
+pub fn exit_status(pid: Pid) -> Option<ExitStatus> {
+    with_table(|table| table.processes.get(&pid).and_then(Process::exit_status))
+}
 
-    // Phase 1: Map all pages as writable and copy binary content.
-    let writable_flags =
-        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+/// Returns the exit code of the process `pid`, if it has exited. See
+/// [`ExitStatus::code`].
+#[must_use]
+pub fn exit_code(pid: Pid) -> Option<u64> {
+    exit_status(pid).map(ExitStatus::code)
+}
 
-    for i in 0..num_pages {
-        let page: Page<Size4KiB> = Page::containing_address(start_addr + i * 4096);
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or("failed to allocate frame for user binary")?;
+/// Removes the exited process `pid` from the process table and returns its
+/// exit status.
+///
+/// Returns `None`, and leaves the table untouched, if there is no such
+/// process or if it has not exited yet.
+#[must_use]
+pub fn reap(pid: Pid) -> Option<ExitStatus> {
+    with_table(|table| {
+        let status = table.processes.get(&pid)?.exit_status?;
+        table.processes.remove(&pid);
+        Some(status)
+    })
+}
 
-        // SAFETY:
-        //
-        // The page is in the user address range and is not already mapped.
-        // The frame was freshly allocated by the frame allocator.
-        unsafe {
-            mapper
-                .map_to(page, frame, writable_flags, frame_allocator)
-                .map_err(|_| "failed to map user binary page")?
-                .flush();
+/// Terminates the process `pid` with [`ExitStatus::Signaled`] and `signal`.
+///
+/// The memory of a process that is not executing is released right away;
+/// the process currently executing in Ring 3, if `kill` is called from an
+/// interrupt handler, releases it when it traps back to [`run`]. Returns
+/// `false` if there is no such process or if it has already exited.
+#[must_use]
+pub fn kill(pid: Pid, signal: u64) -> bool {
+    let released = with_table(|table| {
+        if !table.terminate(pid, ExitStatus::Signaled(signal)) {
+            return None;
+        }
+        if table.current == Some(pid) {
+            return Some(None);
         }
+        Some(table.release(pid))
+    });
+    let Some(address_space) = released else {
+        return false;
+    };
 
-        // Copy binary content into the mapped page.
-        let page_start = (i * 4096) as usize;
-        let page_end = core::cmp::min(page_start + 4096, binary.len());
-        let dest_ptr = page.start_address().as_u64() as *mut u8;
+    if let Some(killed) = address_space {
+        memory::with_frame_allocator(|frame_allocator| killed.free(frame_allocator));
+    }
+    serial_println!("[kernel] process {} killed by signal {}", pid, signal);
+    true
+}
 
-        if page_start < binary.len() {
-            let chunk = &binary[page_start..page_end];
+/// Returns the number of processes in the process table, including the
+/// exited ones that have not been reaped yet.
+#[must_use]
+pub fn count() -> usize {
+    with_table(|table| table.processes.len())
+}
 
+/// Returns the PID of the process currently executing in Ring 3, if any.
+#[must_use]
+pub fn current_pid() -> Option<Pid> {
+    with_table(|table| table.current)
+}
+
+/// Runs `f` with the address space of the process currently executing in
+/// Ring 3.
+///
+/// Returns `None` if no user process is running. The process table stays
+/// locked while `f` runs; `f` may use the kernel frame allocator but must not
+/// access the process table.
+pub(crate) fn with_current_address_space<R, F>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut AddressSpace) -> R,
+{
+    with_table(|table| table.current_mut()?.address_space.as_mut().map(f))
+}
+
+/// Resolves a page fault at `addr` caused by `access` in the process
+/// currently executing in Ring 3, by mapping the page on demand.
+///
+/// Called by the page fault handler, and when the kernel checks user memory
+/// passed to a syscall.
+///
+/// # Errors
+///
+/// Returns a [`PageFaultError`] if the fault cannot be resolved, in which
+/// case the process must be terminated. [`PageFaultError::NotMapped`] is
+/// returned if no user process is running.
+pub(crate) fn handle_page_fault(addr: u64, access: Access) -> Result<(), PageFaultError> {
+    with_current_address_space(|address_space| {
+        memory::with_frame_allocator(|frame_allocator| {
+            address_space.handle_page_fault(addr, access, frame_allocator)
+        })
+    })
+    .unwrap_or(Err(PageFaultError::NotMapped))
+}
+
+/// Marks the process currently executing in Ring 3 as exited with `status`.
+///
+/// Called by the `sys_exit` syscall handler, and by exception handlers to
+/// terminate a faulting process. A parent waiting for the process is made
+/// ready again. The children of the process that have already exited are
+/// removed from the table, and the others become orphans. Returns the PID of
+/// the process that exited, or `None` if no user process is running.
+pub(crate) fn exit_current(status: ExitStatus) -> Option<Pid> {
+    with_table(|table| {
+        let pid = table.current?;
+        table.terminate(pid, status).then_some(pid)
+    })
+}
+
+/// Creates a child of the process currently executing in Ring 3, with a
+/// copy-on-write copy of its address space.
+///
+/// The child resumes from `context`, the context saved by the `sys_fork`
+/// syscall, with `0` as the result of the syscall.
+///
+/// Returns the PID of the child, or `None` if no user process is running.
+///
+/// # Errors
+///
+/// Returns a [`LoadError`] if the address space cannot be copied.
+pub(crate) fn fork_current(context: &Context) -> Option<Result<Pid, LoadError>> {
+    with_table(|table| {
+        let parent = table.current_mut()?;
+        let parent_pid = parent.pid;
+        let address_space = parent.address_space.as_mut()?;
+        let duplicated = memory::with_frame_allocator(|frame_allocator| {
+            address_space.duplicate(frame_allocator)
+        });
+
+        Some(duplicated.map(|address_space| {
+            let pid = Pid::new();
+            let child = Process {
+                pid,
+                parent: Some(parent_pid),
+                orphan: false,
+                context: Context { rax: 0, ..*context },
+                address_space: Some(address_space),
+                state: ProcessState::Ready,
+                exit_status: None,
+            };
+            table.processes.insert(pid, child);
+            serial_println!("[kernel] process {} forked process {}", parent_pid, pid);
+            pid
+        }))
+    })
+}
+
+/// Replaces the image of the process currently executing in Ring 3 with the
+/// ELF executable `binary`.
+///
+/// A new address space is loaded and activated, and the previous one is
+/// released. Returns the initial context of the new image, which the
+/// `sys_exec` syscall installs in place of the saved context of the process,
+/// or `None` if no user process is running.
+///
+/// # Errors
+///
+/// Returns a [`LoadError`] if the executable cannot be loaded. The process is
+/// left untouched in that case.
+pub(crate) fn exec_current(binary: &[u8]) -> Option<Result<Context, LoadError>> {
+    let elf = match ElfFile::parse(binary) {
+        Ok(elf) => elf,
+        Err(error) => return Some(Err(error.into())),
+    };
+    let address_space = match new_address_space(&elf) {
+        Ok(address_space) => address_space,
+        Err(error) => return Some(Err(error)),
+    };
+
+    let replaced = with_table(|table| match table.current_mut() {
+        Some(process) => {
             // SAFETY:
             //
-            // We just mapped this page as writable. The destination pointer is
-            // valid for `chunk.len()` bytes and does not overlap with `chunk`.
+            // The address space is stored in the process table, where it stays
+            // until the process has exited and the kernel page table has been
+            // restored.
             unsafe {
-                core::ptr::copy_nonoverlapping(chunk.as_ptr(), dest_ptr, chunk.len());
+                address_space.activate();
             }
+            Ok(process.address_space.replace(address_space))
+        }
+        None => Err(address_space),
+    });
+
+    memory::with_frame_allocator(|frame_allocator| match replaced {
+        Ok(previous) => {
+            if let Some(old_address_space) = previous {
+                old_address_space.free(frame_allocator);
+            }
+            Some(Ok(Context::new_user(
+                elf.entry(),
+                userspace::USER_STACK_TOP,
+            )))
+        }
+        Err(address_space) => {
+            address_space.free(frame_allocator);
+            None
+        }
+    })
+}
+
+/// Status of the children of the current process, reported by
+/// [`reap_child`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChildStatus {
+    /// The child exited with the given code and has been removed from the
+    /// process table.
+    Exited(Pid, u64),
+    /// No matching child has exited yet.
+    Running,
+    /// The process has no matching child.
+    NoChild,
+}
 
-            // Zero the remainder of the page if the chunk is smaller than 4 KiB.
-            if chunk.len() < 4096 {
-                // SAFETY:
-                //
-                // The rest of the page (after the copied bytes) is within the
-                // same mapped frame and is valid for writing.
-                unsafe {
-                    core::ptr::write_bytes(dest_ptr.add(chunk.len()), 0, 4096 - chunk.len());
+/// Removes an exited child of the process currently executing in Ring 3 from
+/// the process table.
+///
+/// Only the child `pid` is considered if it is not `None`. Returns `None` if
+/// no user process is running.
+pub(crate) fn reap_child(pid: Option<Pid>) -> Option<ChildStatus> {
+    with_table(|table| {
+        let parent = table.current?;
+        let mut children = table
+            .processes
+            .values()
+            .filter(|process| {
+                process.parent == Some(parent) && pid.is_none_or(|child| process.pid == child)
+            })
+            .peekable();
+
+        if children.peek().is_none() {
+            return Some(ChildStatus::NoChild);
+        }
+        let Some(exited) = children.find(|child| child.state == ProcessState::Exited) else {
+            return Some(ChildStatus::Running);
+        };
+
+        let child = exited.pid;
+        let code = exited.exit_code().unwrap_or_default();
+        table.processes.remove(&child);
+        Some(ChildStatus::Exited(child, code))
+    })
+}
+
+/// Saves `context` as the context of the process currently executing in
+/// Ring 3 and puts the process to sleep until one of its children exits.
+///
+/// Called by the wait syscalls when no child has exited yet; the process is
+/// resumed from `context` once a child exits.
+pub(crate) fn wait_current(context: &Context) {
+    with_table(|table| {
+        if let Some(process) = table.current_mut() {
+            process.context = *context;
+            process.state = ProcessState::Waiting;
+        }
+    });
+}
+
+/// Saves `context` as the context of the process currently executing in
+/// Ring 3 and puts the process to sleep until the monotonic clock reaches
+/// `until`.
+///
+/// Called by `sys_sleep`; the process is resumed from `context` by
+/// [`wake_sleepers`].
+pub(crate) fn sleep_current(context: &Context, until: Instant) {
+    with_table(|table| {
+        if let Some(process) = table.current_mut() {
+            process.context = *context;
+            process.state = ProcessState::Sleeping { until };
+        }
+    });
+}
+
+/// Makes the processes sleeping until `now` or earlier ready again.
+///
+/// Called by the timer interrupt handler with the current time.
+pub(crate) fn wake_sleepers(now: Instant) {
+    with_table(|table| {
+        for process in table.processes.values_mut() {
+            if let ProcessState::Sleeping { until } = process.state {
+                if until <= now {
+                    process.state = ProcessState::Ready;
                 }
             }
-        } else {
-            // SAFETY:
-            //
-            // The entire page is mapped and writable; zero-fill it.
-            unsafe {
-                core::ptr::write_bytes(dest_ptr, 0, 4096);
+        }
+    });
+}
+
+/// Saves the context of the running process and marks it ready, so that the
+/// next ready process can be scheduled.
+///
+/// Called by the timer interrupt handler when the time slice of the running
+/// process has expired, and by `sys_yield`. The process is only preempted if
+/// another process is ready; otherwise it keeps the CPU and `false` is
+/// returned.
+pub(crate) fn preempt_current(context: &Context) -> bool {
+    with_table(|table| {
+        let Some(pid) = table.current else {
+            return false;
+        };
+        if table.next_ready(Some(pid)).is_none() {
+            return false;
+        }
+        let Some(process) = table.processes.get_mut(&pid) else {
+            return false;
+        };
+        process.context = *context;
+        process.state = ProcessState::Ready;
+        true
+    })
+}
+
+/// Creates an address space holding the image of `elf`.
+///
+/// Frames allocated for a program that fails to load are given back to the
+/// kernel frame allocator.
+fn new_address_space(elf: &ElfFile<'_>) -> Result<AddressSpace, LoadError> {
+    memory::with_frame_allocator(|frame_allocator| {
+        let mut address_space = AddressSpace::new(frame_allocator)?;
+        match load_image(elf, &mut address_space, frame_allocator) {
+            Ok(()) => Ok(address_space),
+            Err(error) => {
+                address_space.free(frame_allocator);
+                Err(error)
             }
         }
+    })
+}
+
+/// Maps the executable image into `address_space`, reserves the user stack
+/// and starts the heap right after the image.
+fn load_image<A>(
+    elf: &ElfFile<'_>,
+    address_space: &mut AddressSpace,
+    frame_allocator: &mut A,
+) -> Result<(), LoadError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let mut image_end = userspace::USER_CODE_START;
+    for program_header in elf.load_segments() {
+        let segment = program_header?;
+        map_segment(elf, &segment, address_space, frame_allocator)?;
+        image_end = image_end.max(segment.memory_range().end);
+    }
+    address_space.set_heap_start(image_end);
+    reserve_user_stack(address_space);
+
+    Ok(())
+}
+
+/// Maps a single `PT_LOAD` segment into user-accessible pages.
+///
+/// All pages of the segment are mapped zeroed with the permissions requested
+/// by the segment: `WRITABLE` only for `PF_W` segments and `NO_EXECUTE` for
+/// segments without `PF_X`, enforcing W^X. The file bytes are then copied in
+/// through the physical memory mapping; the bytes between `p_filesz` and
+/// `p_memsz` are therefore already zero-filled (`.bss`).
+///
+/// # Arguments
+///
+/// * `elf` - The parsed executable the segment belongs to.
+/// * `segment` - The segment to map.
+/// * `address_space` - The address space of the process being loaded.
+/// * `frame_allocator` - A physical frame allocator.
+fn map_segment<A>(
+    elf: &ElfFile<'_>,
+    segment: &LoadSegment,
+    address_space: &mut AddressSpace,
+    frame_allocator: &mut A,
+) -> Result<(), LoadError>
+where
+    A: FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>,
+{
+    let range = segment.memory_range();
+    if range.start < userspace::USER_CODE_START || range.end > userspace::USER_MMAP_START {
+        return Err(LoadError::SegmentOutOfRange);
+    }
+    if range.is_empty() {
+        return Ok(());
     }
 
-    // Phase 2: Remap read-only pages (code + rodata) without WRITABLE.
-    // This enforces W^X: these pages remain executable but can no longer be
-    // written to, preventing the program from modifying its own code.
-    let readonly_flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let data = elf.segment_data(segment)?;
+    let first_page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(range.start));
+    let last_page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(range.end - 1));
+    let pages = Page::range_inclusive(first_page, last_page);
 
-    for i in 0..core::cmp::min(readonly_pages, num_pages) {
-        let page: Page<Size4KiB> = Page::containing_address(start_addr + i * 4096);
+    let mut flags = PageTableFlags::empty();
+    if segment.flags.writable {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if !segment.flags.executable {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
 
-        // SAFETY:
-        //
-        // The page was mapped in Phase 1 and is still valid. We are only
-        // changing the flags to remove WRITABLE.
-        unsafe {
-            mapper
-                .update_flags(page, readonly_flags)
-                .map_err(|_| "failed to update flags for read-only page")?
-                .flush();
-        }
+    for page in pages {
+        address_space.map_page(page, flags, frame_allocator)?;
     }
 
+    // Copy the file-backed part of the segment.
+    address_space.write(VirtAddr::new(range.start), data)?;
+
     serial_println!(
-        "[kernel] mapped {} pages for user binary at {:#x} ({} read-only, {} writable)",
-        num_pages,
-        userspace::USER_CODE_START,
-        core::cmp::min(readonly_pages, num_pages),
-        num_pages.saturating_sub(readonly_pages),
-    );
-    println!(
-        "[kernel] mapped {} pages for user binary at {:#x} ({} read-only, {} writable)",
-        num_pages,
-        userspace::USER_CODE_START,
-        core::cmp::min(readonly_pages, num_pages),
-        num_pages.saturating_sub(readonly_pages),
+        "[kernel] mapped segment {:#x}-{:#x} ({} pages, {}{}{})",
+        range.start,
+        range.end,
+        pages.count(),
+        if segment.flags.readable { 'r' } else { '-' },
+        if segment.flags.writable { 'w' } else { '-' },
+        if segment.flags.executable { 'x' } else { '-' },
     );
 
     Ok(())
 }
 
-/// Allocates and maps user-accessible stack pages in the range
+/// Reserves the user stack area, from
 /// [`USER_STACK_BOTTOM`](super::USER_STACK_BOTTOM) to
-/// [`USER_STACK_TOP`](super::USER_STACK_TOP).
-fn map_user_stack(
-    mapper: &mut impl Mapper<Size4KiB>,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) -> Result<(), &'static str> {
-    let flags =
-        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
-
-    let num_pages = userspace::USER_STACK_SIZE / 4096;
-    let stack_start = VirtAddr::new(userspace::USER_STACK_BOTTOM);
-
-    for i in 0..num_pages {
-        let page: Page<Size4KiB> = Page::containing_address(stack_start + i * 4096);
-        let frame = frame_allocator
-            .allocate_frame()
-            .ok_or("failed to allocate frame for user stack")?;
-
-        // SAFETY:
-        //
-        // The page is in the user stack range and is not already mapped.
-        // The frame was freshly allocated by the frame allocator.
-        unsafe {
-            mapper
-                .map_to(page, frame, flags, frame_allocator)
-                .map_err(|_| "failed to map user stack page")?
-                .flush();
-        }
-
-        // Zero-initialize the stack page.
-        let dest_ptr = page.start_address().as_u64() as *mut u8;
-
-        // SAFETY:
-        //
-        // The page is mapped and writable; zero-fill for a clean stack.
-        unsafe {
-            core::ptr::write_bytes(dest_ptr, 0, 4096);
-        }
-    }
+/// [`USER_STACK_TOP`](super::USER_STACK_TOP). Its pages are mapped by the page
+/// fault handler as the stack grows.
+fn reserve_user_stack(address_space: &mut AddressSpace) {
+    address_space.reserve_stack();
 
     serial_println!(
-        "[kernel] mapped {} stack pages at {:#x}-{:#x}",
-        num_pages,
+        "[kernel] reserved stack at {:#x}-{:#x}",
         userspace::USER_STACK_BOTTOM,
         userspace::USER_STACK_TOP,
     );
     println!(
-        "[kernel] mapped {} stack pages at {:#x}-{:#x}",
-        num_pages,
+        "[kernel] reserved stack at {:#x}-{:#x}",
         userspace::USER_STACK_BOTTOM,
         userspace::USER_STACK_TOP,
     );
-
-    Ok(())
 }
 
 /// Performs the actual transition from Ring 0 to Ring 3 via `iretq`.
 ///
-/// Before executing `iretq`, this function saves all callee-saved registers
-/// and the kernel RSP into [`KERNEL_RSP`]. When the user process calls
-/// `sys_exit`, the syscall handler restores the saved RSP, pops the
-/// callee-saved registers, and executes `ret`, causing this function to
-/// return normally to its caller.
+/// Before executing `iretq`, this function saves RFLAGS, all callee-saved
+/// registers and the kernel RSP into [`KERNEL_RSP`]. It then loads every register from
+/// `context` and returns to user mode. When the user process calls
+/// `sys_exit` or is preempted, the trap handler jumps to [`return_to_kernel`],
+/// which restores the saved RSP, pops the callee-saved registers and RFLAGS,
+/// and executes `ret`, causing this function to return normally to its caller.
 ///
 /// # Arguments
 ///
 /// Uses the System V AMD64 calling convention (naked function):
-/// * `rdi` - The virtual address of the user binary entry point.
-/// * `rsi` - The top of the user-mode stack (initial RSP in Ring 3).
-/// * `rdx` - The user code segment selector (with RPL=3).
-/// * `rcx` - The user data segment selector (with RPL=3).
+/// * `rdi` - Pointer to the [`Context`] to restore.
 ///
 /// # Safety
 ///
 /// The caller must guarantee that:
-/// - `entry_point` points to valid, executable, user-accessible code.
-/// - `user_stack` points to a valid, writable, user-accessible stack region.
-/// - `user_cs` and `user_ds` are valid Ring 3 segment selectors in the GDT.
+/// - `context.rip` points to valid, executable, user-accessible code.
+/// - `context.rsp` points to a valid, writable, user-accessible stack region.
+/// - `context.cs` and `context.ss` are valid Ring 3 segment selectors in the GDT.
 /// - The TSS `RSP0` is set to a valid kernel stack for interrupt re-entry.
 ///
-/// This function blocks until the user process calls `sys_exit`.
+/// This function blocks until the user process calls `sys_exit` or is preempted.
 #[naked]
-unsafe extern "C" fn switch_to_user_mode(
-    _entry_point: u64,
-    _user_stack: u64,
-    _user_cs: u64,
-    _user_ds: u64,
-) {
+unsafe extern "C" fn switch_to_user_mode(_context: *const Context) {
     // SAFETY:
     //
     // This naked function manually manages the entire stack layout.
     // On entry (System V AMD64 ABI):
-    //   rdi = entry_point
-    //   rsi = user_stack
-    //   rdx = user_cs
-    //   rcx = user_ds
+    //   rdi = pointer to the context to restore
     //   [rsp] = return address to caller (process::run)
     //
     // We save all callee-saved registers so the caller's state is preserved
     // when sys_exit restores the kernel RSP and executes `ret`.
     unsafe {
         core::arch::naked_asm!(
+            // Save the kernel RFLAGS: traps clear IF, so they must be restored
+            // explicitly when returning to the kernel.
+            "pushfq",
+
             // Save callee-saved registers (System V ABI).
             "push rbx",
             "push rbp",
@@ -335,36 +1009,117 @@ unsafe extern "C" fn switch_to_user_mode(
             // the original kernel stack.
             "mov [{kernel_rsp}], rsp",
 
-            // Load user data segment (rcx) into all data segment registers.
-            "mov ax, cx",
+            // Use the context as the stack: its layout matches the one built
+            // by the syscall entry point (GPRs followed by an iretq frame).
+            "mov rsp, rdi",
+
+            // Load the user data segment (saved SS) into all data segment registers.
+            "mov ax, [rsp + 0x98]",
             "mov ds, ax",
             "mov es, ax",
             "mov fs, ax",
             "mov gs, ax",
 
-            // Build an iretq frame on the stack:
-            //   push SS      (user data segment)
-            //   push RSP     (user stack pointer)
-            //   push RFLAGS  (with IF set to enable interrupts)
-            //   push CS      (user code segment)
-            //   push RIP     (user entry point)
-            "push rax",       // SS = user data selector
-            "push rsi",       // RSP = user stack top
-            "pushfq",
-            "pop r11",
-            "or r11, 0x200",  // set IF (Interrupt Flag) so interrupts work in user mode
-            "push r11",       // RFLAGS
-            "push rdx",       // CS = user code selector
-            "push rdi",       // RIP = entry point
+            // Restore all general-purpose registers.
+            pop_context!(),
 
+            // Pops RIP, CS, RFLAGS, RSP and SS from the context.
             "iretq",
 
             // Execution never reaches here via iretq.
-            // When sys_exit fires, the syscall handler restores RSP from
-            // KERNEL_RSP, pops r15..rbx, restores kernel segments, and
-            // executes `ret` — which returns to the caller of this function.
+            // When the process exits or is preempted, the trap handler jumps
+            // to `return_to_kernel`, which restores RSP from KERNEL_RSP, pops
+            // r15..rbx and RFLAGS, restores kernel segments, and executes
+            // `ret` — which returns to the caller of this function.
 
             kernel_rsp = sym KERNEL_RSP,
         );
     }
 }
+
+/// Abandons the current trap stack and resumes the kernel context saved by
+/// [`switch_to_user_mode`], making that call return to [`run`].
+///
+/// Trap entry points jump here (instead of returning with `iretq`) once the
+/// user context has been saved or the process has exited.
+///
+/// # Safety
+///
+/// Must only be jumped to from a trap taken while a process started by
+/// [`switch_to_user_mode`] was running, so that [`KERNEL_RSP`] is valid.
+#[naked]
+pub(crate) unsafe extern "C" fn return_to_kernel() -> ! {
+    // SAFETY:
+    //
+    // The current stack (TSS RSP0) is abandoned. We load the kernel RSP that
+    // was saved before iretq, pop the callee-saved registers and RFLAGS that
+    // switch_to_user_mode pushed, restore kernel data segments, and ret back
+    // into process::run.
+    unsafe {
+        core::arch::naked_asm!(
+            "mov rsp, [{kernel_rsp}]",
+
+            // Pop callee-saved registers (reverse of switch_to_user_mode pushes).
+            "pop r15",
+            "pop r14",
+            "pop r13",
+            "pop r12",
+            "pop rbp",
+            "pop rbx",
+
+            // Restore kernel data segments (long mode typically uses 0).
+            "xor ax, ax",
+            "mov ds, ax",
+            "mov es, ax",
+            "mov fs, ax",
+            "mov gs, ax",
+
+            // Restore the kernel RFLAGS (including IF) saved by switch_to_user_mode.
+            "popfq",
+
+            // Return from switch_to_user_mode back to process::run.
+            "ret",
+
+            kernel_rsp = sym KERNEL_RSP,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_pids_are_unique_and_increasing() {
+        let first = Pid::new();
+        let second = Pid::new();
+        assert!(
+            first < second,
+            "PIDs should be allocated in increasing order."
+        );
+        assert!(
+            first.as_u64() >= Pid::FIRST,
+            "PIDs should start at Pid::FIRST."
+        );
+    }
+
+    #[test_case]
+    fn test_exit_status_codes_follow_the_signal_convention() {
+        assert_eq!(ExitStatus::Exited(3).code(), 3, "sys_exit code.");
+        assert_eq!(ExitStatus::Signaled(SIGKILL).code(), 137, "SIGKILL.");
+        let fault = ExitStatus::Faulted {
+            exception: Exception::PageFault,
+            address: 0x70_0000,
+        };
+        assert_eq!(fault.code(), 139, "SIGSEGV.");
+    }
+
+    #[test_case]
+    fn test_exit_without_current_process_is_ignored() {
+        assert_eq!(
+            exit_current(ExitStatus::Exited(0)),
+            None,
+            "sys_exit outside of a user process should not mark any process.",
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//! Registry of the programs that user processes can execute.
+//!
+//! There is no filesystem yet: the kernel embeds its user programs and
+//! registers each of them under a name with [`register`]. `sys_exec` looks a
+//! program up by name with [`lookup`] and loads it in place of the image of
+//! the calling process.
+
+use alloc::collections::btree_map::BTreeMap;
+
+use spin::Mutex;
+
+/// Registered programs, indexed by name.
+static PROGRAMS: Mutex<BTreeMap<&'static str, &'static [u8]>> = Mutex::new(BTreeMap::new());
+
+/// Registers the ELF executable `binary` under `name`, replacing any program
+/// previously registered under that name.
+pub fn register(name: &'static str, binary: &'static [u8]) {
+    PROGRAMS.lock().insert(name, binary);
+}
+
+/// Returns the ELF executable registered under `name`.
+#[must_use]
+pub fn lookup(name: &str) -> Option<&'static [u8]> {
+    PROGRAMS.lock().get(name).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_registered_programs_can_be_looked_up() {
+        static BINARY: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+        register("test-program", &BINARY);
+        assert_eq!(
+            lookup("test-program"),
+            Some(&BINARY[..]),
+            "A registered program must be found by its name.",
+        );
+        assert_eq!(
+            lookup("missing-program"),
+            None,
+            "An unknown name must not match any program.",
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Preemptive round-robin scheduling of user processes.
+//!
+//! The timer interrupt (PIT, IRQ 0) calls [`timer_tick`] on every tick. Each
+//! process started by [`process::run`](super::process::run) receives a time
+//! slice of [`time_slice`] ticks; once it is used up and another process is
+//! ready, the interrupted Ring 3 context is saved in the process table and
+//! control returns to the kernel, which switches to the next ready process.
+//! A user program that spins forever therefore no longer freezes the machine.
+//!
+//! Processes blocked in `sys_sleep` are kept off the run queue until the
+//! [monotonic clock](crate::time) passes their deadline; the first tick after
+//! it makes them ready again.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    time,
+    userspace::{context::Context, process},
+};
+
+/// Default length of a time slice, in timer ticks.
+///
+/// The timer fires [`time::DEFAULT_FREQUENCY`] (100) times per second, so ten
+/// ticks give each process 100 ms of CPU time before it can be preempted.
+pub const DEFAULT_TIME_SLICE: u64 = 10;
+
+/// Length of a time slice, in timer ticks.
+static TIME_SLICE: AtomicU64 = AtomicU64::new(DEFAULT_TIME_SLICE);
+
+/// Number of ticks left in the time slice of the running process.
+static SLICE_REMAINING: AtomicU64 = AtomicU64::new(DEFAULT_TIME_SLICE);
+
+/// Sets the length of a time slice, in timer ticks.
+///
+/// A slice is always at least one tick long; `0` is treated as `1`. The new
+/// length applies from the next time slice on.
+pub fn set_time_slice(ticks: u64) {
+    TIME_SLICE.store(ticks.max(1), Ordering::Relaxed);
+}
+
+/// Returns the length of a time slice, in timer ticks.
+#[must_use]
+pub fn time_slice() -> u64 {
+    TIME_SLICE.load(Ordering::Relaxed)
+}
+
+/// Starts a new time slice for the process about to enter Ring 3.
+pub(crate) fn start_time_slice() {
+    SLICE_REMAINING.store(time_slice(), Ordering::Relaxed);
+}
+
+/// Accounts for one timer tick and decides whether the interrupted process
+/// must be preempted.
+///
+/// The sleeping processes whose deadline has passed are woken up, whatever
+/// code the tick interrupted.
+///
+/// Returns `true` if the context of the running process has been saved and
+/// the trap handler must return to the kernel instead of resuming `context`.
+/// Ticks that interrupt kernel code never preempt anything.
+pub(crate) fn timer_tick(context: &Context) -> bool {
+    process::wake_sleepers(time::now());
+
+    if !context.is_user() {
+        return false;
+    }
+
+    let remaining = SLICE_REMAINING.load(Ordering::Relaxed).saturating_sub(1);
+    if remaining > 0 {
+        SLICE_REMAINING.store(remaining, Ordering::Relaxed);
+        return false;
+    }
+
+    // The slice has expired: either hand the CPU over to another process,
+    // or let the current one continue with a fresh slice.
+    start_time_slice();
+    process::preempt_current(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_time_slice_is_at_least_one_tick() {
+        set_time_slice(0);
+        assert_eq!(
+            time_slice(),
+            1,
+            "A time slice of 0 ticks should be clamped to 1."
+        );
+        set_time_slice(DEFAULT_TIME_SLICE);
+    }
+
+    #[test_case]
+    fn test_kernel_ticks_never_preempt() {
+        let kernel_context = Context::default();
+        assert!(
+            !timer_tick(&kernel_context),
+            "A tick interrupting kernel code must not preempt anything.",
+        );
+    }
+}
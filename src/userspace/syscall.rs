@@ -1,197 +1,287 @@
 //! Syscall handler module.
 //!
-//! Provides the interrupt-based syscall interface for user-mode programs.
-//! User programs invoke syscalls via `int 0x80` with the following register convention:
+//! Provides the syscall interface for user-mode programs. User programs invoke
+//! syscalls either via `int 0x80` or with the `syscall` instruction, using the
+//! following register convention:
 //!
 //! - `rax`: syscall number
 //! - `rdi`: first argument
 //! - `rsi`: second argument
 //! - `rdx`: third argument
 //!
-//! The return value is placed in `rax`.
+//! The return value is placed in `rax`. The `syscall` instruction also
+//! clobbers `rcx` and `r11`, which hold the return address and `RFLAGS` while
+//! in the kernel.
 
-use core::arch::naked_asm;
+use core::{
+    arch::naked_asm,
+    str,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
-use x86_64::{structures::idt::InterruptStackFrame, VirtAddr};
+use abi::{Timespec, CLOCK_MONOTONIC, PROGRAM_NAME_MAX};
+use x86_64::{
+    registers::{
+        model_specific::{Efer, EferFlags, LStar, SFMask, Star},
+        rflags::RFlags,
+    },
+    structures::idt::InterruptStackFrame,
+    VirtAddr,
+};
 
-use crate::{print, println, serial_println, userspace};
+use crate::{
+    gdt, memory, pop_context, print, println, push_context, serial_println,
+    time::{self, Instant},
+    userspace::{
+        self,
+        context::Context,
+        errno::{self, Errno, SyscallResult},
+        process::{self, ChildStatus, ExitStatus, Pid},
+        programs,
+        user_ptr::{copy_from_user, UserPtr, UserSlice},
+    },
+};
 
-/// Syscall number for `sys_exit`: terminates the current user process.
-pub const SYS_EXIT: u64 = 0;
+/// Syscall numbers, defined by the [`abi`] crate shared with user programs.
+pub use abi::{
+    SYS_BRK, SYS_CLOCK_GETTIME, SYS_EXEC, SYS_EXIT, SYS_FORK, SYS_MMAP, SYS_MUNMAP, SYS_SLEEP,
+    SYS_TIME, SYS_WAIT, SYS_WAITPID, SYS_WRITE, SYS_YIELD,
+};
 
-/// Syscall number for `sys_write`: writes a buffer to the VGA text display.
-pub const SYS_WRITE: u64 = 1;
+/// Length of both the `syscall` and the `int 0x80` instructions. A syscall is
+/// restarted by moving the saved `rip` back by this length.
+const SYSCALL_INSTRUCTION_LEN: u64 = 2;
+
+/// Value of `SFMASK`: `RFLAGS` bits cleared by `SYSCALL`.
+///
+/// Interrupts stay disabled until the kernel stack is installed, and the
+/// direction and trap flags are reset for the kernel, like with an interrupt
+/// gate.
+const SYSCALL_RFLAGS_MASK: RFlags = RFlags::INTERRUPT_FLAG
+    .union(RFlags::TRAP_FLAG)
+    .union(RFlags::DIRECTION_FLAG)
+    .union(RFlags::ALIGNMENT_CHECK);
+
+/// User RSP saved by [`fast_syscall_entry`] while it switches to the kernel
+/// stack (`SYSCALL` does not switch stacks).
+static SYSCALL_USER_RSP: AtomicU64 = AtomicU64::new(0);
+
+/// Kernel stack loaded by [`fast_syscall_entry`], the `RSP0` of the TSS
+/// aligned on 16 bytes.
+static SYSCALL_KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
 
 /// Naked entry point for the `int 0x80` syscall interrupt.
 ///
-/// This function saves all general-purpose registers, extracts the syscall
-/// arguments from the saved register state, calls the Rust dispatch function,
-/// and then either returns to user mode via `iretq` or halts if the process
-/// exited.
-///
-/// # Register layout on the stack after all pushes
-///
-/// ```text
-/// rsp + 0x00 : r15
-/// rsp + 0x08 : r14
-/// rsp + 0x10 : r13
-/// rsp + 0x18 : r12
-/// rsp + 0x20 : r11
-/// rsp + 0x28 : r10
-/// rsp + 0x30 : r9
-/// rsp + 0x38 : r8
-/// rsp + 0x40 : rbp
-/// rsp + 0x48 : rdi  (arg1)
-/// rsp + 0x50 : rsi  (arg2)
-/// rsp + 0x58 : rdx  (arg3)
-/// rsp + 0x60 : rcx
-/// rsp + 0x68 : rbx
-/// rsp + 0x70 : rax  (syscall number)
-/// rsp + 0x78 : RIP  (pushed by CPU)
-/// rsp + 0x80 : CS
-/// rsp + 0x88 : RFLAGS
-/// rsp + 0x90 : RSP
-/// rsp + 0x98 : SS
-/// ```
+/// This function saves all general-purpose registers as a
+/// [`Context`](super::context::Context) and calls [`syscall_handler`]. It then
+/// either returns to user mode via `iretq` or, if the process exited, returns
+/// to the kernel scheduler.
 #[naked]
 pub(crate) extern "x86-interrupt" fn syscall_entry(_frame: InterruptStackFrame) {
     // SAFETY:
     //
     // This naked function manually manages the entire register save/restore
     // and stack layout. The CPU has already pushed SS, RSP, RFLAGS, CS, RIP
-    // before entering this handler. We save all GPRs, set up the C calling
-    // convention arguments, and call the Rust handler.
+    // before entering this handler.
     unsafe {
         naked_asm!(
             // Save all general-purpose registers.
-            "push rax",
-            "push rbx",
-            "push rcx",
-            "push rdx",
-            "push rsi",
-            "push rdi",
-            "push rbp",
-            "push r8",
-            "push r9",
-            "push r10",
-            "push r11",
-            "push r12",
-            "push r13",
-            "push r14",
-            "push r15",
-
-            // Set up arguments for the Rust syscall dispatcher:
-            //   rdi = syscall number (was in rax)
-            //   rsi = arg1 (was in rdi)
-            //   rdx = arg2 (was in rsi)
-            //   rcx = arg3 (was in rdx)
-            "mov rdi, [rsp + 0x70]",  // saved rax -> syscall number
-            "mov rsi, [rsp + 0x48]",  // saved rdi -> arg1
-            "mov rdx, [rsp + 0x50]",  // saved rsi -> arg2
-            "mov rcx, [rsp + 0x58]",  // saved rdx -> arg3
-
-            "call {dispatch}",
-
-            // Check if the process should exit (magic sentinel value).
-            "mov rcx, {sentinel}",
-            "cmp rax, rcx",
-            "je 2f",
-
-            // Normal return: store result in the saved rax slot.
-            "mov [rsp + 0x70], rax",
-
-            // Restore all general-purpose registers.
-            "pop r15",
-            "pop r14",
-            "pop r13",
-            "pop r12",
-            "pop r11",
-            "pop r10",
-            "pop r9",
-            "pop r8",
-            "pop rbp",
-            "pop rdi",
-            "pop rsi",
-            "pop rdx",
-            "pop rcx",
-            "pop rbx",
-            "pop rax",
+            push_context!(),
+
+            // rdi = pointer to the saved context.
+            "mov rdi, rsp",
+            "call {handler}",
+
+            // The handler returns `true` if the process exited: the current
+            // stack (TSS RSP0) is abandoned and the kernel context saved by
+            // switch_to_user_mode is restored.
+            "test al, al",
+            "jnz {return_to_kernel}",
+
+            // Restore all general-purpose registers, including the result
+            // stored in the saved rax slot.
+            pop_context!(),
             "iretq",
 
-            // Process exit path: restore kernel context saved by switch_to_user_mode.
-            //
-            // The current stack (TSS RSP0) is abandoned. We load the kernel RSP
-            // that was saved before iretq, pop the callee-saved registers that
-            // switch_to_user_mode pushed, restore kernel data segments, and ret
-            // back into process::run.
-            "2:",
-            "mov rsp, [{kernel_rsp}]",
-
-            // Pop callee-saved registers (reverse of switch_to_user_mode pushes).
-            "pop r15",
-            "pop r14",
-            "pop r13",
-            "pop r12",
-            "pop rbp",
-            "pop rbx",
-
-            // Restore kernel data segments (long mode typically uses 0).
-            "xor ax, ax",
-            "mov ds, ax",
-            "mov es, ax",
-            "mov fs, ax",
-            "mov gs, ax",
-
-            // Return from switch_to_user_mode back to process::run.
-            "ret",
-
-            dispatch = sym syscall_dispatch,
-            kernel_rsp = sym super::process::KERNEL_RSP,
-            sentinel = const PROCESS_EXIT_SENTINEL,
+            handler = sym syscall_handler,
+            return_to_kernel = sym process::return_to_kernel,
+        );
+    }
+}
+
+/// Naked entry point for the `syscall` instruction (`LSTAR`).
+///
+/// `SYSCALL` saves the user RIP in `rcx` and RFLAGS in `r11`, loads the kernel
+/// code and stack selectors and masks RFLAGS with `SFMASK`, but does not
+/// switch stacks. This function switches to the kernel stack and builds the
+/// same [`Context`](super::context::Context) as [`syscall_entry`], so that
+/// both paths share [`syscall_handler`] and a process entered through either
+/// of them can be preempted or resumed with `iretq`. It returns to user mode
+/// with `sysretq`, which clobbers `rcx` and `r11` as documented by the ABI.
+#[naked]
+unsafe extern "C" fn fast_syscall_entry() {
+    // SAFETY:
+    //
+    // This naked function manually manages the entire register save/restore
+    // and stack layout. Interrupts are disabled by SFMASK until sysretq, so
+    // the scratch statics cannot be overwritten by a nested syscall.
+    unsafe {
+        naked_asm!(
+            // Switch to the kernel stack.
+            "mov [rip + {user_rsp}], rsp",
+            "mov rsp, [rip + {kernel_rsp}]",
+
+            // Build the frame an interrupt would have pushed.
+            "push {user_ss}",
+            "push qword ptr [rip + {user_rsp}]",
+            "push r11",
+            "push {user_cs}",
+            "push rcx",
+
+            // Save all general-purpose registers.
+            push_context!(),
+
+            // rdi = pointer to the saved context.
+            "mov rdi, rsp",
+            "call {handler}",
+
+            "test al, al",
+            "jnz {return_to_kernel}",
+
+            pop_context!(),
+
+            // sysretq loads RIP from rcx and RFLAGS from r11. The saved RIP
+            // is the one of the syscall instruction, so it is canonical.
+            "mov rcx, [rsp]",
+            "mov r11, [rsp + 0x10]",
+            "mov rsp, [rsp + 0x18]",
+            "sysretq",
+
+            user_rsp = sym SYSCALL_USER_RSP,
+            kernel_rsp = sym SYSCALL_KERNEL_RSP,
+            user_ss = const gdt::USER_DATA_SELECTOR.0,
+            user_cs = const gdt::USER_CODE_SELECTOR.0,
+            handler = sym syscall_handler,
+            return_to_kernel = sym process::return_to_kernel,
         );
     }
 }
 
-/// Sentinel value returned by [`syscall_dispatch`] to signal that the current
-/// process has called `sys_exit` and execution should not return to user mode.
-const PROCESS_EXIT_SENTINEL: u64 = u64::MAX;
+/// Handles a syscall trapped by [`syscall_entry`] or [`fast_syscall_entry`].
+///
+/// Calls [`syscall_dispatch`] with the saved user registers and stores the
+/// encoded result in the saved `rax`, or installs the new context of a process
+/// that executed another program. Returns `true` if the process exited or
+/// sleeps and the kernel scheduler must run instead of returning to user mode.
+extern "C" fn syscall_handler(context: &mut Context) -> bool {
+    match syscall_dispatch(context) {
+        SyscallOutcome::Return(result) => {
+            context.rax = errno::encode_result(result);
+            false
+        }
+        SyscallOutcome::Replace(new_context) => {
+            *context = new_context;
+            false
+        }
+        SyscallOutcome::Wait => {
+            // The syscall is restarted once a child has exited.
+            process::wait_current(&Context {
+                rip: context.rip - SYSCALL_INSTRUCTION_LEN,
+                ..*context
+            });
+            true
+        }
+        SyscallOutcome::Yield => {
+            context.rax = 0;
+            process::preempt_current(context)
+        }
+        SyscallOutcome::Sleep(until) => {
+            context.rax = 0;
+            process::sleep_current(context, until);
+            true
+        }
+        SyscallOutcome::Exit => true,
+    }
+}
 
-/// Error value returned to the user program when a syscall number is unknown.
-const SYSCALL_ERROR: u64 = u64::MAX - 1;
+/// What happens to the calling process once a syscall has been handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SyscallOutcome {
+    /// The process resumes with the result in `rax`.
+    Return(SyscallResult),
+    /// The process resumes from a new context, after `sys_exec`.
+    Replace(Context),
+    /// The process sleeps until one of its children exits, then restarts the
+    /// syscall.
+    Wait,
+    /// The process gives the CPU up to another ready process, if any.
+    Yield,
+    /// The process sleeps until the monotonic clock reaches the given time.
+    Sleep(Instant),
+    /// The process has exited and never returns to user mode.
+    Exit,
+}
 
 /// Dispatches a syscall to the appropriate handler based on the syscall number.
 ///
-/// This function is called from the naked [`syscall_entry`] with the System V
-/// AMD64 calling convention.
+/// This function is called by [`syscall_handler`] for both syscall entry
+/// points.
 ///
 /// # Arguments
 ///
-/// * `num` - The syscall number (from user `rax`).
-/// * `arg1` - First argument (from user `rdi`).
-/// * `arg2` - Second argument (from user `rsi`).
-/// * `arg3` - Third argument (from user `rdx`).
+/// * `context` - The saved user registers: the syscall number is in `rax`,
+///   the arguments in `rdi`, `rsi` and `rdx`.
 ///
 /// # Returns
 ///
-/// The syscall return value, or [`PROCESS_EXIT_SENTINEL`] to signal process exit.
-extern "C" fn syscall_dispatch(num: u64, arg1: u64, arg2: u64, _arg3: u64) -> u64 {
-    match num {
+/// The result of the syscall, or what else happens to the process. Unknown
+/// syscall numbers fail with [`Errno::ENOSYS`].
+fn syscall_dispatch(context: &Context) -> SyscallOutcome {
+    let (num, arg1, arg2) = (context.rax, context.rdi, context.rsi);
+    let result = match num {
         SYS_EXIT => {
+            process::exit_current(ExitStatus::Exited(arg1));
             serial_println!("[kernel] user process exited with code: {}", arg1);
             println!("[kernel] user process exited with code: {}", arg1);
-            PROCESS_EXIT_SENTINEL
+            return SyscallOutcome::Exit;
         }
         SYS_WRITE => sys_write(arg1, arg2),
+        SYS_BRK => sys_brk(arg1),
+        SYS_MMAP => sys_mmap(arg1, arg2),
+        SYS_MUNMAP => sys_munmap(arg1, arg2),
+        SYS_FORK => sys_fork(context),
+        SYS_EXEC => match sys_exec(arg1, arg2) {
+            Ok(new_context) => return SyscallOutcome::Replace(new_context),
+            Err(errno) => Err(errno),
+        },
+        SYS_WAIT => return sys_waitpid(None, arg1),
+        SYS_YIELD => return SyscallOutcome::Yield,
+        SYS_SLEEP => return sys_sleep(arg1),
+        SYS_CLOCK_GETTIME => sys_clock_gettime(arg1, arg2),
+        SYS_TIME => sys_time(arg1),
+        SYS_WAITPID => return sys_waitpid(Some(Pid::from_u64(arg1)), arg2),
         _ => {
             serial_println!("[kernel] unknown syscall number: {}", num);
             println!("[kernel] unknown syscall number: {}", num);
-            SYSCALL_ERROR
+            Err(Errno::ENOSYS)
         }
-    }
+    };
+
+    SyscallOutcome::Return(result)
 }
 
+/// Size of a user page in bytes.
+const PAGE_SIZE: u64 = 4096;
+
+/// Size of the kernel buffer through which [`sys_write`] copies user memory.
+const WRITE_CHUNK_SIZE: usize = 256;
+
 /// Writes a buffer from user memory to the VGA text display.
 ///
+/// The buffer is copied in chunks of [`WRITE_CHUNK_SIZE`] bytes through
+/// [`UserSlice`], after checking that all of it is readable by the process.
+///
 /// # Arguments
 ///
 /// * `buf_ptr` - Virtual address of the buffer in user space.
@@ -199,42 +289,298 @@ extern "C" fn syscall_dispatch(num: u64, arg1: u64, arg2: u64, _arg3: u64) -> u6
 ///
 /// # Returns
 ///
-/// The number of bytes successfully written, or [`SYSCALL_ERROR`] on failure.
-fn sys_write(buf_ptr: u64, len: u64) -> u64 {
-    let buf_end = buf_ptr.saturating_add(len);
+/// The number of bytes written, or [`Errno::EFAULT`] if the buffer is not
+/// readable by the process.
+fn sys_write(buf_ptr: u64, len: u64) -> SyscallResult {
+    let buf = UserSlice::new(buf_ptr, len)
+        .and_then(|buf| buf.check_readable().map(|()| buf))
+        .inspect_err(|fault| println!("[kernel] sys_write: {}", fault))?;
 
-    // Validate that the buffer resides entirely within user-accessible memory.
-    if buf_ptr < userspace::USER_CODE_START
-        || buf_end > userspace::USER_STACK_TOP
-        || buf_end < buf_ptr
-    {
-        println!("[kernel] sys_write: invalid buffer range");
-        return SYSCALL_ERROR;
+    let mut chunk = [0; WRITE_CHUNK_SIZE];
+    // Bytes of a UTF-8 sequence split by the end of the previous chunk.
+    let mut pending = 0;
+    let mut offset = 0;
+    while offset < buf.len() {
+        let count = (buf.len() - offset).min(WRITE_CHUNK_SIZE - pending);
+        buf.read(offset, &mut chunk[pending..pending + count])?;
+        offset += count;
+
+        let filled = pending + count;
+        pending = write_utf8_lossy(&chunk[..filled], offset == buf.len());
+        chunk.copy_within(filled - pending..filled, 0);
     }
 
-    // SAFETY:
-    //
-    // The buffer pointer is within user-mapped memory that the kernel has set up
-    // and can access. We verified the range is within the user address space.
-    let buf = unsafe { core::slice::from_raw_parts(buf_ptr as *const u8, len as usize) };
-
-    match core::str::from_utf8(buf) {
-        Ok(s) => {
-            serial_println!("[kernel] sys_write: \"{}\"", s);
-            print!("{}", s);
-            len
+    Ok(len)
+}
+
+/// Prints `bytes` as text, replacing invalid UTF-8 and control characters by
+/// `.`.
+///
+/// Unless `last` is set, an incomplete UTF-8 sequence at the end of `bytes` is
+/// not printed; its length is returned so that the caller can complete it with
+/// the next bytes.
+fn write_utf8_lossy(bytes: &[u8], last: bool) -> usize {
+    let mut rest = bytes;
+    loop {
+        let (valid, failure) = match str::from_utf8(rest) {
+            Ok(s) => (s, None),
+            Err(error) => {
+                // SAFETY:
+                //
+                // `from_utf8` validated the bytes up to `valid_up_to`.
+                let s = unsafe { str::from_utf8_unchecked(&rest[..error.valid_up_to()]) };
+                (s, Some(error))
+            }
+        };
+        if !valid.is_empty() {
+            serial_println!("[kernel] sys_write: \"{}\"", valid);
+            print!("{}", valid);
         }
-        Err(_) => {
-            // Fall back to printing byte-by-byte for non-UTF-8 data.
-            for &byte in buf {
-                if byte.is_ascii_graphic() || byte == b' ' || byte == b'\n' {
-                    print!("{}", byte as char);
-                } else {
-                    print!(".");
-                }
+
+        let Some(error) = failure else {
+            return 0;
+        };
+        let invalid = &rest[error.valid_up_to()..];
+        let Some(invalid_len) = error.error_len() else {
+            if last {
+                print_invalid(invalid);
+                return 0;
             }
-            len
+            return invalid.len();
+        };
+        print_invalid(&invalid[..invalid_len]);
+        rest = &invalid[invalid_len..];
+    }
+}
+
+/// Prints bytes that are not valid UTF-8, keeping the printable ASCII ones.
+fn print_invalid(bytes: &[u8]) {
+    for &byte in bytes {
+        if byte.is_ascii_graphic() || byte == b' ' || byte == b'\n' {
+            print!("{}", byte as char);
+        } else {
+            print!(".");
+        }
+    }
+}
+
+/// Moves the program break of the current process to `addr`.
+///
+/// The heap pages up to the new break are mapped zeroed when they are first
+/// touched, and unmapped if the heap shrinks. An `addr` of `0` only queries
+/// the break.
+///
+/// # Returns
+///
+/// The new program break, or [`Errno::ENOMEM`] if `addr` is outside the heap
+/// region.
+fn sys_brk(addr: u64) -> SyscallResult {
+    process::with_current_address_space(|address_space| {
+        if addr != 0 {
+            memory::with_frame_allocator(|frame_allocator| {
+                address_space.set_brk(addr, frame_allocator)
+            })?;
+        }
+        Ok(address_space.brk())
+    })
+    .unwrap_or(Err(Errno::ESRCH))
+}
+
+/// Maps `len` bytes of zeroed, private, anonymous memory into the current
+/// process.
+///
+/// The pages are writable and non-executable, and only allocated when they
+/// are first touched. They are placed at `addr` if it is non-zero and the
+/// pages there are free, and at the lowest free address of the anonymous
+/// mapping region otherwise.
+///
+/// # Returns
+///
+/// The address of the mapping, [`Errno::EINVAL`] if `len` is zero or `addr`
+/// is not page-aligned, or [`Errno::ENOMEM`] if no room is left.
+fn sys_mmap(addr: u64, len: u64) -> SyscallResult {
+    if len == 0 || addr % PAGE_SIZE != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let hint = (addr != 0).then_some(addr);
+
+    process::with_current_address_space(|address_space| {
+        let pages = address_space
+            .find_free_pages(hint, len.div_ceil(PAGE_SIZE))
+            .ok_or(Errno::ENOMEM)?;
+        address_space.map_anonymous(pages);
+        Ok(pages.start.start_address().as_u64())
+    })
+    .unwrap_or(Err(Errno::ESRCH))
+}
+
+/// Unmaps the anonymous mappings in the `len` bytes starting at `addr`.
+///
+/// # Returns
+///
+/// `0`, or [`Errno::EINVAL`] if `len` is zero, `addr` is not page-aligned or
+/// the range leaves the anonymous mapping region.
+fn sys_munmap(addr: u64, len: u64) -> SyscallResult {
+    let end = addr.checked_add(len).ok_or(Errno::EINVAL)?;
+    if len == 0
+        || addr % PAGE_SIZE != 0
+        || addr < userspace::USER_MMAP_START
+        || end > userspace::USER_MMAP_END
+    {
+        return Err(Errno::EINVAL);
+    }
+
+    process::with_current_address_space(|address_space| {
+        memory::with_frame_allocator(|frame_allocator| {
+            address_space.unmap_anonymous(addr, end, frame_allocator);
+        });
+        Ok(0)
+    })
+    .unwrap_or(Err(Errno::ESRCH))
+}
+
+/// Creates a child process running a copy of the current one.
+///
+/// The child resumes from the same `context`, with `0` in `rax`.
+///
+/// # Returns
+///
+/// The PID of the child, or [`Errno::ENOMEM`] if the address space cannot be
+/// copied.
+fn sys_fork(context: &Context) -> SyscallResult {
+    let child = process::fork_current(context).ok_or(Errno::ESRCH)??;
+    Ok(child.as_u64())
+}
+
+/// Replaces the image of the current process with the registered program
+/// whose name is the `len` bytes at `name_ptr`.
+///
+/// # Returns
+///
+/// The initial context of the new image, [`Errno::ENAMETOOLONG`] if the name
+/// is longer than [`PROGRAM_NAME_MAX`] bytes, [`Errno::EFAULT`] if it is not
+/// readable, [`Errno::ENOENT`] if no program has that name, or the error of
+/// the loader.
+fn sys_exec(name_ptr: u64, len: u64) -> Result<Context, Errno> {
+    let name_len = usize::try_from(len)
+        .ok()
+        .filter(|&name_len| name_len <= PROGRAM_NAME_MAX)
+        .ok_or(Errno::ENAMETOOLONG)?;
+    let mut buf = [0; PROGRAM_NAME_MAX];
+    let bytes = &mut buf[..name_len];
+    copy_from_user(bytes, name_ptr)?;
+
+    let name = str::from_utf8(bytes).ok().ok_or(Errno::ENOENT)?;
+    let binary = programs::lookup(name).ok_or(Errno::ENOENT)?;
+    serial_println!("[kernel] executing program {}", name);
+
+    Ok(process::exec_current(binary).ok_or(Errno::ESRCH)??)
+}
+
+/// Waits for the child `pid` of the current process to exit, or for any
+/// child if `pid` is `None`.
+///
+/// If a matching child has exited, it is removed from the process table and
+/// its exit code is stored at `status_ptr`, unless it is `0`. Otherwise the
+/// process sleeps and the syscall is restarted when one of its children
+/// exits.
+///
+/// # Returns
+///
+/// The PID of the child, [`Errno::ECHILD`] if the process has no matching
+/// child, or [`Errno::EFAULT`] if the exit code cannot be stored.
+fn sys_waitpid(pid: Option<Pid>, status_ptr: u64) -> SyscallOutcome {
+    let result = match process::reap_child(pid) {
+        Some(ChildStatus::Exited(child, code)) => {
+            let stored = if status_ptr == 0 {
+                Ok(())
+            } else {
+                UserPtr::new(status_ptr).write(code)
+            };
+            stored.map(|()| child.as_u64()).map_err(Errno::from)
         }
+        Some(ChildStatus::Running) => return SyscallOutcome::Wait,
+        Some(ChildStatus::NoChild) => Err(Errno::ECHILD),
+        None => Err(Errno::ESRCH),
+    };
+    SyscallOutcome::Return(result)
+}
+
+/// Blocks the current process for at least `ms` milliseconds.
+///
+/// The process is kept off the run queue until the monotonic clock has
+/// advanced by `ms`; the first timer tick after that wakes it up. It then
+/// resumes with `0` as the result.
+fn sys_sleep(ms: u64) -> SyscallOutcome {
+    SyscallOutcome::Sleep(time::now() + Duration::from_millis(ms))
+}
+
+/// Stores the current time of the clock `clock_id` at `timespec_ptr`.
+///
+/// # Returns
+///
+/// `0`, [`Errno::EINVAL`] if the clock is not [`CLOCK_MONOTONIC`], or
+/// [`Errno::EFAULT`] if the time cannot be stored.
+fn sys_clock_gettime(clock_id: u64, timespec_ptr: u64) -> SyscallResult {
+    if clock_id != CLOCK_MONOTONIC {
+        return Err(Errno::EINVAL);
+    }
+
+    let uptime = time::uptime();
+    let timespec = Timespec {
+        tv_sec: uptime.as_secs(),
+        tv_nsec: u64::from(uptime.subsec_nanos()),
+    };
+    UserPtr::new(timespec_ptr).write(timespec)?;
+    Ok(0)
+}
+
+/// Returns the number of seconds since the Unix epoch, and stores it at
+/// `time_ptr` unless it is `0`.
+///
+/// # Returns
+///
+/// The time, or [`Errno::EFAULT`] if it cannot be stored.
+fn sys_time(time_ptr: u64) -> SyscallResult {
+    let seconds = time::wall_time().as_secs();
+    if time_ptr != 0 {
+        UserPtr::new(time_ptr).write(seconds)?;
+    }
+    Ok(seconds)
+}
+
+/// Enables the `syscall`/`sysret` instructions.
+///
+/// Sets `EFER.SCE` and programs `STAR` with the GDT selectors, `LSTAR` with
+/// [`fast_syscall_entry`] and `SFMASK` with the RFLAGS bits cleared on entry.
+/// Must be called after the GDT has been loaded.
+///
+/// # Panics
+///
+/// Panics if the GDT layout does not satisfy the requirements of `SYSRET`.
+pub fn init_syscall_instructions() {
+    SYSCALL_KERNEL_RSP.store(
+        gdt::kernel_stack_top().align_down(16_u64).as_u64(),
+        Ordering::Relaxed,
+    );
+
+    if let Err(error) = Star::write(
+        gdt::USER_CODE_SELECTOR,
+        gdt::USER_DATA_SELECTOR,
+        gdt::KERNEL_CODE_SELECTOR,
+        gdt::KERNEL_DATA_SELECTOR,
+    ) {
+        panic!("Invalid GDT layout for SYSCALL/SYSRET: {error}");
+    }
+    LStar::write(VirtAddr::new(fast_syscall_entry as *const () as u64));
+    SFMask::write(SYSCALL_RFLAGS_MASK);
+
+    // SAFETY:
+    //
+    // STAR, LSTAR and SFMASK are programmed, so the syscall instruction enters
+    // the kernel through `fast_syscall_entry` with valid selectors.
+    unsafe {
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
     }
 }
 
@@ -265,29 +611,42 @@ pub fn register_syscall_handler(idt: &mut x86_64::structures::idt::InterruptDesc
 mod tests {
     use super::*;
 
+    /// Dispatches the syscall `num` with the arguments `arg1` and `arg2`.
+    fn dispatch(num: u64, arg1: u64, arg2: u64) -> SyscallOutcome {
+        syscall_dispatch(&Context {
+            rax: num,
+            rdi: arg1,
+            rsi: arg2,
+            ..Context::default()
+        })
+    }
+
     #[test_case]
     fn test_unknown_syscall_returns_error() {
-        let result = syscall_dispatch(999, 0, 0, 0);
+        let result = dispatch(999, 0, 0);
         assert_eq!(
-            result, SYSCALL_ERROR,
-            "Unknown syscall should return SYSCALL_ERROR.",
+            result,
+            SyscallOutcome::Return(Err(Errno::ENOSYS)),
+            "Unknown syscall should return ENOSYS.",
         );
     }
 
     #[test_case]
-    fn test_sys_exit_returns_sentinel() {
-        let result = syscall_dispatch(SYS_EXIT, 42, 0, 0);
+    fn test_sys_exit_does_not_return() {
+        let result = dispatch(SYS_EXIT, 42, 0);
         assert_eq!(
-            result, PROCESS_EXIT_SENTINEL,
-            "sys_exit should return PROCESS_EXIT_SENTINEL.",
+            result,
+            SyscallOutcome::Exit,
+            "sys_exit should not return to the process.",
         );
     }
 
     #[test_case]
     fn test_sys_write_rejects_null_pointer() {
-        let result = syscall_dispatch(SYS_WRITE, 0, 10, 0);
+        let result = dispatch(SYS_WRITE, 0, 10);
         assert_eq!(
-            result, SYSCALL_ERROR,
+            result,
+            SyscallOutcome::Return(Err(Errno::EFAULT)),
             "sys_write with address 0 should fail validation.",
         );
     }
@@ -295,9 +654,10 @@ mod tests {
     #[test_case]
     fn test_sys_write_rejects_out_of_bounds_buffer() {
         // Buffer starting past the user stack top.
-        let result = syscall_dispatch(SYS_WRITE, userspace::USER_STACK_TOP + 1, 10, 0);
+        let result = dispatch(SYS_WRITE, userspace::USER_STACK_TOP + 1, 10);
         assert_eq!(
-            result, SYSCALL_ERROR,
+            result,
+            SyscallOutcome::Return(Err(Errno::EFAULT)),
             "sys_write with out-of-bounds address should fail validation.",
         );
     }
@@ -305,10 +665,83 @@ mod tests {
     #[test_case]
     fn test_sys_write_rejects_overflow() {
         // Buffer that would overflow u64.
-        let result = syscall_dispatch(SYS_WRITE, userspace::USER_CODE_START, u64::MAX, 0);
+        let result = dispatch(SYS_WRITE, userspace::USER_CODE_START, u64::MAX);
         assert_eq!(
-            result, SYSCALL_ERROR,
+            result,
+            SyscallOutcome::Return(Err(Errno::EFAULT)),
             "sys_write with overflowing length should fail validation.",
         );
     }
+
+    #[test_case]
+    fn test_process_syscalls_require_a_current_process() {
+        for (num, name) in [(SYS_FORK, "sys_fork"), (SYS_WAIT, "sys_wait")] {
+            assert_eq!(
+                dispatch(num, 0, 0),
+                SyscallOutcome::Return(Err(Errno::ESRCH)),
+                "{name} must fail outside of a user process.",
+            );
+        }
+    }
+
+    #[test_case]
+    fn test_sys_exec_rejects_long_names() {
+        let result = dispatch(
+            SYS_EXEC,
+            userspace::USER_CODE_START,
+            PROGRAM_NAME_MAX as u64 + 1,
+        );
+        assert_eq!(
+            result,
+            SyscallOutcome::Return(Err(Errno::ENAMETOOLONG)),
+            "sys_exec must reject names longer than PROGRAM_NAME_MAX.",
+        );
+    }
+
+    #[test_case]
+    fn test_sys_sleep_sets_a_deadline_after_the_requested_time() {
+        let now = time::now();
+        let SyscallOutcome::Sleep(until) = dispatch(SYS_SLEEP, 100, 0) else {
+            panic!("sys_sleep must put the process to sleep.");
+        };
+        assert!(
+            until >= now + Duration::from_millis(100),
+            "The process must sleep for at least the requested time.",
+        );
+        assert_eq!(
+            dispatch(SYS_YIELD, 0, 0),
+            SyscallOutcome::Yield,
+            "sys_yield gives the CPU up.",
+        );
+    }
+
+    #[test_case]
+    fn test_sys_clock_gettime_only_supports_the_monotonic_clock() {
+        assert_eq!(
+            dispatch(SYS_CLOCK_GETTIME, 0, 0x1000),
+            SyscallOutcome::Return(Err(Errno::EINVAL)),
+            "The realtime clock is not supported.",
+        );
+        assert_eq!(
+            dispatch(SYS_CLOCK_GETTIME, CLOCK_MONOTONIC, 0),
+            SyscallOutcome::Return(Err(Errno::EFAULT)),
+            "The time cannot be stored at a null pointer.",
+        );
+    }
+
+    #[test_case]
+    fn test_sys_time_returns_the_wall_time() {
+        let SyscallOutcome::Return(Ok(seconds)) = dispatch(SYS_TIME, 0, 0) else {
+            panic!("sys_time without a pointer cannot fail.");
+        };
+        assert!(
+            seconds >= time::boot_time().as_secs(),
+            "The wall time is not before the boot time.",
+        );
+        assert_eq!(
+            dispatch(SYS_TIME, userspace::USER_STACK_TOP + 1, 0),
+            SyscallOutcome::Return(Err(Errno::EFAULT)),
+            "The time cannot be stored outside user space.",
+        );
+    }
 }
@@ -0,0 +1,422 @@
+//! Checked access to user memory.
+//!
+//! Syscall arguments that point into user memory cannot be trusted: the range
+//! may leave the user address space, or one of its pages may be unmapped or
+//! not accessible from Ring 3. Dereferencing such a pointer in the kernel would
+//! fault the kernel itself. [`UserPtr`] and [`UserSlice`] wrap these addresses
+//! and walk the page tables of the active address space to verify that every
+//! page is present and `USER_ACCESSIBLE` (and `WRITABLE` when the kernel writes
+//! to it) before copying anything. Pages of the process that are not mapped
+//! yet are populated on demand, and copy-on-write pages are copied before the
+//! kernel writes to them, as if the process touched them itself. A failed
+//! check is reported as a [`UserFault`], which syscalls turn into an error for
+//! the caller.
+//!
+//! The bytes are copied through the physical memory mapping, so the kernel
+//! never dereferences a user address.
+
+use core::{fmt, marker::PhantomData, mem::MaybeUninit, ptr, slice};
+
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{mapper::TranslateResult, PageTableFlags, Translate},
+    PhysAddr, VirtAddr,
+};
+
+use crate::{
+    memory,
+    userspace::{process, vma::Access, USER_CODE_START, USER_STACK_TOP},
+};
+
+/// Error returned when user memory cannot be accessed, the equivalent of
+/// `EFAULT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserFault {
+    /// First address that could not be accessed.
+    pub addr: u64,
+}
+
+impl fmt::Display for UserFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bad user address {:#x}", self.addr)
+    }
+}
+
+/// Returns the physical address backing the user address `addr` in the active
+/// address space, if the page allows `access` from user mode.
+///
+/// A page of the current process that has not been touched yet is mapped on
+/// demand, and a copy-on-write page is copied before it is written, as if the
+/// process had accessed it.
+fn translate(addr: u64, access: Access) -> Result<PhysAddr, UserFault> {
+    let virt = VirtAddr::try_new(addr).ok().ok_or(UserFault { addr })?;
+    if let Some(phys) = translate_mapped(virt, access) {
+        return Ok(phys);
+    }
+
+    process::handle_page_fault(addr, access)
+        .ok()
+        .and_then(|()| translate_mapped(virt, access))
+        .ok_or(UserFault { addr })
+}
+
+/// Returns the physical address backing `virt` in the active address space,
+/// if its page is mapped and allows `access` from user mode.
+fn translate_mapped(virt: VirtAddr, access: Access) -> Option<PhysAddr> {
+    // SAFETY:
+    //
+    // The active level 4 table is only read here, and syscalls run with
+    // interrupts disabled so it cannot be modified concurrently.
+    let mapper = unsafe { memory::mapper_for(Cr3::read().0) };
+    match mapper.translate(virt) {
+        TranslateResult::Mapped {
+            frame,
+            offset,
+            flags,
+        } if flags.contains(PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE)
+            && access.is_allowed_by(flags) =>
+        {
+            Some(frame.start_address() + offset)
+        }
+        TranslateResult::Mapped { .. }
+        | TranslateResult::NotMapped
+        | TranslateResult::InvalidFrameAddress(_) => None,
+    }
+}
+
+/// A byte range in user memory.
+///
+/// Creating a `UserSlice` only checks that the range lies in the user address
+/// space; the pages are checked by every access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserSlice {
+    addr: u64,
+    len: usize,
+}
+
+impl UserSlice {
+    /// Creates a slice of `len` bytes starting at the user address `addr`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`UserFault`] if the range overflows or is not entirely
+    /// between [`USER_CODE_START`] and [`USER_STACK_TOP`].
+    pub fn new(addr: u64, len: u64) -> Result<Self, UserFault> {
+        let end = addr.checked_add(len).ok_or(UserFault { addr })?;
+        if addr < USER_CODE_START {
+            return Err(UserFault { addr });
+        }
+        if end > USER_STACK_TOP {
+            return Err(UserFault {
+                addr: addr.max(USER_STACK_TOP),
+            });
+        }
+        Ok(Self {
+            addr,
+            len: usize::try_from(len).ok().ok_or(UserFault { addr })?,
+        })
+    }
+
+    /// Returns the user address of the first byte.
+    #[must_use]
+    pub const fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// Returns the length of the slice in bytes.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the slice is empty.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Checks that every page of the slice is readable from user mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`UserFault`] with the first address that is not readable.
+    pub fn check_readable(&self) -> Result<(), UserFault> {
+        self.check(0, self.len, Access::Read)
+    }
+
+    /// Checks that every page of the slice is writable from user mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`UserFault`] with the first address that is not writable.
+    pub fn check_writable(&self) -> Result<(), UserFault> {
+        self.check(0, self.len, Access::Write)
+    }
+
+    /// Copies `dst.len()` bytes starting `offset` bytes into the slice to
+    /// `dst`.
+    ///
+    /// Nothing is copied unless the whole source range is readable.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`UserFault`] if the range is not readable or does not fit in
+    /// the slice.
+    pub fn read(&self, offset: usize, dst: &mut [u8]) -> Result<(), UserFault> {
+        self.check(offset, dst.len(), Access::Read)?;
+        self.for_each_page(offset, dst.len(), Access::Read, |phys, done, chunk| {
+            // SAFETY:
+            //
+            // `phys` is backed by a user page and the chunk does not cross the
+            // end of that page. The destination is kernel memory and cannot
+            // overlap with it.
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    memory::phys_to_virt(phys).as_ptr::<u8>(),
+                    dst.as_mut_ptr().add(done),
+                    chunk,
+                );
+            }
+        })
+    }
+
+    /// Copies `src` to the slice, starting `offset` bytes into it.
+    ///
+    /// Nothing is copied unless the whole destination range is writable.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`UserFault`] if the range is not writable or does not fit in
+    /// the slice.
+    pub fn write(&self, offset: usize, src: &[u8]) -> Result<(), UserFault> {
+        self.check(offset, src.len(), Access::Write)?;
+        self.for_each_page(offset, src.len(), Access::Write, |phys, done, chunk| {
+            // SAFETY:
+            //
+            // `phys` is backed by a writable user page and the chunk does not
+            // cross the end of that page. The source is kernel memory and
+            // cannot overlap with it.
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    src.as_ptr().add(done),
+                    memory::phys_to_virt(phys).as_mut_ptr::<u8>(),
+                    chunk,
+                );
+            }
+        })
+    }
+
+    /// Checks that the `len` bytes starting `offset` bytes into the slice
+    /// allow `access`.
+    fn check(&self, offset: usize, len: usize, access: Access) -> Result<(), UserFault> {
+        self.for_each_page(offset, len, access, |_, _, _| {})
+    }
+
+    /// Calls `f` with the physical address, the number of bytes already
+    /// visited and the length of every page-sized chunk of the `len` bytes
+    /// starting `offset` bytes into the slice.
+    fn for_each_page<F>(
+        &self,
+        offset: usize,
+        len: usize,
+        access: Access,
+        mut f: F,
+    ) -> Result<(), UserFault>
+    where
+        F: FnMut(PhysAddr, usize, usize),
+    {
+        let start = self.addr + u64::try_from(offset).unwrap_or(u64::MAX);
+        if offset.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(UserFault {
+                addr: start.min(self.addr + self.len as u64),
+            });
+        }
+
+        let mut done = 0;
+        while done < len {
+            let addr = start + done as u64;
+            let phys = translate(addr, access)?;
+            let chunk = (len - done).min(4096 - usize::from(VirtAddr::new(addr).page_offset()));
+            f(phys, done, chunk);
+            done += chunk;
+        }
+
+        Ok(())
+    }
+}
+
+/// Types that can be copied from and to user memory.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value of the type, since its bytes come
+/// from user memory.
+pub unsafe trait UserData: Copy {}
+
+// SAFETY:
+//
+// Every bit pattern is a valid integer.
+unsafe impl UserData for u8 {}
+// SAFETY: See above.
+unsafe impl UserData for u16 {}
+// SAFETY: See above.
+unsafe impl UserData for u32 {}
+// SAFETY: See above.
+unsafe impl UserData for u64 {}
+// SAFETY: See above.
+unsafe impl UserData for usize {}
+// SAFETY: See above.
+unsafe impl UserData for i32 {}
+// SAFETY: See above.
+unsafe impl UserData for i64 {}
+// SAFETY:
+//
+// A `Timespec` is made of integers only.
+unsafe impl UserData for abi::Timespec {}
+// SAFETY:
+//
+// An array is valid if each of its elements is.
+unsafe impl<T: UserData, const N: usize> UserData for [T; N] {}
+
+/// A pointer to a value of type `T` in user memory.
+///
+/// The pointer does not need to be aligned: the value is copied byte by byte.
+#[derive(Debug)]
+pub struct UserPtr<T> {
+    addr: u64,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T: UserData> UserPtr<T> {
+    /// Wraps the user address `addr`.
+    #[must_use]
+    pub const fn new(addr: u64) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the user address of the value.
+    #[must_use]
+    pub const fn addr(self) -> u64 {
+        self.addr
+    }
+
+    /// Returns `true` if the address is zero.
+    #[must_use]
+    pub const fn is_null(self) -> bool {
+        self.addr == 0
+    }
+
+    /// Returns the bytes of the value as a [`UserSlice`].
+    fn as_slice(self) -> Result<UserSlice, UserFault> {
+        UserSlice::new(self.addr, size_of::<T>() as u64)
+    }
+
+    /// Copies the value from user memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`UserFault`] if the value is not readable from user mode.
+    pub fn read(self) -> Result<T, UserFault> {
+        let mut value = MaybeUninit::<T>::uninit();
+
+        // SAFETY:
+        //
+        // The bytes of `value` are only written before being read.
+        let bytes =
+            unsafe { slice::from_raw_parts_mut(value.as_mut_ptr().cast::<u8>(), size_of::<T>()) };
+        self.as_slice()?.read(0, bytes)?;
+
+        // SAFETY:
+        //
+        // Every byte has been initialized, and any bit pattern is a valid `T`
+        // since it implements `UserData`.
+        Ok(unsafe { value.assume_init() })
+    }
+
+    /// Copies `value` to user memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`UserFault`] if the value is not writable from user mode.
+    pub fn write(self, value: T) -> Result<(), UserFault> {
+        // SAFETY:
+        //
+        // `value` is a plain `UserData` value living for the whole call.
+        let bytes =
+            unsafe { slice::from_raw_parts(ptr::from_ref(&value).cast::<u8>(), size_of::<T>()) };
+        self.as_slice()?.write(0, bytes)
+    }
+}
+
+/// Copies `dst.len()` bytes from the user address `src` to `dst`.
+///
+/// # Errors
+///
+/// Returns a [`UserFault`] if the source is not readable from user mode.
+pub fn copy_from_user(dst: &mut [u8], src: u64) -> Result<(), UserFault> {
+    UserSlice::new(src, dst.len() as u64)?.read(0, dst)
+}
+
+/// Copies `src` to the user address `dst`.
+///
+/// # Errors
+///
+/// Returns a [`UserFault`] if the destination is not writable from user mode.
+pub fn copy_to_user(dst: u64, src: &[u8]) -> Result<(), UserFault> {
+    UserSlice::new(dst, src.len() as u64)?.write(0, src)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_user_slice_rejects_kernel_addresses() {
+        assert_eq!(
+            UserSlice::new(0, 16),
+            Err(UserFault { addr: 0 }),
+            "A range below the user space must be rejected.",
+        );
+        assert_eq!(
+            UserSlice::new(USER_STACK_TOP - 8, 16),
+            Err(UserFault {
+                addr: USER_STACK_TOP
+            }),
+            "A range crossing the end of the user space must be rejected.",
+        );
+    }
+
+    #[test_case]
+    fn test_user_slice_rejects_overflow() {
+        assert_eq!(
+            UserSlice::new(USER_CODE_START, u64::MAX),
+            Err(UserFault {
+                addr: USER_CODE_START
+            }),
+            "An overflowing range must be rejected.",
+        );
+    }
+
+    #[test_case]
+    fn test_user_slice_rejects_accesses_past_its_end() {
+        let mut buffer = [0; 4];
+        assert_eq!(
+            UserSlice::new(USER_CODE_START, 8).and_then(|slice| slice.read(6, &mut buffer)),
+            Err(UserFault {
+                addr: USER_CODE_START + 6
+            }),
+            "A read past the end of the slice must be rejected.",
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Virtual memory areas.
+//!
+//! The heap, the anonymous mappings and the stack of a process are not backed
+//! by frames when they are created. They are described by [`Vma`]s, ranges of
+//! user addresses that the process may access, and their pages are only
+//! allocated by the page fault handler when they are first touched (demand
+//! paging). A fault outside every area is a genuine access violation.
+//!
+//! The stack area reserves [`USER_STACK_SIZE`](super::USER_STACK_SIZE) bytes
+//! below [`USER_STACK_TOP`](super::USER_STACK_TOP) and grows down as the
+//! process touches it. The page right below it,
+//! [`USER_STACK_GUARD_PAGE`](super::USER_STACK_GUARD_PAGE), is never mapped: a
+//! fault there is reported as a stack overflow.
+
+use core::{fmt, ops::Range};
+
+use x86_64::structures::paging::PageTableFlags;
+
+/// Kind of access that caused a page fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// A read.
+    Read,
+    /// A write.
+    Write,
+    /// An instruction fetch.
+    Execute,
+}
+
+impl Access {
+    /// Returns `true` if a page mapped with `flags` allows this access.
+    #[must_use]
+    pub const fn is_allowed_by(self, flags: PageTableFlags) -> bool {
+        match self {
+            Self::Read => true,
+            Self::Write => flags.contains(PageTableFlags::WRITABLE),
+            Self::Execute => !flags.contains(PageTableFlags::NO_EXECUTE),
+        }
+    }
+}
+
+/// What a virtual memory area is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaKind {
+    /// The heap, between the end of the program image and the program break.
+    Heap,
+    /// An anonymous mapping created by `sys_mmap`.
+    Anonymous,
+    /// The user stack.
+    Stack,
+}
+
+/// A range of user addresses whose pages are allocated on first touch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vma {
+    range: Range<u64>,
+    flags: PageTableFlags,
+    kind: VmaKind,
+}
+
+impl Vma {
+    /// Creates an area covering the page-aligned `range`, whose pages are
+    /// mapped with `flags`.
+    #[must_use]
+    pub const fn new(range: Range<u64>, flags: PageTableFlags, kind: VmaKind) -> Self {
+        Self { range, flags, kind }
+    }
+
+    /// Returns the addresses covered by the area.
+    #[must_use]
+    pub const fn range(&self) -> &Range<u64> {
+        &self.range
+    }
+
+    /// Returns the flags of the pages of the area.
+    #[must_use]
+    pub const fn flags(&self) -> PageTableFlags {
+        self.flags
+    }
+
+    /// Returns what the area is used for.
+    #[must_use]
+    pub const fn kind(&self) -> VmaKind {
+        self.kind
+    }
+
+    /// Returns `true` if `addr` lies in the area.
+    #[must_use]
+    pub fn contains(&self, addr: u64) -> bool {
+        self.range.contains(&addr)
+    }
+
+    /// Returns `true` if the area shares an address with `range`.
+    #[must_use]
+    pub const fn overlaps(&self, range: &Range<u64>) -> bool {
+        self.range.start < range.end && range.start < self.range.end
+    }
+
+    /// Sets the end of the area, used to move the program break.
+    pub const fn set_end(&mut self, end: u64) {
+        self.range.end = end;
+    }
+
+    /// Removes `range` from the area.
+    ///
+    /// Returns the areas left before and after `range`, which are empty if
+    /// `range` covers the start or the end of the area.
+    #[must_use]
+    pub fn split_off(self, range: &Range<u64>) -> [Self; 2] {
+        let before = self.range.start..range.start.clamp(self.range.start, self.range.end);
+        let after = range.end.clamp(self.range.start, self.range.end)..self.range.end;
+        [
+            Self::new(before, self.flags, self.kind),
+            Self::new(after, self.flags, self.kind),
+        ]
+    }
+
+    /// Returns `true` if the area covers no address.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.range.start >= self.range.end
+    }
+}
+
+/// Reasons why a page fault cannot be resolved by demand paging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageFaultError {
+    /// The address is not covered by any area.
+    NotMapped,
+    /// The address is in the guard page below the stack.
+    StackOverflow,
+    /// The access is not allowed by the flags of the page.
+    AccessViolation,
+    /// No frame is left to back the page.
+    OutOfMemory,
+}
+
+impl fmt::Display for PageFaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match *self {
+            Self::NotMapped => "address not mapped",
+            Self::StackOverflow => "stack overflow",
+            Self::AccessViolation => "access not allowed by the page flags",
+            Self::OutOfMemory => "out of memory",
+        };
+        f.write_str(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_access_is_checked_against_the_page_flags() {
+        let data = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        assert!(
+            Access::Write.is_allowed_by(data),
+            "Data pages are writable."
+        );
+        assert!(
+            !Access::Execute.is_allowed_by(data),
+            "Data pages are not executable.",
+        );
+        assert!(
+            !Access::Write.is_allowed_by(PageTableFlags::empty()),
+            "Code pages are read-only.",
+        );
+    }
+
+    #[test_case]
+    fn test_split_off_keeps_both_sides() {
+        let vma = Vma::new(0x1000..0x5000, PageTableFlags::WRITABLE, VmaKind::Anonymous);
+        let [before, after] = vma.split_off(&(0x2000..0x3000));
+        assert_eq!(before.range(), &(0x1000..0x2000), "Pages before the hole.");
+        assert_eq!(after.range(), &(0x3000..0x5000), "Pages after the hole.");
+    }
+
+    #[test_case]
+    fn test_split_off_covering_the_area_leaves_nothing() {
+        let vma = Vma::new(0x1000..0x3000, PageTableFlags::WRITABLE, VmaKind::Anonymous);
+        let [before, after] = vma.split_off(&(0x0..0x4000));
+        assert!(before.is_empty(), "Nothing is left before the hole.");
+        assert!(after.is_empty(), "Nothing is left after the hole.");
+    }
+}
@@ -1,11 +1,18 @@
 //! Macros for printing to the screen.
 //! Provides a safe interface to write on the VGA text buffer with println macro.
 
-use core::fmt;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use x86_64::instructions::interrupts;
 
-use crate::vga_buffer::writer::WRITER;
+use crate::{interrupts::in_hardware_interrupt, vga_buffer::writer::WRITER};
+
+/// Number of prints skipped because they were made by a hardware interrupt
+/// handler.
+static SKIPPED_PRINTS: AtomicU64 = AtomicU64::new(0);
 
 ////////////////////////
 //    Print macros    //
@@ -24,10 +31,25 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/// Returns the number of prints skipped because they were made by a hardware
+/// interrupt handler.
+#[must_use]
+pub fn skipped_prints() -> u64 {
+    SKIPPED_PRINTS.load(Ordering::Relaxed)
+}
+
 /// Macro that allow to print to the screen with the WRITER static instance.
+///
+/// Nothing is printed from a hardware interrupt handler: the interrupted code
+/// may hold the WRITER lock, which would never be released.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
+
+    if in_hardware_interrupt() {
+        SKIPPED_PRINTS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
     #[expect(
         clippy::unwrap_used,
         reason = "We should be able to write inside the vga frame buffer"
@@ -0,0 +1,174 @@
+//! Tests for the timers of async tasks: sleep, interval and timeout futures
+//! woken by the timer interrupt.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(self_rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![allow(missing_docs)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::expect_used)]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, task::Wake};
+use bootloader::{entry_point, BootInfo};
+use core::{
+    future::{self, Future},
+    panic::PanicInfo,
+    pin::pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use self_rust_os::{
+    allocator, memory,
+    task::{self, timer},
+    time,
+};
+use x86_64::{instructions::hlt, VirtAddr};
+
+entry_point!(test_kernel_main);
+
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    self_rust_os::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    // SAFETY: Physical memory offset is valid as guaranteed by the bootloader.
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+
+    // SAFETY: The memory map is valid as guaranteed by the bootloader.
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map);
+    }
+    memory::with_frame_allocator(|frame_allocator| {
+        allocator::init_heap(&mut mapper, frame_allocator)
+    })
+    .expect("Heap initialization failed.");
+
+    test_main();
+
+    self_rust_os::hlt_loop();
+}
+
+/// Waker recording that it has been woken.
+struct FlagWaker(AtomicBool);
+
+impl Wake for FlagWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Runs `future` to completion, polling it only after it has been woken and
+/// halting the CPU in between.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let flag = Arc::new(FlagWaker(AtomicBool::new(false)));
+    let waker = Waker::from(flag.clone());
+    let mut context = Context::from_waker(&waker);
+    let mut pinned = pin!(future);
+    loop {
+        if let Poll::Ready(output) = pinned.as_mut().poll(&mut context) {
+            return output;
+        }
+        while !flag.0.swap(false, Ordering::SeqCst) {
+            hlt();
+        }
+    }
+}
+
+#[test_case]
+fn test_sleep_is_woken_by_the_timer_interrupt() {
+    let before = timer::pending();
+    let start = time::now();
+
+    block_on(task::sleep(Duration::from_millis(50)));
+
+    assert!(
+        start.elapsed() >= Duration::from_millis(50),
+        "The sleep lasts at least its duration.",
+    );
+    assert_eq!(timer::pending(), before, "The timer left the queue.");
+}
+
+#[test_case]
+fn test_dropping_a_sleep_cancels_its_timer() {
+    let before = timer::pending();
+    let waker = Waker::from(Arc::new(FlagWaker(AtomicBool::new(false))));
+    let mut context = Context::from_waker(&waker);
+
+    {
+        let mut sleep = pin!(task::sleep(Duration::from_secs(60)));
+        assert_eq!(
+            sleep.as_mut().poll(&mut context),
+            Poll::Pending,
+            "The deadline is far away.",
+        );
+        assert_eq!(timer::pending(), before + 1, "The timer is queued.");
+    }
+
+    assert_eq!(timer::pending(), before, "Dropping the future cancels it.");
+}
+
+#[test_case]
+fn test_interval_ticks_every_period() {
+    let period = Duration::from_millis(20);
+    let mut interval = task::interval(period);
+    let start = time::now();
+
+    let first = block_on(interval.tick());
+    let second = block_on(interval.tick());
+    let third = block_on(interval.tick());
+
+    assert!(
+        first >= start + period,
+        "The first tick is one period away."
+    );
+    assert_eq!(
+        second.saturating_duration_since(first),
+        period,
+        "Ticks are scheduled one period apart.",
+    );
+    assert_eq!(
+        third.saturating_duration_since(second),
+        period,
+        "Ticks are scheduled one period apart.",
+    );
+    assert!(time::now() >= third, "Ticks are not delivered early.");
+}
+
+#[test_case]
+fn test_timeout() {
+    assert_eq!(
+        block_on(task::timeout(future::ready(7), Duration::ZERO)),
+        Ok(7),
+        "A ready future completes even without time left.",
+    );
+    assert_eq!(
+        block_on(task::timeout(
+            future::pending::<()>(),
+            Duration::from_millis(30)
+        )),
+        Err(timer::Elapsed),
+        "A future that never completes times out.",
+    );
+    assert_eq!(
+        block_on(task::timeout(
+            task::sleep(Duration::from_millis(10)),
+            Duration::from_secs(60),
+        )),
+        Ok(()),
+        "A future completing in time is not cancelled.",
+    );
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    self_rust_os::test_panic_handler(info)
+}
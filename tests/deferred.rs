@@ -0,0 +1,132 @@
+//! Tests for the deferred work of interrupt handlers, and the rule keeping
+//! them from printing.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(self_rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![allow(missing_docs)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::expect_used)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use self_rust_os::{
+    allocator, interrupts, memory, println,
+    task::{
+        deferred::{self, DeferError},
+        executor::Executor,
+        Task,
+    },
+    vga_buffer::macros,
+};
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+entry_point!(test_kernel_main);
+
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    self_rust_os::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    // SAFETY: Physical memory offset is valid as guaranteed by the bootloader.
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+
+    // SAFETY: The memory map is valid as guaranteed by the bootloader.
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map);
+    }
+    memory::with_frame_allocator(|frame_allocator| {
+        allocator::init_heap(&mut mapper, frame_allocator)
+    })
+    .expect("Heap initialization failed.");
+    deferred::init();
+
+    test_main();
+
+    self_rust_os::hlt_loop();
+}
+
+/// Arguments of the work items run so far.
+static LOG: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+fn record(argument: u64) {
+    LOG.lock().push(argument);
+}
+
+const fn nothing(_: u64) {}
+
+#[test_case]
+fn test_deferred_work_runs_in_order_before_normal_tasks() {
+    LOG.lock().clear();
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async {
+        record(u64::MAX);
+    }));
+    for argument in 0..5 {
+        deferred::defer(record, argument).expect("The queue has room.");
+    }
+
+    executor.spawn(deferred::worker());
+    executor.run_until_idle();
+
+    assert_eq!(
+        *LOG.lock(),
+        [0, 1, 2, 3, 4, u64::MAX],
+        "Deferred work runs in order, before the normal task.",
+    );
+    assert_eq!(executor.len(), 1, "The worker waits for more work.");
+
+    deferred::defer(record, 5).expect("The queue has room.");
+    executor.run_until_idle();
+    assert_eq!(LOG.lock().last(), Some(&5), "Deferring wakes the worker.");
+}
+
+#[test_case]
+fn test_work_is_dropped_when_the_queue_is_full() {
+    let dropped = deferred::dropped();
+
+    let mut queued = 0;
+    let error = loop {
+        match deferred::defer(nothing, 0) {
+            Ok(()) => queued += 1,
+            Err(error) => break error,
+        }
+    };
+    assert_eq!(error, DeferError::Full, "The queue is full.");
+    assert!(
+        queued <= deferred::CAPACITY,
+        "The queue has a fixed capacity."
+    );
+    assert!(
+        deferred::dropped() > dropped,
+        "The dropped item is counted."
+    );
+
+    let mut executor = Executor::new();
+    executor.spawn(deferred::worker());
+    executor.run_until_idle();
+    assert_eq!(deferred::pending(), 0, "The worker ran every batch.");
+}
+
+#[test_case]
+fn test_tasks_are_not_in_interrupt_context() {
+    let skipped = macros::skipped_prints();
+
+    assert!(
+        !interrupts::in_hardware_interrupt(),
+        "Tests do not run in an interrupt handler.",
+    );
+    println!("Printing from a task is allowed.");
+    assert_eq!(macros::skipped_prints(), skipped, "Nothing was skipped.");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    self_rust_os::test_panic_handler(info)
+}
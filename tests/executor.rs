@@ -0,0 +1,292 @@
+//! Tests for the async executor: spawning from running tasks, join handles
+//! and cancellation.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(self_rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![allow(missing_docs)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::expect_used)]
+
+extern crate alloc;
+
+use alloc::{rc::Rc, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use core::{
+    cell::{Cell, RefCell},
+    future::{self, Future},
+    panic::PanicInfo,
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+use self_rust_os::{
+    allocator, memory,
+    task::{
+        self,
+        executor::{self, Executor},
+        timer, Priority, Task,
+    },
+};
+use x86_64::{instructions::hlt, VirtAddr};
+
+entry_point!(test_kernel_main);
+
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    self_rust_os::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    // SAFETY: Physical memory offset is valid as guaranteed by the bootloader.
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+
+    // SAFETY: The memory map is valid as guaranteed by the bootloader.
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map);
+    }
+    memory::with_frame_allocator(|frame_allocator| {
+        allocator::init_heap(&mut mapper, frame_allocator)
+    })
+    .expect("Heap initialization failed.");
+
+    test_main();
+
+    self_rust_os::hlt_loop();
+}
+
+#[test_case]
+fn test_running_task_spawns_and_joins_another() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let result = Rc::new(Cell::new(0));
+
+    let output = result.clone();
+    executor.spawn(Task::new(async move {
+        let handle = spawner.spawn(async { 6 * 7 });
+        output.set(handle.await);
+    }));
+    executor.run_until_idle();
+
+    assert_eq!(result.get(), 42, "The output of the child is joined.");
+    assert!(executor.is_empty(), "Both tasks have completed.");
+}
+
+#[test_case]
+fn test_dropping_the_handle_cancels_the_task() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let timers = timer::pending();
+    let woke_up = Rc::new(Cell::new(false));
+
+    let flag = woke_up.clone();
+    let handle = spawner.spawn(async move {
+        task::sleep(Duration::from_secs(60)).await;
+        flag.set(true);
+    });
+    executor.run_until_idle();
+    assert_eq!(executor.len(), 1, "The task waits for its timer.");
+    assert!(!handle.is_finished(), "The task has not completed.");
+
+    drop(handle);
+    executor.run_until_idle();
+    assert!(executor.is_empty(), "The cancelled task is removed.");
+    assert!(!woke_up.get(), "The cancelled task never resumed.");
+    assert_eq!(timer::pending(), timers, "Its timer is cancelled too.");
+}
+
+#[test_case]
+fn test_detached_task_runs_to_completion() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let done = Rc::new(Cell::new(false));
+
+    let flag = done.clone();
+    spawner
+        .spawn(async move {
+            task::sleep(Duration::from_millis(20)).await;
+            flag.set(true);
+        })
+        .detach();
+
+    while !executor.is_empty() {
+        executor.run_until_idle();
+        hlt();
+    }
+    assert!(done.get(), "The detached task completed.");
+}
+
+#[test_case]
+fn test_more_tasks_than_the_old_queue_capacity() {
+    let mut executor = Executor::new();
+    let completed = Rc::new(Cell::new(0));
+
+    for _ in 0..500 {
+        let counter = completed.clone();
+        executor.spawn(Task::new(async move {
+            counter.set(counter.get() + 1);
+        }));
+    }
+    executor.run_until_idle();
+
+    assert_eq!(completed.get(), 500, "Every task ran.");
+    assert!(executor.is_empty(), "Every task completed.");
+}
+
+#[test_case]
+fn test_burst_of_wakeups_is_coalesced() {
+    let mut executor = Executor::new();
+    let before = executor.stats();
+
+    let mut first_poll = true;
+    executor.spawn(Task::new(future::poll_fn(move |cx| {
+        if !first_poll {
+            return Poll::Ready(());
+        }
+        first_poll = false;
+        for _ in 0..1000 {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    })));
+    executor.run_until_idle();
+
+    let after = executor.stats();
+    assert_eq!(after.tasks, 0, "The task completed.");
+    assert_eq!(after.polls - before.polls, 2, "The task was polled twice.");
+    assert_eq!(
+        after.wakes - before.wakes,
+        1001,
+        "The spawn and every wakeup are counted.",
+    );
+    assert_eq!(
+        after.coalesced_wakes - before.coalesced_wakes,
+        999,
+        "Only the first wakeup queued the task again.",
+    );
+}
+
+#[test_case]
+fn test_higher_priority_classes_run_first() {
+    let mut executor = Executor::new();
+    let order = Rc::new(RefCell::new(Vec::new()));
+
+    for priority in [Priority::Idle, Priority::Normal, Priority::BottomHalf] {
+        let log = order.clone();
+        executor.spawn(
+            Task::new(async move {
+                log.borrow_mut().push(priority);
+            })
+            .with_priority(priority),
+        );
+    }
+    executor.run_until_idle();
+
+    assert_eq!(
+        *order.borrow(),
+        [Priority::BottomHalf, Priority::Normal, Priority::Idle],
+        "Tasks run from the highest class to the lowest.",
+    );
+}
+
+#[test_case]
+fn test_lower_classes_are_not_starved() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let normal_ran = Rc::new(Cell::new(false));
+    let hog_polls = Rc::new(Cell::new(0_u32));
+    let polls_before_normal = Rc::new(Cell::new(0_u32));
+
+    // A bottom half keeping the executor busy until the normal task has run.
+    let (ran, polls) = (normal_ran.clone(), hog_polls.clone());
+    spawner
+        .spawn_with_priority(
+            future::poll_fn(move |cx| {
+                if ran.get() {
+                    return Poll::Ready(());
+                }
+                polls.set(polls.get() + 1);
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }),
+            Priority::BottomHalf,
+        )
+        .detach();
+
+    let (ran, seen) = (normal_ran.clone(), polls_before_normal.clone());
+    spawner
+        .spawn(async move {
+            seen.set(hog_polls.get());
+            ran.set(true);
+        })
+        .detach();
+
+    executor.run_until_idle();
+
+    assert!(normal_ran.get(), "The normal task ran.");
+    assert!(executor.is_empty(), "Both tasks completed.");
+    assert_eq!(
+        polls_before_normal.get(),
+        executor::STARVATION_LIMIT,
+        "The normal task waited for STARVATION_LIMIT rounds.",
+    );
+}
+
+#[test_case]
+fn test_task_waking_itself_is_throttled() {
+    let mut executor = Executor::new();
+    let before = executor.stats();
+
+    let mut polls = 0;
+    executor.spawn(Task::new(future::poll_fn(move |cx| {
+        polls += 1;
+        if polls > executor::POLL_BUDGET {
+            return Poll::Ready(());
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })));
+    executor.run_until_idle();
+
+    let after = executor.stats();
+    assert_eq!(after.tasks, 0, "The task completed.");
+    assert_eq!(
+        after.throttled - before.throttled,
+        1,
+        "The task was throttled once its budget was used up.",
+    );
+}
+
+#[test_case]
+fn test_task_woken_by_interrupts_is_not_throttled() {
+    let mut executor = Executor::new();
+    let before = executor.stats();
+
+    let mut polls = 0;
+    executor.spawn(Task::new(future::poll_fn(move |cx| {
+        polls += 1;
+        if polls > executor::POLL_BUDGET {
+            return Poll::Ready(());
+        }
+        // Wait for the timer interrupt to wake the task during the poll.
+        let mut sleep = pin!(task::sleep(Duration::from_millis(1)));
+        while sleep.as_mut().poll(cx).is_pending() {
+            hlt();
+        }
+        Poll::Pending
+    })));
+    executor.run_until_idle();
+
+    let after = executor.stats();
+    assert_eq!(after.tasks, 0, "The task completed.");
+    assert_eq!(
+        after.throttled, before.throttled,
+        "Wakeups by interrupt handlers do not use up the budget.",
+    );
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    self_rust_os::test_panic_handler(info)
+}
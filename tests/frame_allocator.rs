@@ -0,0 +1,178 @@
+//! Tests for the physical frame allocator: allocation statistics, reuse of
+//! freed frames and reference counting of shared frames.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(self_rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![allow(missing_docs)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::expect_used)]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use self_rust_os::memory::{self, BootInfoFrameAllocator, FrameReferences};
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator},
+    VirtAddr,
+};
+
+/// Frame allocator shared with the test cases.
+static FRAME_ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
+entry_point!(test_kernel_main);
+
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    self_rust_os::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    // SAFETY: Physical memory offset is valid as guaranteed by the bootloader.
+    let _mapper = unsafe { memory::init(phys_mem_offset) };
+
+    // SAFETY: The memory map is valid as guaranteed by the bootloader.
+    *FRAME_ALLOCATOR.lock() = Some(unsafe { BootInfoFrameAllocator::new(&boot_info.memory_map) });
+
+    test_main();
+
+    self_rust_os::hlt_loop();
+}
+
+#[test_case]
+fn test_allocation_updates_statistics() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let frame_allocator = guard
+        .as_mut()
+        .expect("Frame allocator should be initialized.");
+
+    let before = frame_allocator.stats();
+    assert_eq!(
+        before.used + before.free,
+        before.total,
+        "Every usable frame is either used or free.",
+    );
+
+    let frame = frame_allocator
+        .allocate_frame()
+        .expect("Allocation failed.");
+    let allocated = frame_allocator.stats();
+    assert_eq!(allocated.used, before.used + 1, "One more frame is used.");
+    assert_eq!(allocated.free, before.free - 1, "One less frame is free.");
+    assert_eq!(allocated.total, before.total, "The total never changes.");
+
+    // SAFETY: The frame was just allocated and is not used.
+    unsafe {
+        frame_allocator.deallocate_frame(frame);
+    }
+    assert_eq!(
+        frame_allocator.stats(),
+        before,
+        "Freeing the frame restores the statistics.",
+    );
+}
+
+#[test_case]
+fn test_freed_frame_is_reused() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let frame_allocator = guard
+        .as_mut()
+        .expect("Frame allocator should be initialized.");
+
+    let first = frame_allocator
+        .allocate_frame()
+        .expect("Allocation failed.");
+    let second = frame_allocator
+        .allocate_frame()
+        .expect("Allocation failed.");
+    assert_ne!(first, second, "A frame must not be handed out twice.");
+
+    // SAFETY: The frame was just allocated and is not used.
+    unsafe {
+        frame_allocator.deallocate_frame(first);
+    }
+    let third = frame_allocator
+        .allocate_frame()
+        .expect("Allocation failed.");
+    assert_eq!(third, first, "The lowest free frame is allocated first.");
+
+    // SAFETY: Both frames were allocated by this test and are not used.
+    unsafe {
+        frame_allocator.deallocate_frame(second);
+        frame_allocator.deallocate_frame(third);
+    }
+}
+
+#[test_case]
+fn test_shared_frame_is_freed_with_its_last_reference() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let frame_allocator = guard
+        .as_mut()
+        .expect("Frame allocator should be initialized.");
+
+    let before = frame_allocator.stats();
+    let frame = frame_allocator
+        .allocate_frame()
+        .expect("Allocation failed.");
+    frame_allocator.add_reference(frame);
+    assert_eq!(frame_allocator.reference_count(frame), 2, "Two references.");
+
+    // SAFETY: The frame was allocated by this test and is not used.
+    unsafe {
+        frame_allocator.deallocate_frame(frame);
+    }
+    assert_eq!(
+        frame_allocator.reference_count(frame),
+        1,
+        "Dropping a reference keeps the frame allocated.",
+    );
+    assert_eq!(frame_allocator.stats().used, before.used + 1, "Still used.");
+
+    // SAFETY: The frame was allocated by this test and is not used.
+    unsafe {
+        frame_allocator.deallocate_frame(frame);
+    }
+    assert_eq!(
+        frame_allocator.reference_count(frame),
+        0,
+        "The last reference frees the frame.",
+    );
+    assert_eq!(frame_allocator.stats(), before, "The frame is free again.");
+}
+
+#[test_case]
+fn test_saturated_frame_is_never_freed() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let frame_allocator = guard
+        .as_mut()
+        .expect("Frame allocator should be initialized.");
+
+    let frame = frame_allocator
+        .allocate_frame()
+        .expect("Allocation failed.");
+    while frame_allocator.reference_count(frame) < u16::MAX {
+        frame_allocator.add_reference(frame);
+    }
+    let before = frame_allocator.stats();
+
+    // SAFETY: The frame was allocated by this test and is not used.
+    unsafe {
+        frame_allocator.deallocate_frame(frame);
+    }
+    assert_eq!(
+        frame_allocator.reference_count(frame),
+        u16::MAX,
+        "The saturated count is not decremented.",
+    );
+    assert_eq!(
+        frame_allocator.stats().free,
+        before.free,
+        "The saturated frame stays allocated.",
+    );
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    self_rust_os::test_panic_handler(info)
+}
@@ -0,0 +1,275 @@
+//! Tests for the synchronization primitives of async tasks: channels, mutex,
+//! semaphore and notify.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(self_rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![allow(missing_docs)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::expect_used)]
+
+extern crate alloc;
+
+use alloc::{rc::Rc, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use core::{cell::RefCell, panic::PanicInfo};
+use self_rust_os::{
+    allocator, memory,
+    task::{
+        executor::Executor,
+        sync::{mpsc, oneshot, Mutex, Notify, Semaphore},
+        Task,
+    },
+};
+use x86_64::VirtAddr;
+
+entry_point!(test_kernel_main);
+
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    self_rust_os::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    // SAFETY: Physical memory offset is valid as guaranteed by the bootloader.
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+
+    // SAFETY: The memory map is valid as guaranteed by the bootloader.
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map);
+    }
+    memory::with_frame_allocator(|frame_allocator| {
+        allocator::init_heap(&mut mapper, frame_allocator)
+    })
+    .expect("Heap initialization failed.");
+
+    test_main();
+
+    self_rust_os::hlt_loop();
+}
+
+#[test_case]
+fn test_unbounded_channel_delivers_in_order() {
+    let mut executor = Executor::new();
+    let (sender, mut receiver) = mpsc::unbounded();
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    let log = received.clone();
+    executor.spawn(Task::new(async move {
+        while let Some(value) = receiver.recv().await {
+            log.borrow_mut().push(value);
+        }
+    }));
+    executor.run_until_idle();
+    assert_eq!(executor.len(), 1, "The receiver waits for values.");
+
+    let other = sender.clone();
+    for value in 0..3 {
+        sender.send(value).expect("The receiver is alive.");
+    }
+    other.send(3).expect("The receiver is alive.");
+    executor.run_until_idle();
+    assert_eq!(*received.borrow(), [0, 1, 2, 3], "Values arrive in order.");
+
+    drop((sender, other));
+    executor.run_until_idle();
+    assert!(
+        executor.is_empty(),
+        "Dropping every sender closes the channel."
+    );
+}
+
+#[test_case]
+fn test_bounded_channel_applies_backpressure() {
+    let mut executor = Executor::new();
+    let (sender, mut receiver) = mpsc::channel(2);
+    let sent = Rc::new(RefCell::new(Vec::new()));
+
+    let log = sent.clone();
+    executor.spawn(Task::new(async move {
+        for value in 0..5 {
+            sender.send(value).await.expect("The receiver is alive.");
+            log.borrow_mut().push(value);
+        }
+    }));
+    executor.run_until_idle();
+    assert_eq!(*sent.borrow(), [0, 1], "The sender waits once it is full.");
+
+    assert_eq!(receiver.try_recv(), Ok(0), "Values arrive in order.");
+    executor.run_until_idle();
+    assert_eq!(*sent.borrow(), [0, 1, 2], "Receiving makes room.");
+
+    assert_eq!(receiver.try_recv(), Ok(1), "Values arrive in order.");
+    assert_eq!(receiver.try_recv(), Ok(2), "Values arrive in order.");
+    executor.run_until_idle();
+    assert_eq!(*sent.borrow(), [0, 1, 2, 3, 4], "Every value is sent.");
+    assert!(executor.is_empty(), "The sender completed.");
+
+    assert_eq!(receiver.try_recv(), Ok(3), "Values arrive in order.");
+    assert_eq!(receiver.try_recv(), Ok(4), "Values arrive in order.");
+    assert_eq!(
+        receiver.try_recv(),
+        Err(mpsc::TryRecvError::Disconnected),
+        "The sender is gone.",
+    );
+}
+
+#[test_case]
+fn test_dropping_the_receiver_closes_a_full_channel() {
+    let mut executor = Executor::new();
+    let (sender, receiver) = mpsc::channel(1);
+    let result = Rc::new(RefCell::new(None));
+
+    assert_eq!(sender.try_send(1), Ok(()), "The channel has room.");
+    assert_eq!(
+        sender.try_send(2),
+        Err(mpsc::TrySendError::Full(2)),
+        "The channel is full.",
+    );
+
+    let output = result.clone();
+    executor.spawn(Task::new(async move {
+        *output.borrow_mut() = Some(sender.send(3).await);
+    }));
+    executor.run_until_idle();
+    assert_eq!(executor.len(), 1, "The sender waits for room.");
+
+    drop(receiver);
+    executor.run_until_idle();
+    assert_eq!(
+        *result.borrow(),
+        Some(Err(mpsc::SendError(3))),
+        "The waiting sender gets its value back.",
+    );
+}
+
+#[test_case]
+fn test_oneshot_channel() {
+    let mut executor = Executor::new();
+    let (sender, receiver) = oneshot::channel();
+    let result = Rc::new(RefCell::new(None));
+
+    let output = result.clone();
+    executor.spawn(Task::new(async move {
+        *output.borrow_mut() = Some(receiver.await);
+    }));
+    executor.run_until_idle();
+    assert_eq!(*result.borrow(), None, "The receiver waits for the value.");
+
+    sender.send(42).expect("The receiver is alive.");
+    executor.run_until_idle();
+    assert_eq!(*result.borrow(), Some(Ok(42)), "The value is received.");
+
+    let (sender, receiver) = oneshot::channel::<()>();
+    drop(sender);
+    let output = result.clone();
+    executor.spawn(Task::new(async move {
+        *output.borrow_mut() = Some(receiver.await.map(|()| 0));
+    }));
+    executor.run_until_idle();
+    assert_eq!(
+        *result.borrow(),
+        Some(Err(oneshot::RecvError)),
+        "Dropping the sender closes the channel.",
+    );
+
+    let (sender, receiver) = oneshot::channel();
+    drop(receiver);
+    assert!(sender.is_closed(), "The receiver is gone.");
+    assert_eq!(sender.send(7), Err(7), "The value is given back.");
+}
+
+#[test_case]
+fn test_mutex_is_held_across_await_points() {
+    static MUTEX: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    let mut executor = Executor::new();
+    let (sender, receiver) = oneshot::channel::<()>();
+
+    executor.spawn(Task::new(async move {
+        let mut guard = MUTEX.lock().await;
+        guard.push(1);
+        receiver.await.expect("The sender sends.");
+        guard.push(2);
+    }));
+    executor.spawn(Task::new(async {
+        MUTEX.lock().await.push(3);
+    }));
+    executor.run_until_idle();
+    assert_eq!(executor.len(), 2, "The second task waits for the lock.");
+    assert!(MUTEX.try_lock().is_none(), "The mutex is locked.");
+
+    sender.send(()).expect("The receiver is alive.");
+    executor.run_until_idle();
+    assert!(executor.is_empty(), "Both tasks completed.");
+    assert_eq!(
+        *MUTEX.try_lock().expect("The mutex is unlocked."),
+        [1, 2, 3],
+        "The first task kept the lock until it released it.",
+    );
+}
+
+#[test_case]
+fn test_semaphore_limits_concurrent_tasks() {
+    static SEMAPHORE: Semaphore = Semaphore::new(2);
+
+    let mut executor = Executor::new();
+    let gate = Rc::new(Notify::new());
+    let running = Rc::new(RefCell::new(Vec::new()));
+
+    for id in 0..4 {
+        let (released, log) = (gate.clone(), running.clone());
+        executor.spawn(Task::new(async move {
+            let _permit = SEMAPHORE.acquire().await;
+            log.borrow_mut().push(id);
+            released.notified().await;
+        }));
+    }
+    executor.run_until_idle();
+    assert_eq!(*running.borrow(), [0, 1], "Only two tasks hold a permit.");
+    assert_eq!(SEMAPHORE.available_permits(), 0, "Every permit is taken.");
+
+    gate.notify_waiters();
+    executor.run_until_idle();
+    assert_eq!(
+        *running.borrow(),
+        [0, 1, 2, 3],
+        "Released permits go to the waiting tasks.",
+    );
+
+    gate.notify_waiters();
+    executor.run_until_idle();
+    assert!(executor.is_empty(), "Every task completed.");
+    assert_eq!(SEMAPHORE.available_permits(), 2, "Every permit is back.");
+}
+
+#[test_case]
+fn test_notify_stores_a_permit() {
+    let mut executor = Executor::new();
+    let notify = Rc::new(Notify::new());
+
+    notify.notify_one();
+    let waiter = notify.clone();
+    executor.spawn(Task::new(async move {
+        waiter.notified().await;
+    }));
+    executor.run_until_idle();
+    assert!(executor.is_empty(), "The stored permit is consumed.");
+
+    let waiter = notify.clone();
+    executor.spawn(Task::new(async move {
+        waiter.notified().await;
+    }));
+    executor.run_until_idle();
+    assert_eq!(executor.len(), 1, "No permit is left.");
+
+    notify.notify_one();
+    executor.run_until_idle();
+    assert!(executor.is_empty(), "The waiting task is woken.");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    self_rust_os::test_panic_handler(info)
+}
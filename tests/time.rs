@@ -0,0 +1,138 @@
+//! Tests for the monotonic clock and the timer interrupt rate.
+
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(self_rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![allow(missing_docs)]
+#![allow(clippy::missing_panics_doc)]
+
+use bootloader::{entry_point, BootInfo};
+use core::{panic::PanicInfo, time::Duration};
+use self_rust_os::{memory, time};
+use x86_64::{instructions::hlt, VirtAddr};
+
+entry_point!(test_kernel_main);
+
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    self_rust_os::init();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    // SAFETY: Physical memory offset is valid as guaranteed by the bootloader.
+    let _mapper = unsafe { memory::init(phys_mem_offset) };
+
+    // SAFETY: The memory map is valid as guaranteed by the bootloader.
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map);
+    }
+
+    test_main();
+
+    self_rust_os::hlt_loop();
+}
+
+/// Waits for the next `count` timer interrupts.
+fn wait_ticks(count: u64) {
+    let start = time::ticks();
+    while time::ticks() - start < count {
+        hlt();
+    }
+}
+
+#[test_case]
+fn test_default_frequency() {
+    assert_eq!(
+        time::frequency(),
+        time::DEFAULT_FREQUENCY,
+        "init programs the default rate.",
+    );
+}
+
+#[test_case]
+fn test_pit_clock_advances_with_ticks() {
+    let start = time::now();
+    wait_ticks(10);
+    assert!(
+        start.elapsed() >= time::tick_period() * 9,
+        "Ten ticks cover at least nine tick periods.",
+    );
+}
+
+#[test_case]
+fn test_set_frequency_changes_the_tick_rate() {
+    time::set_frequency(1000);
+    assert_eq!(time::frequency(), 1000, "The PIT runs at 1 kHz.");
+    assert!(
+        time::tick_period() < Duration::from_micros(1001),
+        "A tick lasts about 1 ms.",
+    );
+
+    let start = time::now();
+    wait_ticks(100);
+    assert!(
+        start.elapsed() >= Duration::from_millis(99),
+        "100 ticks at 1 kHz last about 100 ms.",
+    );
+
+    time::set_frequency(time::DEFAULT_FREQUENCY);
+    assert_eq!(
+        time::frequency(),
+        time::DEFAULT_FREQUENCY,
+        "The default rate is restored.",
+    );
+}
+
+#[test_case]
+fn test_hpet_clock_keeps_counting() {
+    let before = time::now();
+    let uses_hpet = time::init_hpet();
+    assert_eq!(
+        uses_hpet,
+        time::uses_hpet(),
+        "init_hpet reports the source."
+    );
+
+    let after = time::now();
+    assert!(after >= before, "Switching source never goes backwards.");
+
+    wait_ticks(2);
+    assert!(
+        time::now() > after,
+        "The clock advances after switching source.",
+    );
+    assert!(
+        time::uptime() >= after.saturating_duration_since(time::Instant::ZERO),
+        "The uptime follows the clock.",
+    );
+}
+
+#[test_case]
+fn test_wall_time_follows_the_monotonic_clock() {
+    /// 2020-01-01T00:00:00Z.
+    const YEAR_2020: u64 = 1_577_836_800;
+
+    assert!(
+        time::boot_time().as_secs() >= YEAR_2020,
+        "The RTC holds a recent date.",
+    );
+
+    let uptime = time::uptime();
+    let wall = time::wall_time();
+    assert!(
+        wall >= time::boot_time() + uptime,
+        "The wall time is the boot time plus the uptime, read later.",
+    );
+
+    let rtc = time::rtc::read().unix_timestamp();
+    assert!(
+        rtc.abs_diff(time::wall_time().as_secs()) <= 1,
+        "The wall time agrees with the RTC, to the second.",
+    );
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    self_rust_os::test_panic_handler(info)
+}
@@ -11,17 +11,79 @@
 #![test_runner(self_rust_os::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 #![allow(missing_docs)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::expect_used)]
 
 extern crate alloc;
 
+use alloc::{vec, vec::Vec};
 use bootloader::{entry_point, BootInfo};
-use core::panic::PanicInfo;
+use core::{panic::PanicInfo, ptr, time::Duration};
 use self_rust_os::{
     allocator,
-    memory::{self, BootInfoFrameAllocator},
-    serial_println, userspace,
+    interrupts::exceptions::Exception,
+    memory::{self, FrameReferences, FrameStats},
+    serial_println, time,
+    userspace::{
+        self,
+        address_space::{AddressSpace, COPY_ON_WRITE},
+        elf::ElfError,
+        errno::Errno,
+        process::{self, ExitStatus, LoadError},
+        programs,
+        vma::Access,
+    },
 };
-use x86_64::VirtAddr;
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{Page, PageTableFlags},
+    VirtAddr,
+};
+
+/// The hello user program, embedded as an ELF executable.
+static HELLO_ELF: &[u8] = include_bytes!("../user_programs/hello/hello.elf");
+
+/// Builds a minimal ELF executable whose single read-only, executable
+/// segment holds `code`, starting at the entry point.
+fn user_program(code: &[u8]) -> Vec<u8> {
+    const HEADERS_SIZE: u64 = 64 + 56;
+    let file_size = HEADERS_SIZE + code.len() as u64;
+
+    let mut elf = Vec::new();
+    // ELF header: 64-bit, little-endian, current version, x86-64 executable.
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&2_u16.to_le_bytes());
+    elf.extend_from_slice(&62_u16.to_le_bytes());
+    elf.extend_from_slice(&1_u32.to_le_bytes());
+    elf.extend_from_slice(&(userspace::USER_CODE_START + HEADERS_SIZE).to_le_bytes());
+    elf.extend_from_slice(&64_u64.to_le_bytes());
+    elf.extend_from_slice(&0_u64.to_le_bytes());
+    elf.extend_from_slice(&0_u32.to_le_bytes());
+    for field in [64_u16, 56, 1, 0, 0, 0] {
+        elf.extend_from_slice(&field.to_le_bytes());
+    }
+    // Program header: PT_LOAD, PF_R | PF_X, the whole file at USER_CODE_START.
+    elf.extend_from_slice(&1_u32.to_le_bytes());
+    elf.extend_from_slice(&5_u32.to_le_bytes());
+    for field in [
+        0,
+        userspace::USER_CODE_START,
+        userspace::USER_CODE_START,
+        file_size,
+        file_size,
+        0x1000,
+    ] {
+        elf.extend_from_slice(&field.to_le_bytes());
+    }
+    elf.extend_from_slice(code);
+    elf
+}
+
+/// Returns the usage of the kernel frame allocator.
+fn frame_stats() -> FrameStats {
+    memory::with_frame_allocator(|frame_allocator| frame_allocator.stats())
+}
 
 entry_point!(test_kernel_main);
 
@@ -34,9 +96,14 @@ fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
 
     // SAFETY: The memory map is valid as guaranteed by the bootloader.
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::new(&boot_info.memory_map) };
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map);
+    }
 
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("Heap initialization failed.");
+    memory::with_frame_allocator(|frame_allocator| {
+        allocator::init_heap(&mut mapper, frame_allocator)
+    })
+    .expect("Heap initialization failed.");
 
     test_main();
 
@@ -80,10 +147,14 @@ fn test_userspace_module_constants_are_consistent() {
         "USER_CODE_START must be page-aligned.",
     );
 
-    // Code and stack regions must not overlap.
+    // Code, anonymous mapping and stack regions must not overlap.
+    assert!(
+        userspace::USER_CODE_START < userspace::USER_MMAP_START,
+        "User code region must be below the anonymous mapping region.",
+    );
     assert!(
-        userspace::USER_CODE_START < userspace::USER_STACK_BOTTOM,
-        "User code region must be below user stack region.",
+        userspace::USER_MMAP_END <= userspace::USER_STACK_GUARD_PAGE,
+        "Anonymous mapping region must be below the stack guard page.",
     );
 
     serial_println!("[ok] userspace constants are consistent");
@@ -99,6 +170,604 @@ fn test_syscall_interrupt_index_is_0x80() {
     );
 }
 
+/// Verify that a malformed executable is rejected with a typed error.
+#[test_case]
+fn test_spawn_rejects_malformed_elf() {
+    assert_eq!(
+        process::spawn(&HELLO_ELF[..16]),
+        Err(LoadError::InvalidElf(ElfError::Truncated)),
+        "A truncated ELF header must be rejected.",
+    );
+}
+
+/// Verify that two instances of the same program can be loaded at the same
+/// time and that both run to completion with exit code 0.
+#[test_case]
+fn test_two_processes_run_to_completion() {
+    let first = process::spawn(HELLO_ELF).expect("First spawn failed.");
+    let second = process::spawn(HELLO_ELF).expect("Second spawn failed.");
+    assert_ne!(first, second, "Each process must get its own PID.");
+
+    let (kernel_table, _) = Cr3::read();
+    process::run();
+
+    assert_eq!(
+        Cr3::read().0,
+        kernel_table,
+        "The kernel page table must be active again once every process exited.",
+    );
+
+    assert_eq!(
+        process::exit_code(first),
+        Some(0),
+        "First process exit code."
+    );
+    assert_eq!(
+        process::exit_code(second),
+        Some(0),
+        "Second process exit code."
+    );
+}
+
+/// Verify that every frame of a process is released once it has exited.
+#[test_case]
+fn test_exited_process_releases_its_frames() {
+    let before = frame_stats();
+    let pid = process::spawn(HELLO_ELF).expect("Spawn failed.");
+    assert!(
+        frame_stats().used > before.used,
+        "Loading a process allocates frames.",
+    );
+
+    process::run();
+
+    assert_eq!(process::exit_code(pid), Some(0), "Process exit code.");
+    assert_eq!(
+        frame_stats(),
+        before,
+        "Every frame of the process must be given back.",
+    );
+}
+
+/// Verify that a program can be executed again once it has exited, and that
+/// the kernel collects its exit status and releases its memory every time.
+#[test_case]
+fn test_execute_reports_the_exit_status_and_cleans_up() {
+    // mov edi, 3; xor eax, eax; syscall
+    let exit_3 = user_program(&[0xbf, 0x03, 0x00, 0x00, 0x00, 0x31, 0xc0, 0x0f, 0x05]);
+
+    let before = frame_stats();
+    for _ in 0..3 {
+        assert_eq!(
+            process::execute(HELLO_ELF),
+            Ok(ExitStatus::Exited(0)),
+            "The hello program must run every time.",
+        );
+        assert_eq!(
+            process::execute(&exit_3),
+            Ok(ExitStatus::Exited(3)),
+            "Another program must run after it.",
+        );
+    }
+    assert_eq!(frame_stats(), before, "Every frame must be given back.");
+}
+
+/// Verify that a process killed by the kernel before it runs is reported as
+/// killed by the signal and that its memory is released.
+#[test_case]
+fn test_kill_terminates_a_process_with_a_signal() {
+    let before = frame_stats();
+    let pid = process::spawn(HELLO_ELF).expect("Spawn failed.");
+
+    assert!(
+        process::kill(pid, process::SIGKILL),
+        "The process is alive."
+    );
+    assert!(
+        !process::kill(pid, process::SIGKILL),
+        "A process can only be killed once.",
+    );
+    process::run();
+
+    assert_eq!(
+        process::reap(pid),
+        Some(ExitStatus::Signaled(process::SIGKILL)),
+        "The process must be reported as killed.",
+    );
+    assert_eq!(process::exit_status(pid), None, "The process was reaped.");
+    assert_eq!(frame_stats(), before, "Every frame must be given back.");
+}
+
+/// Verify that a page fault in Ring 3 only terminates the faulting process,
+/// with a distinctive exit code, while the other processes keep running.
+#[test_case]
+fn test_page_fault_terminates_only_the_faulting_process() {
+    // mov rax, [0x700000] (unmapped address between the code and the stack)
+    let read_unmapped = user_program(&[0x48, 0x8b, 0x04, 0x25, 0x00, 0x00, 0x70, 0x00]);
+    // mov byte [0x400000], 0 (write to the read-only code segment)
+    let write_code = user_program(&[0xc6, 0x04, 0x25, 0x00, 0x00, 0x40, 0x00, 0x00]);
+
+    let before = frame_stats();
+    let first = process::spawn(&read_unmapped).expect("Spawn failed.");
+    let second = process::spawn(&write_code).expect("Spawn failed.");
+    let hello = process::spawn(HELLO_ELF).expect("Spawn failed.");
+
+    process::run();
+
+    assert_eq!(
+        process::exit_status(first),
+        Some(ExitStatus::Faulted {
+            exception: Exception::PageFault,
+            address: 0x70_0000,
+        }),
+        "Reading an unmapped page terminates the process.",
+    );
+    assert_eq!(
+        process::exit_status(second),
+        Some(ExitStatus::Faulted {
+            exception: Exception::PageFault,
+            address: userspace::USER_CODE_START,
+        }),
+        "Writing to a read-only page terminates the process.",
+    );
+    assert_eq!(
+        process::exit_code(first),
+        Some(Exception::PageFault.exit_code()),
+        "The exit code follows the signal convention.",
+    );
+    assert_eq!(
+        process::exit_code(hello),
+        Some(0),
+        "Other processes are not affected.",
+    );
+    assert_eq!(
+        frame_stats(),
+        before,
+        "The frames of terminated processes must be given back.",
+    );
+}
+
+/// Verify that `sys_sleep` blocks a process for the requested time and that
+/// `sys_yield` returns to the caller.
+#[test_case]
+fn test_sleep_and_yield() {
+    // mov eax, SYS_SLEEP; mov edi, 200; syscall; mov rdi, rax; xor eax, eax;
+    // syscall
+    let sleep = user_program(&[
+        0xb8, 0x0a, 0x00, 0x00, 0x00, 0xbf, 0xc8, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0xc7,
+        0x31, 0xc0, 0x0f, 0x05,
+    ]);
+    // mov eax, SYS_YIELD; syscall; mov rdi, rax; xor eax, eax; syscall
+    let yield_now = user_program(&[
+        0xb8, 0x09, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0xc7, 0x31, 0xc0, 0x0f, 0x05,
+    ]);
+
+    let sleep_pid = process::spawn(&sleep).expect("Spawn failed.");
+    let yield_pid = process::spawn(&yield_now).expect("Spawn failed.");
+    let start = time::now();
+
+    process::run();
+
+    assert!(
+        start.elapsed() >= Duration::from_millis(200),
+        "The process must sleep for at least 200 ms.",
+    );
+    assert_eq!(
+        process::exit_code(sleep_pid),
+        Some(0),
+        "sys_sleep returns 0.",
+    );
+    assert_eq!(
+        process::exit_code(yield_pid),
+        Some(0),
+        "sys_yield returns 0.",
+    );
+}
+
+/// Verify that the other CPU exceptions raised in Ring 3 terminate the
+/// faulting process with the exit code of the exception.
+#[test_case]
+fn test_cpu_exceptions_terminate_the_faulting_process() {
+    let programs = [
+        // ud2
+        (user_program(&[0x0f, 0x0b]), Exception::InvalidOpcode),
+        // xor ecx, ecx; div ecx
+        (
+            user_program(&[0x31, 0xc9, 0xf7, 0xf1]),
+            Exception::DivideError,
+        ),
+        // hlt (privileged instruction)
+        (user_program(&[0xf4]), Exception::GeneralProtection),
+    ];
+
+    let before = frame_stats();
+    let pids = programs
+        .map(|(program, exception)| (process::spawn(&program).expect("Spawn failed."), exception));
+
+    process::run();
+
+    for (pid, exception) in pids {
+        assert_eq!(
+            process::exit_code(pid),
+            Some(exception.exit_code()),
+            "The process must be terminated by the exception.",
+        );
+    }
+    assert_eq!(
+        frame_stats(),
+        before,
+        "The frames of terminated processes must be given back.",
+    );
+}
+
+#[test_case]
+fn test_syscall_instruction_enters_the_kernel() {
+    // mov eax, SYS_EXIT; mov edi, 7; syscall
+    let exit = user_program(&[
+        0xb8, 0x00, 0x00, 0x00, 0x00, 0xbf, 0x07, 0x00, 0x00, 0x00, 0x0f, 0x05,
+    ]);
+    // mov eax, SYS_WRITE; mov edi, message; mov esi, 5; syscall;
+    // mov edi, eax; xor eax, eax; syscall; message: "fast\n"
+    let write = user_program(&[
+        0xb8, 0x01, 0x00, 0x00, 0x00, 0xbf, 0x8f, 0x00, 0x40, 0x00, 0xbe, 0x05, 0x00, 0x00, 0x00,
+        0x0f, 0x05, 0x89, 0xc7, 0x31, 0xc0, 0x0f, 0x05, b'f', b'a', b's', b't', b'\n',
+    ]);
+
+    let exit_pid = process::spawn(&exit).expect("Spawn failed.");
+    let write_pid = process::spawn(&write).expect("Spawn failed.");
+
+    process::run();
+
+    assert_eq!(
+        process::exit_code(exit_pid),
+        Some(7),
+        "sys_exit must receive its argument through the syscall instruction.",
+    );
+    assert_eq!(
+        process::exit_code(write_pid),
+        Some(5),
+        "sys_write must return the number of bytes written in rax.",
+    );
+}
+
+#[test_case]
+fn test_sys_write_rejects_unmapped_buffers() {
+    // mov eax, SYS_WRITE; mov edi, <buffer>; mov esi, <len>; syscall;
+    // mov rdi, rax; xor eax, eax; syscall
+    let write_and_exit = |buffer: [u8; 4], len: [u8; 4]| {
+        let mut code = vec![0xb8, 0x01, 0x00, 0x00, 0x00, 0xbf];
+        code.extend_from_slice(&buffer);
+        code.push(0xbe);
+        code.extend_from_slice(&len);
+        code.extend_from_slice(&[0x0f, 0x05, 0x48, 0x89, 0xc7, 0x31, 0xc0, 0x0f, 0x05]);
+        user_program(&code)
+    };
+    // A buffer in an unmapped page of the user range.
+    let unmapped = write_and_exit(0x70_0000_u32.to_le_bytes(), 4_u32.to_le_bytes());
+    // A buffer starting in the code page and running into the next page.
+    let partially_mapped = write_and_exit(
+        u32::try_from(userspace::USER_CODE_START)
+            .expect("The user code fits in 32 bits.")
+            .to_le_bytes(),
+        0x2000_u32.to_le_bytes(),
+    );
+
+    let pids = [unmapped, partially_mapped]
+        .map(|program| process::spawn(&program).expect("Spawn failed."));
+
+    process::run();
+
+    for pid in pids {
+        assert_eq!(
+            process::exit_code(pid),
+            Some(Errno::EFAULT.as_return_value()),
+            "sys_write must fail instead of faulting the kernel.",
+        );
+    }
+}
+
+/// Verify that the heap grows with `sys_brk` and that `sys_mmap` and
+/// `sys_munmap` map and unmap writable anonymous memory.
+#[test_case]
+fn test_brk_and_mmap_map_writable_memory() {
+    // mov eax, SYS_BRK; xor edi, edi; syscall; mov rbx, rax;
+    // lea rdi, [rax + 0x2000]; mov eax, SYS_BRK; syscall; mov byte [rax - 1], 1;
+    // sub rax, rbx; mov rdi, rax; xor eax, eax; syscall
+    let brk = user_program(&[
+        0xb8, 0x02, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05, 0x48, 0x89, 0xc3, 0x48, 0x8d, 0xb8,
+        0x00, 0x20, 0x00, 0x00, 0xb8, 0x02, 0x00, 0x00, 0x00, 0x0f, 0x05, 0xc6, 0x40, 0xff, 0x01,
+        0x48, 0x29, 0xd8, 0x48, 0x89, 0xc7, 0x31, 0xc0, 0x0f, 0x05,
+    ]);
+    // mov eax, SYS_MMAP; xor edi, edi; mov esi, 0x3000; syscall;
+    // mov byte [rax + 0x2fff], 1; mov rdi, rax; xor eax, eax; syscall
+    let mmap = user_program(&[
+        0xb8, 0x03, 0x00, 0x00, 0x00, 0x31, 0xff, 0xbe, 0x00, 0x30, 0x00, 0x00, 0x0f, 0x05, 0xc6,
+        0x80, 0xff, 0x2f, 0x00, 0x00, 0x01, 0x48, 0x89, 0xc7, 0x31, 0xc0, 0x0f, 0x05,
+    ]);
+    // mov eax, SYS_MMAP; xor edi, edi; mov esi, 0x1000; syscall; mov rbx, rax;
+    // mov rdi, rax; mov esi, 0x1000; mov eax, SYS_MUNMAP; syscall;
+    // mov byte [rbx], 1
+    let munmap = user_program(&[
+        0xb8, 0x03, 0x00, 0x00, 0x00, 0x31, 0xff, 0xbe, 0x00, 0x10, 0x00, 0x00, 0x0f, 0x05, 0x48,
+        0x89, 0xc3, 0x48, 0x89, 0xc7, 0xbe, 0x00, 0x10, 0x00, 0x00, 0xb8, 0x04, 0x00, 0x00, 0x00,
+        0x0f, 0x05, 0xc6, 0x03, 0x01,
+    ]);
+
+    let before = frame_stats();
+    let brk_pid = process::spawn(&brk).expect("Spawn failed.");
+    let mmap_pid = process::spawn(&mmap).expect("Spawn failed.");
+    let munmap_pid = process::spawn(&munmap).expect("Spawn failed.");
+
+    process::run();
+
+    assert_eq!(
+        process::exit_code(brk_pid),
+        Some(0x2000),
+        "sys_brk must return the new break and map the heap up to it.",
+    );
+    assert_eq!(
+        process::exit_code(mmap_pid),
+        Some(userspace::USER_MMAP_START),
+        "sys_mmap must map writable memory in the anonymous mapping region.",
+    );
+    assert_eq!(
+        process::exit_code(munmap_pid),
+        Some(Exception::PageFault.exit_code()),
+        "Unmapped memory must no longer be accessible.",
+    );
+    assert_eq!(
+        frame_stats(),
+        before,
+        "The heap and the anonymous mappings must be given back.",
+    );
+}
+
+/// Verify that the user stack grows on demand within its reserved area, and
+/// that running into the guard page below it terminates the process.
+#[test_case]
+fn test_stack_grows_on_demand_and_overflows_into_the_guard_page() {
+    // lea rdi, [rsp - 0x3f000]; mov byte [rdi], 1; xor edi, edi; xor eax, eax;
+    // syscall
+    let deep_stack = user_program(&[
+        0x48, 0x8d, 0xbc, 0x24, 0x00, 0x10, 0xfc, 0xff, 0xc6, 0x07, 0x01, 0x31, 0xff, 0x31, 0xc0,
+        0x0f, 0x05,
+    ]);
+    // loop: push rax; jmp loop
+    let overflow = user_program(&[0x50, 0xeb, 0xfd]);
+
+    let before = frame_stats();
+    let deep_stack_pid = process::spawn(&deep_stack).expect("Spawn failed.");
+    let overflow_pid = process::spawn(&overflow).expect("Spawn failed.");
+
+    process::run();
+
+    assert_eq!(
+        process::exit_code(deep_stack_pid),
+        Some(0),
+        "The stack pages must be mapped when they are first touched.",
+    );
+    assert_eq!(
+        process::exit_code(overflow_pid),
+        Some(Exception::PageFault.exit_code()),
+        "A stack overflow must terminate the process.",
+    );
+    assert_eq!(
+        frame_stats(),
+        before,
+        "The stack pages mapped on demand must be given back.",
+    );
+}
+
+/// Verify that a forked child runs on a copy of the memory of its parent, and
+/// that the parent collects its exit code with `sys_waitpid`.
+#[test_case]
+fn test_fork_and_waitpid() {
+    // push 5; mov eax, SYS_FORK; syscall; test rax, rax; jnz parent;
+    // mov qword [rsp], 9; mov edi, 42; xor eax, eax; syscall;
+    // parent: push 0; mov rdi, rax; mov rsi, rsp; mov eax, SYS_WAITPID; syscall;
+    // mov rdi, [rsp]; add rdi, [rsp + 8]; xor eax, eax; syscall
+    let fork = user_program(&[
+        0x6a, 0x05, 0xb8, 0x05, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x85, 0xc0, 0x75, 0x11, 0x48,
+        0xc7, 0x04, 0x24, 0x09, 0x00, 0x00, 0x00, 0xbf, 0x2a, 0x00, 0x00, 0x00, 0x31, 0xc0, 0x0f,
+        0x05, 0x6a, 0x00, 0x48, 0x89, 0xc7, 0x48, 0x89, 0xe6, 0xb8, 0x08, 0x00, 0x00, 0x00, 0x0f,
+        0x05, 0x48, 0x8b, 0x3c, 0x24, 0x48, 0x03, 0x7c, 0x24, 0x08, 0x31, 0xc0, 0x0f, 0x05,
+    ]);
+    // mov eax, SYS_WAIT; xor edi, edi; syscall; mov rdi, rax; xor eax, eax;
+    // syscall
+    let wait_without_child = user_program(&[
+        0xb8, 0x07, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05, 0x48, 0x89, 0xc7, 0x31, 0xc0, 0x0f,
+        0x05,
+    ]);
+
+    let before = frame_stats();
+    let fork_pid = process::spawn(&fork).expect("Spawn failed.");
+    let wait_pid = process::spawn(&wait_without_child).expect("Spawn failed.");
+
+    process::run();
+
+    assert_eq!(
+        process::exit_code(fork_pid),
+        Some(42 + 5),
+        "The parent must get the exit code of the child and keep its own memory.",
+    );
+    assert_eq!(
+        process::exit_code(wait_pid),
+        Some(Errno::ECHILD.as_return_value()),
+        "sys_wait must fail without children.",
+    );
+    assert_eq!(
+        frame_stats(),
+        before,
+        "The frames of the parent and the child must be given back.",
+    );
+}
+
+/// Verify that the children of a parent exiting without waiting for them are
+/// removed from the process table, whether they exit before or after it.
+#[test_case]
+fn test_children_of_an_exited_parent_are_removed() {
+    // mov eax, SYS_FORK; syscall; test rax, rax; jnz parent;
+    // mov ecx, 0x20000000; spin: dec ecx; jnz spin; xor edi, edi; xor eax, eax;
+    // syscall; parent: xor edi, edi; xor eax, eax; syscall
+    let orphan_exits_later = user_program(&[
+        0xb8, 0x05, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x85, 0xc0, 0x75, 0x0f, 0xb9, 0x00, 0x00,
+        0x00, 0x20, 0xff, 0xc9, 0x75, 0xfc, 0x31, 0xff, 0x31, 0xc0, 0x0f, 0x05, 0x31, 0xff, 0x31,
+        0xc0, 0x0f, 0x05,
+    ]);
+    // mov eax, SYS_FORK; syscall; test rax, rax; jnz parent;
+    // xor edi, edi; xor eax, eax; syscall; parent: mov ecx, 0x20000000;
+    // spin: dec ecx; jnz spin; xor edi, edi; xor eax, eax; syscall
+    let child_exits_first = user_program(&[
+        0xb8, 0x05, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x85, 0xc0, 0x75, 0x06, 0x31, 0xff, 0x31,
+        0xc0, 0x0f, 0x05, 0xb9, 0x00, 0x00, 0x00, 0x20, 0xff, 0xc9, 0x75, 0xfc, 0x31, 0xff, 0x31,
+        0xc0, 0x0f, 0x05,
+    ]);
+
+    let before = frame_stats();
+    let processes = process::count();
+    let later_pid = process::spawn(&orphan_exits_later).expect("Spawn failed.");
+    let first_pid = process::spawn(&child_exits_first).expect("Spawn failed.");
+
+    process::run();
+
+    assert_eq!(
+        process::reap(later_pid),
+        Some(ExitStatus::Exited(0)),
+        "Parent exit status."
+    );
+    assert_eq!(
+        process::reap(first_pid),
+        Some(ExitStatus::Exited(0)),
+        "Parent exit status."
+    );
+    assert_eq!(
+        process::count(),
+        processes,
+        "No child must be left in the process table.",
+    );
+    assert_eq!(
+        frame_stats(),
+        before,
+        "The frames of the parents and the children must be given back.",
+    );
+}
+
+/// Verify that a duplicated address space shares the frames of the original
+/// one, and that a write to a shared page gives the writer a private copy.
+#[test_case]
+fn test_duplicate_shares_frames_until_they_are_written() {
+    let addr = VirtAddr::new(userspace::USER_MMAP_START);
+    let page = Page::containing_address(addr);
+    let before = frame_stats();
+
+    memory::with_frame_allocator(|frame_allocator| {
+        let mut parent = AddressSpace::new(frame_allocator).expect("Allocation failed.");
+        parent
+            .map_page(
+                page,
+                PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+                frame_allocator,
+            )
+            .expect("Mapping failed.");
+        parent.write(addr, b"parent").expect("Write failed.");
+
+        let used = frame_allocator.stats().used;
+        let mut child = parent.duplicate(frame_allocator).expect("Fork failed.");
+        let (frame, flags) = parent.translate(addr).expect("Parent page.");
+        assert_eq!(
+            child.translate(addr),
+            Some((frame, flags)),
+            "The child must map the frame of the parent with the same flags.",
+        );
+        assert!(
+            flags.contains(COPY_ON_WRITE) && !flags.contains(PageTableFlags::WRITABLE),
+            "Shared pages must be read-only and copy-on-write.",
+        );
+        assert_eq!(frame_allocator.reference_count(frame), 2, "Two owners.");
+        assert!(
+            frame_allocator.stats().used - used < 8,
+            "Only the page tables of the child are allocated.",
+        );
+
+        child
+            .handle_page_fault(addr.as_u64(), Access::Write, frame_allocator)
+            .expect("Copy-on-write failed.");
+        let (copy, copy_flags) = child.translate(addr).expect("Child page.");
+        assert_ne!(copy, frame, "The writer must get its own frame.");
+        assert!(
+            copy_flags.contains(PageTableFlags::WRITABLE) && !copy_flags.contains(COPY_ON_WRITE),
+            "The copy must be writable.",
+        );
+        assert_eq!(frame_allocator.reference_count(frame), 1, "One owner left.");
+
+        child.write(addr, b"child!").expect("Write failed.");
+        let mut bytes = [0; 6];
+        // SAFETY: The frame backs the page of the parent, which holds 6 bytes.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                memory::phys_to_virt(frame.start_address()).as_ptr::<u8>(),
+                bytes.as_mut_ptr(),
+                bytes.len(),
+            );
+        }
+        assert_eq!(&bytes, b"parent", "The parent keeps its own data.");
+
+        parent
+            .handle_page_fault(addr.as_u64(), Access::Write, frame_allocator)
+            .expect("Copy-on-write failed.");
+        let (kept, kept_flags) = parent.translate(addr).expect("Parent page.");
+        assert_eq!(kept, frame, "The last owner keeps the frame.");
+        assert!(
+            kept_flags.contains(PageTableFlags::WRITABLE),
+            "The last owner may write to the frame again.",
+        );
+
+        child.free(frame_allocator);
+        parent.free(frame_allocator);
+    });
+
+    assert_eq!(frame_stats(), before, "Every frame must be given back.");
+}
+
+/// Verify that `sys_exec` replaces the calling process with a registered
+/// program, and fails for unknown names.
+#[test_case]
+fn test_exec_runs_a_registered_program() {
+    // mov eax, SYS_EXEC; lea rdi, [rip + name]; mov esi, 5; syscall;
+    // mov rdi, rax; xor eax, eax; syscall; name: 5 bytes
+    let exec = |name: &[u8; 5]| {
+        let mut code = vec![
+            0xb8, 0x06, 0x00, 0x00, 0x00, 0x48, 0x8d, 0x3d, 0x0e, 0x00, 0x00, 0x00, 0xbe, 0x05,
+            0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0xc7, 0x31, 0xc0, 0x0f, 0x05,
+        ];
+        code.extend_from_slice(name);
+        user_program(&code)
+    };
+    programs::register("hello", HELLO_ELF);
+
+    let before = frame_stats();
+    let hello_pid = process::spawn(&exec(b"hello")).expect("Spawn failed.");
+    let unknown_pid = process::spawn(&exec(b"nope!")).expect("Spawn failed.");
+
+    process::run();
+
+    assert_eq!(
+        process::exit_code(hello_pid),
+        Some(0),
+        "The process must run the hello program and exit with its code.",
+    );
+    assert_eq!(
+        process::exit_code(unknown_pid),
+        Some(Errno::ENOENT.as_return_value()),
+        "sys_exec must fail for an unknown program.",
+    );
+    assert_eq!(
+        frame_stats(),
+        before,
+        "The image replaced by sys_exec must be given back.",
+    );
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     self_rust_os::test_panic_handler(info)
@@ -0,0 +1,88 @@
+//! Heap allocator of the user programs.
+//!
+//! The heap starts empty at the initial program break. When an allocation
+//! does not fit, the break is moved up with [`syscall::brk`] and the new
+//! memory is added to the free list of a [`linked_list_allocator`] heap.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, NonNull},
+};
+
+use linked_list_allocator::{Heap, LockedHeap};
+
+use crate::syscall;
+
+/// Minimum number of bytes the heap grows by (16 KiB).
+const HEAP_GROWTH: usize = 4096 * 4;
+
+/// The allocator used by `alloc` in every user program.
+#[global_allocator]
+static ALLOCATOR: BrkHeap = BrkHeap(LockedHeap::empty());
+
+/// A heap grown on demand by moving the program break.
+struct BrkHeap(LockedHeap);
+
+impl BrkHeap {
+    /// Moves the program break up to make room for an allocation of
+    /// `layout`.
+    ///
+    /// Returns `false` if the kernel refuses to move the break.
+    fn grow(heap: &mut Heap, layout: Layout) -> bool {
+        let Some(by) = layout
+            .size()
+            .checked_add(layout.align())
+            .map(|size| size.max(HEAP_GROWTH).next_multiple_of(4096))
+        else {
+            return false;
+        };
+
+        if heap.size() == 0 {
+            let Ok(bottom) = syscall::brk(0) else {
+                return false;
+            };
+            let Ok(top) = syscall::brk(bottom + by) else {
+                return false;
+            };
+            // SAFETY:
+            //
+            // The memory between the initial break and the new one is mapped,
+            // writable and used by nothing else.
+            unsafe { heap.init(bottom, top - bottom) };
+        } else {
+            let Ok(top) = syscall::brk(heap.top() + by) else {
+                return false;
+            };
+            // SAFETY:
+            //
+            // The heap ends at the previous break, and the memory up to the
+            // new break is mapped and unused.
+            unsafe { heap.extend(top - heap.top()) };
+        }
+        true
+    }
+}
+
+// SAFETY:
+//
+// Allocations are served by `Heap`, whose memory is never handed out twice
+// nor released to the kernel.
+unsafe impl GlobalAlloc for BrkHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(allocation) = heap.allocate_first_fit(layout) {
+            return allocation.as_ptr();
+        }
+        if !Self::grow(&mut heap, layout) {
+            return ptr::null_mut();
+        }
+        heap.allocate_first_fit(layout)
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(allocation) = NonNull::new(ptr) {
+            self.0.lock().deallocate(allocation, layout);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Text output through `sys_write`.
+
+use core::fmt::{self, Write};
+
+use crate::syscall;
+
+/// Size of the buffer collecting the output of a [`print!`](crate::print)
+/// call before it is written.
+const BUFFER_SIZE: usize = 128;
+
+/// Writer collecting text in a buffer and writing it with `sys_write` when the
+/// buffer is full or dropped.
+struct Stdout {
+    buffer: [u8; BUFFER_SIZE],
+    len: usize,
+}
+
+impl Stdout {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    fn flush(&mut self) -> fmt::Result {
+        let pending = &self.buffer[..self.len];
+        self.len = 0;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        syscall::write(pending).map(|_| ()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len == BUFFER_SIZE {
+                self.flush()?;
+            }
+            self.buffer[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the formatted arguments, ignoring errors.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let mut stdout = Stdout::new();
+    let _ = stdout.write_fmt(args).and_then(|()| stdout.flush());
+}
+
+/// Prints to the VGA text display through `sys_write`.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::io::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the VGA text display through `sys_write`, with a newline.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
@@ -0,0 +1,55 @@
+//! Runtime shared by the user programs of `self_rust_os`.
+//!
+//! A user program depends on this `no_std` crate instead of talking to the
+//! kernel by hand. It provides:
+//!
+//! - The `_start` entry point, which sets up the stack and calls the function
+//!   declared with [`entry!`], then exits with the code it returns.
+//! - Typed syscall wrappers in [`syscall`], returning a [`Result`] decoded from
+//!   the kernel error codes.
+//! - The [`print!`] and [`println!`] macros, writing to the VGA text display.
+//! - A global allocator for the `alloc` crate, backed by a heap grown with
+//!   `sys_brk`.
+//! - A panic handler that reports the panic message and location before
+//!   exiting with [`EXIT_CODE_PANIC`].
+//!
+//! The syscall numbers and error codes come from the [`abi`] crate, which the
+//! kernel uses as well.
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! libuser::entry!(main);
+//!
+//! fn main() {
+//!     libuser::println!("Hello from user space!");
+//! }
+//! ```
+
+#![no_std]
+#![feature(naked_functions)]
+
+mod heap;
+pub mod io;
+mod rt;
+pub mod syscall;
+
+pub use abi::Errno;
+pub use rt::{Termination, EXIT_CODE_PANIC};
+
+/// Declares the main function of a user program.
+///
+/// The function takes no argument and returns a [`Termination`] value, like
+/// `()`, an exit code (`u64`) or a `Result<(), Errno>`, which becomes the exit
+/// code of the process.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[doc(hidden)]
+        #[export_name = "__libuser_main"]
+        fn __libuser_main() -> u64 {
+            $crate::Termination::exit_code($main())
+        }
+    };
+}
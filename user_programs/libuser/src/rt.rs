@@ -0,0 +1,90 @@
+//! Program startup and termination.
+
+use core::{arch::naked_asm, panic::PanicInfo};
+
+use abi::Errno;
+
+use crate::{println, syscall};
+
+/// Exit code of a process that panicked.
+pub const EXIT_CODE_PANIC: u64 = 101;
+
+/// Values that a main function can return, converted to an exit code.
+pub trait Termination {
+    /// Returns the exit code reporting this value.
+    fn exit_code(self) -> u64;
+}
+
+impl Termination for () {
+    fn exit_code(self) -> u64 {
+        0
+    }
+}
+
+impl Termination for u64 {
+    fn exit_code(self) -> u64 {
+        self
+    }
+}
+
+impl<T: Termination> Termination for Result<T, Errno> {
+    /// Returns the exit code of the `Ok` value, or the error number.
+    fn exit_code(self) -> u64 {
+        match self {
+            Ok(value) => value.exit_code(),
+            Err(errno) => u64::from(errno.number()),
+        }
+    }
+}
+
+/// Entry point of the program, at the start of the `.text` section.
+///
+/// The kernel enters it in Ring 3 with `rsp` at the top of the user stack.
+/// The frame pointer is cleared to terminate stack walks and the stack is
+/// aligned as required by the System V ABI before calling [`start`].
+#[naked]
+#[no_mangle]
+#[link_section = ".text.start"]
+unsafe extern "C" fn _start() -> ! {
+    // SAFETY:
+    //
+    // `start` never returns, so the stack set up here is never unwound.
+    unsafe {
+        naked_asm!(
+            "xor ebp, ebp",
+            "and rsp, -16",
+            "call {start}",
+            "ud2",
+            start = sym start,
+        );
+    }
+}
+
+/// Runs the main function declared with [`entry!`](crate::entry) and exits
+/// with its exit code.
+extern "C" fn start() -> ! {
+    extern "Rust" {
+        fn __libuser_main() -> u64;
+    }
+
+    // SAFETY:
+    //
+    // `__libuser_main` is defined by the `entry!` macro with this signature.
+    let code = unsafe { __libuser_main() };
+    syscall::exit(code)
+}
+
+/// Reports the panic and terminates the process with [`EXIT_CODE_PANIC`].
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    match info.location() {
+        Some(location) => println!(
+            "PANIC in user program at {}:{}: {}",
+            location.file(),
+            location.line(),
+            info.message()
+        ),
+        None => println!("PANIC in user program: {}", info.message()),
+    }
+    syscall::exit(EXIT_CODE_PANIC)
+}
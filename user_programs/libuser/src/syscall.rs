@@ -0,0 +1,220 @@
+//! Typed syscall wrappers.
+//!
+//! Syscalls are invoked with the `syscall` instruction. The value returned in
+//! `rax` is decoded into a [`Result`]: a negated error number becomes an
+//! [`Errno`].
+
+use core::{arch::asm, hint, ptr};
+
+use abi::{
+    errno::decode_result, Errno, Timespec, SYS_BRK, SYS_CLOCK_GETTIME, SYS_EXEC, SYS_EXIT,
+    SYS_FORK, SYS_MMAP, SYS_MUNMAP, SYS_SLEEP, SYS_TIME, SYS_WAIT, SYS_WAITPID, SYS_WRITE,
+    SYS_YIELD,
+};
+
+/// Invokes the syscall `num` with three arguments.
+///
+/// # Safety
+///
+/// The arguments must form a valid request for the syscall `num`; in
+/// particular, pointer arguments must point to memory the kernel may read or
+/// write.
+///
+/// # Errors
+///
+/// Returns the [`Errno`] reported by the kernel.
+#[inline(always)]
+pub unsafe fn syscall3(num: u64, arg1: u64, arg2: u64, arg3: u64) -> Result<u64, Errno> {
+    let result: u64;
+    asm!(
+        "syscall",
+        inlateout("rax") num => result,
+        in("rdi") arg1,
+        in("rsi") arg2,
+        in("rdx") arg3,
+        // The return address and RFLAGS are held in rcx and r11.
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+    decode_result(result)
+}
+
+/// Writes `buf` to the VGA text display.
+///
+/// Returns the number of bytes written.
+///
+/// # Errors
+///
+/// Returns [`Errno::EFAULT`] if the kernel cannot read the buffer.
+pub fn write(buf: &[u8]) -> Result<usize, Errno> {
+    // SAFETY:
+    //
+    // The buffer is borrowed for the whole call and readable by the process.
+    let written = unsafe { syscall3(SYS_WRITE, buf.as_ptr() as u64, buf.len() as u64, 0)? };
+    Ok(usize::try_from(written).unwrap_or(buf.len()))
+}
+
+/// Moves the program break, the end of the heap, to `addr`.
+///
+/// Passing `0` leaves the break unchanged. Returns the new break.
+///
+/// # Errors
+///
+/// Returns [`Errno::ENOMEM`] if the break cannot be moved to `addr`.
+pub fn brk(addr: usize) -> Result<usize, Errno> {
+    // SAFETY:
+    //
+    // The pages released by lowering the break are owned by the caller.
+    let brk = unsafe { syscall3(SYS_BRK, addr as u64, 0, 0)? };
+    Ok(brk as usize)
+}
+
+/// Maps `len` bytes of zeroed, writable memory.
+///
+/// `addr` is a page-aligned address hint, or `0` to let the kernel choose.
+/// Returns the address of the mapping.
+///
+/// # Errors
+///
+/// Returns [`Errno::EINVAL`] if `len` is zero or `addr` is not page-aligned,
+/// and [`Errno::ENOMEM`] if no room or memory is left.
+pub fn mmap(addr: usize, len: usize) -> Result<*mut u8, Errno> {
+    // SAFETY:
+    //
+    // Only free pages are mapped, so no existing memory is affected.
+    let mapping = unsafe { syscall3(SYS_MMAP, addr as u64, len as u64, 0)? };
+    Ok(mapping as *mut u8)
+}
+
+/// Unmaps the `len` bytes of anonymous memory starting at `addr`.
+///
+/// # Safety
+///
+/// No reference to the unmapped memory may be used afterwards.
+///
+/// # Errors
+///
+/// Returns [`Errno::EINVAL`] if `len` is zero, `addr` is not page-aligned or
+/// the range is not in the anonymous mapping region.
+pub unsafe fn munmap(addr: *mut u8, len: usize) -> Result<(), Errno> {
+    syscall3(SYS_MUNMAP, addr as u64, len as u64, 0)?;
+    Ok(())
+}
+
+/// Creates a child process running a copy of the calling one.
+///
+/// Returns the PID of the child in the parent, and `0` in the child.
+///
+/// # Errors
+///
+/// Returns [`Errno::ENOMEM`] if the memory of the process cannot be copied.
+pub fn fork() -> Result<u64, Errno> {
+    // SAFETY:
+    //
+    // `sys_fork` takes no pointer, and the child resumes with the same memory.
+    unsafe { syscall3(SYS_FORK, 0, 0, 0) }
+}
+
+/// Replaces the calling process with the program registered as `name`.
+///
+/// Only returns if the program cannot be started, with the reason.
+pub fn exec(name: &str) -> Errno {
+    // SAFETY:
+    //
+    // The name is borrowed for the whole call and readable by the process.
+    let result = unsafe { syscall3(SYS_EXEC, name.as_ptr() as u64, name.len() as u64, 0) };
+    result.err().unwrap_or(Errno::ENOSYS)
+}
+
+/// Waits for any child to exit.
+///
+/// Returns the PID and the exit code of the child.
+///
+/// # Errors
+///
+/// Returns [`Errno::ECHILD`] if the process has no child.
+pub fn wait() -> Result<(u64, u64), Errno> {
+    let mut code = 0_u64;
+    // SAFETY:
+    //
+    // The exit code is written to a local variable borrowed for the call.
+    let pid = unsafe { syscall3(SYS_WAIT, ptr::from_mut(&mut code) as u64, 0, 0)? };
+    Ok((pid, code))
+}
+
+/// Waits for the child `pid` to exit.
+///
+/// Returns the exit code of the child.
+///
+/// # Errors
+///
+/// Returns [`Errno::ECHILD`] if `pid` is not a child of the process.
+pub fn waitpid(pid: u64) -> Result<u64, Errno> {
+    let mut code = 0_u64;
+    // SAFETY:
+    //
+    // The exit code is written to a local variable borrowed for the call.
+    unsafe { syscall3(SYS_WAITPID, pid, ptr::from_mut(&mut code) as u64, 0)? };
+    Ok(code)
+}
+
+/// Gives the CPU up to another ready process, if any.
+pub fn yield_now() {
+    // SAFETY:
+    //
+    // `sys_yield` takes no argument and cannot fail.
+    let _ = unsafe { syscall3(SYS_YIELD, 0, 0, 0) };
+}
+
+/// Blocks the process for at least `ms` milliseconds.
+pub fn sleep(ms: u64) {
+    // SAFETY:
+    //
+    // `sys_sleep` takes no pointer and cannot fail.
+    let _ = unsafe { syscall3(SYS_SLEEP, ms, 0, 0) };
+}
+
+/// Returns the current time of the clock `clock_id`, such as
+/// [`abi::CLOCK_MONOTONIC`].
+///
+/// # Errors
+///
+/// Returns [`Errno::EINVAL`] if the kernel does not support the clock.
+pub fn clock_gettime(clock_id: u64) -> Result<Timespec, Errno> {
+    let mut timespec = Timespec::default();
+    // SAFETY:
+    //
+    // The time is written to a local variable borrowed for the call.
+    unsafe {
+        syscall3(
+            SYS_CLOCK_GETTIME,
+            clock_id,
+            ptr::from_mut(&mut timespec) as u64,
+            0,
+        )?;
+    }
+    Ok(timespec)
+}
+
+/// Returns the number of seconds since the Unix epoch (1970-01-01 00:00:00
+/// UTC).
+pub fn time() -> u64 {
+    // SAFETY:
+    //
+    // No pointer is passed, so `sys_time` cannot fail.
+    unsafe { syscall3(SYS_TIME, 0, 0, 0) }.unwrap_or(0)
+}
+
+/// Terminates the process with the exit code `code`.
+pub fn exit(code: u64) -> ! {
+    // SAFETY:
+    //
+    // `sys_exit` takes no pointer and does not return.
+    let _ = unsafe { syscall3(SYS_EXIT, code, 0, 0) };
+
+    // The kernel never returns from sys_exit.
+    loop {
+        hint::spin_loop();
+    }
+}